@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use serde_json::json;
+use tracing::{info, info_span};
+
+use crate::matcher::{MatchRule, Matcher};
+use crate::obfuscate::{obfuscate, ObfuscateOptions};
+use crate::prototype::ProtoDatabase;
+
+/// Names resolved by one rule and how many of them agree with the truth
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RuleScore {
+    pub resolved: usize,
+    pub correct: usize,
+}
+
+impl RuleScore {
+    pub fn precision(&self) -> f64 {
+        ratio(self.correct, self.resolved)
+    }
+}
+
+/// The matcher's results on obfuscated copies of a schema, where the right answer is known
+#[derive(Debug, Default)]
+pub struct Benchmark {
+    pub runs: usize,
+    /// Obfuscated names that have a clean name, summed over the runs
+    pub names: usize,
+    /// By the rule that resolved the name, None for names resolved without a recorded decision
+    pub rules: BTreeMap<Option<MatchRule>, RuleScore>,
+}
+
+impl Benchmark {
+    /// Obfuscates the schema once per run, with consecutive seeds from `options.seed`, and matches it back
+    pub fn run(clean: &ProtoDatabase, options: &ObfuscateOptions, runs: usize) -> Self {
+        let mut benchmark = Self::default();
+        for seed in (options.seed..).take(runs) {
+            let _span = info_span!("run", seed).entered();
+
+            let obfuscated = obfuscate(clean, &ObfuscateOptions { seed, ..options.clone() });
+            let mut matcher = Matcher::new(clean.clone(), obfuscated.proto_db);
+            matcher.run();
+
+            benchmark.score(&matcher, &obfuscated.truth);
+            info!("{} of {} names resolved correctly so far", benchmark.total().correct, benchmark.names);
+        }
+        benchmark
+    }
+
+    /// Adds one run, every name the matcher resolved in b is checked against `truth` (obfuscated name to clean name)
+    pub fn score(&mut self, matcher: &Matcher, truth: &BTreeMap<String, String>) {
+        self.runs += 1;
+        self.names += truth.len();
+
+        for (original, current) in matcher.db_b().generate_nametranslation() {
+            // Unresolved, or readable in the first place
+            if original == current {
+                continue;
+            }
+
+            let rule = matcher.events().iter().find(|event| event.name_b == original).map(|event| event.rule);
+            let score = self.rules.entry(rule).or_default();
+            score.resolved += 1;
+            if truth.get(&original) == Some(&current) {
+                score.correct += 1;
+            }
+        }
+    }
+
+    pub fn total(&self) -> RuleScore {
+        self.rules.values().fold(RuleScore::default(), |total, score| RuleScore {
+            resolved: total.resolved + score.resolved,
+            correct: total.correct + score.correct,
+        })
+    }
+
+    /// Share of all obfuscated names that were resolved correctly
+    pub fn recall(&self) -> f64 {
+        ratio(self.total().correct, self.names)
+    }
+
+    pub fn to_text(&self) -> String {
+        let total = self.total();
+        let mut output = String::new();
+        writeln!(output, "{} run(s), {} obfuscated names, {} resolved, {} correct", self.runs, self.names, total.resolved, total.correct).unwrap();
+        writeln!(output, "precision {:.1}%, recall {:.1}%\n", total.precision() * 100.0, self.recall() * 100.0).unwrap();
+
+        writeln!(output, "{:<20} {:>9} {:>9} {:>10} {:>7}", "rule", "resolved", "correct", "precision", "recall").unwrap();
+        for (rule, score) in &self.rules {
+            writeln!(output, "{:<20} {:>9} {:>9} {:>9.1}% {:>6.1}%", rule_key(rule), score.resolved, score.correct, score.precision() * 100.0, ratio(score.correct, self.names) * 100.0).unwrap();
+        }
+        output
+    }
+
+    pub fn to_json(&self) -> String {
+        let total = self.total();
+        let rules = self.rules.iter()
+            .map(|(rule, score)| (rule_key(rule).to_string(), json!({
+                "resolved": score.resolved,
+                "correct": score.correct,
+                "precision": score.precision(),
+                "recall": ratio(score.correct, self.names),
+            })))
+            .collect::<serde_json::Map<_, _>>();
+
+        serde_json::to_string_pretty(&json!({
+            "runs": self.runs,
+            "names": self.names,
+            "resolved": total.resolved,
+            "correct": total.correct,
+            "precision": total.precision(),
+            "recall": self.recall(),
+            "rules": rules,
+        })).unwrap()
+    }
+}
+
+fn rule_key(rule: &Option<MatchRule>) -> &'static str {
+    rule.map_or("other", |rule| rule.key())
+}
+
+fn ratio(part: usize, whole: usize) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::parse_test_proto;
+
+    #[test]
+    fn test_benchmark() {
+        let proto_db = parse_test_proto("
+            message Avatar {
+                uint32 level = 1;
+                string name = 2;
+                int64 exp = 3;
+                int64 rank = 4;
+            }
+        ");
+
+        // Avatar stays readable, its two int64 fields can't be told apart
+        let options = ObfuscateOptions { keep_messages: 1.0, remove_rate: 0.0, add_rate: 0.0, type_change_rate: 0.0, ..Default::default() };
+        let benchmark = Benchmark::run(&proto_db, &options, 2);
+
+        assert_eq!(benchmark.names, 8);
+        assert_eq!(benchmark.rules[&Some(MatchRule::UniqueWeakType)], RuleScore { resolved: 4, correct: 4 });
+        assert_eq!(benchmark.recall(), 0.5);
+        assert!(benchmark.to_text().starts_with("2 run(s), 8 obfuscated names, 4 resolved, 4 correct\nprecision 100.0%, recall 50.0%\n"));
+    }
+}
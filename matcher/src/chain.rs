@@ -0,0 +1,145 @@
+use std::collections::{BTreeMap, HashMap};
+
+use itertools::Itertools;
+use serde_json::json;
+use tracing::{info, info_span};
+
+use crate::matcher::Matcher;
+use crate::prototype::ProtoDatabase;
+
+/// One schema of the chain, with every name that could be resolved from the versions before it
+pub struct ChainVersion {
+    pub label: String,
+    pub proto_db: ProtoDatabase,
+    /// Index of the version each resolved name (by its original text) was matched against
+    matched_via: HashMap<String, usize>,
+}
+
+/// The name an identifier had in one version
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionName {
+    pub original: String,
+    /// Version it was matched against, None when the name was readable already
+    pub via: Option<usize>,
+}
+
+/// Successive versions of a schema, oldest first, the first one with known names
+pub struct Chain {
+    pub versions: Vec<ChainVersion>,
+}
+
+impl Chain {
+    /// Matches every version against all earlier ones, nearest first, until none of them resolves anything more
+    pub fn new(schemas: Vec<(String, ProtoDatabase)>) -> Self {
+        let mut versions: Vec<ChainVersion> = Vec::new();
+
+        for (label, mut proto_db) in schemas {
+            let _span = info_span!("version", label = label.as_str()).entered();
+            let mut matched_via = HashMap::new();
+
+            // A name the previous version never had (or left unresolved) can still be found in an older one,
+            // and every name found there is new evidence for the nearer versions
+            loop {
+                let before = resolved_count(&proto_db);
+                for (index, version) in versions.iter().enumerate().rev() {
+                    let mut matcher = Matcher::new(version.proto_db.clone(), proto_db);
+                    matcher.run();
+
+                    for event in matcher.events() {
+                        matched_via.entry(event.name_b.clone()).or_insert(index);
+                    }
+                    proto_db = matcher.into_db_b();
+                }
+
+                if versions.is_empty() || resolved_count(&proto_db) == before {
+                    break;
+                }
+            }
+
+            if !versions.is_empty() {
+                info!("Resolved {} of {} identifiers", resolved_count(&proto_db), proto_db.identifier_db.len());
+            }
+            versions.push(ChainVersion { label, proto_db, matched_via });
+        }
+
+        Self { versions }
+    }
+
+    /// Every name that was obfuscated in some version, with its name in each version (None where it is absent or unresolved)
+    pub fn history(&self) -> BTreeMap<String, Vec<Option<VersionName>>> {
+        let mut history: BTreeMap<String, Vec<Option<VersionName>>> = BTreeMap::new();
+
+        for (index, version) in self.versions.iter().enumerate() {
+            // Fields of different messages can share a name, the first one registered stands for it
+            for (proto_name, name) in version.proto_db.identifier_db.iter().sorted_by_key(|(proto_name, _)| *proto_name) {
+                if !version.proto_db.is_resolved(&proto_name) {
+                    continue;
+                }
+
+                let original = version.proto_db.original_name(&proto_name);
+                let via = version.matched_via.get(&original).copied();
+                history.entry(name.clone()).or_insert_with(|| vec![None; self.versions.len()])[index].get_or_insert(VersionName { original, via });
+            }
+        }
+
+        history.retain(|name, names| names.iter().flatten().any(|version_name| &version_name.original != name));
+        history
+    }
+
+    pub fn history_json(&self) -> String {
+        let names = self.history().into_iter()
+            .map(|(name, names)| {
+                let names = names.into_iter()
+                    .map(|version_name| version_name.map(|version_name| json!({
+                        "name": version_name.original,
+                        "via": version_name.via.map(|via| &self.versions[via].label),
+                    })))
+                    .collect::<Vec<_>>();
+                (name, json!(names))
+            })
+            .collect::<serde_json::Map<_, _>>();
+
+        let labels = self.versions.iter().map(|version| &version.label).collect::<Vec<_>>();
+        serde_json::to_string_pretty(&json!({ "versions": labels, "names": names })).unwrap()
+    }
+}
+
+fn resolved_count(proto_db: &ProtoDatabase) -> usize {
+    proto_db.identifier_resolutions.values().filter(|resolved| **resolved).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::parse_test_proto;
+
+    #[test]
+    fn test_chain_history() {
+        // 1.1 dropped the name field, 1.2 brought it back, only 1.0 can tell what it is
+        let proto_db_0 = parse_test_proto("
+            message Avatar {
+                uint32 level = 1;
+                string name = 2;
+            }
+        ");
+        let proto_db_1 = parse_test_proto("
+            message Avatar {
+                uint32 ABCDEFGHIJK = 3;
+            }
+        ");
+        let proto_db_2 = parse_test_proto("
+            message Avatar {
+                uint32 BCDEFGHIJKL = 4;
+                string CDEFGHIJKLM = 5;
+            }
+        ");
+
+        let chain = Chain::new(vec![("1.0".to_string(), proto_db_0), ("1.1".to_string(), proto_db_1), ("1.2".to_string(), proto_db_2)]);
+        let history = chain.history();
+        assert_eq!(history.keys().collect::<Vec<_>>(), ["level", "name"]);
+
+        let version_name = |original: &str, via| Some(VersionName { original: original.to_string(), via });
+        assert_eq!(history["level"], [version_name("level", None), version_name("ABCDEFGHIJK", Some(0)), version_name("BCDEFGHIJKL", Some(1))]);
+        assert_eq!(history["name"], [version_name("name", None), None, version_name("CDEFGHIJKLM", Some(0))]);
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use itertools::Itertools;
+use regex::Regex;
+use tracing::{info, warn};
+
+use crate::prototype::{ProtoDatabase, ProtoName};
+use crate::util::TrimIndent;
+
+/// `Class => cmdid` pairs, as printed by derive_csreq_ids.py
+pub struct CmdIdTable {
+    pub entries: BTreeMap<String, u32>,
+}
+
+impl CmdIdTable {
+    pub fn parse(source: &str) -> Self {
+        let re = Regex::new(r"^\s*([\w.]+)\s*=>\s*(\d+)\s*$").unwrap();
+
+        // Anything else in the log (warnings, recursion traces) is skipped
+        let entries = source.lines()
+            .filter_map(|line| re.captures(line))
+            .filter_map(|cap| {
+                let Ok(cmd_id) = cap[2].parse() else {
+                    warn!("Cmd id {} of {} is out of range, skipping it", &cap[2], &cap[1]);
+                    return None;
+                };
+
+                // Classes may be namespace qualified, but messages are not
+                let class_name = cap[1].rsplit('.').next().unwrap().to_string();
+                Some((class_name, cmd_id))
+            })
+            .collect();
+
+        Self { entries }
+    }
+}
+
+pub struct CmdIdEntry {
+    pub name: String,
+    pub original_name: String,
+    pub cmd_id: u32,
+    pub previous_cmd_id: Option<u32>,
+    pub is_resolved: bool,
+    pub in_schema: bool,
+}
+
+impl CmdIdEntry {
+    pub fn is_changed(&self) -> bool {
+        self.previous_cmd_id.is_some_and(|previous| previous != self.cmd_id)
+    }
+}
+
+/// A cmd id table translated to the resolved names of a `ProtoDatabase`
+pub struct CmdIdExport {
+    pub entries: Vec<CmdIdEntry>,
+}
+
+impl CmdIdExport {
+    pub fn new(table: &CmdIdTable, proto_db: &ProtoDatabase, previous: Option<&CmdIdTable>) -> Self {
+        let mut entries = table.entries.iter()
+            .map(|(original_name, &cmd_id)| {
+                let name = proto_db.translate_name(original_name).unwrap_or_else(|| original_name.clone());
+                let in_schema = proto_db.get_message(&name).is_some();
+                let is_resolved = in_schema && proto_db.is_resolved(&ProtoName::lookup(proto_db, &name));
+                let previous_cmd_id = previous.and_then(|previous| previous.entries.get(&name).copied());
+
+                CmdIdEntry {
+                    name,
+                    original_name: original_name.clone(),
+                    cmd_id,
+                    previous_cmd_id,
+                    is_resolved,
+                    in_schema,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        entries.sort_by(|a, b| a.cmd_id.cmp(&b.cmd_id).then_with(|| a.name.cmp(&b.name)));
+
+        Self { entries }
+    }
+
+    pub fn log_flags(&self) {
+        for (cmd_id, entries) in &self.entries.iter().chunk_by(|entry| entry.cmd_id) {
+            let names = entries.map(|entry| entry.name.as_str()).collect::<Vec<_>>();
+            if names.len() > 1 {
+                warn!("Cmd id {} is shared by {}", cmd_id, names.join(", "));
+            }
+        }
+
+        for entry in &self.entries {
+            if !entry.in_schema {
+                warn!("{} ({}) is not a message in the schema", entry.original_name, entry.cmd_id);
+            } else if !entry.is_resolved {
+                warn!("{} ({}) is still unresolved", entry.name, entry.cmd_id);
+            }
+
+            if let Some(previous) = entry.previous_cmd_id.filter(|_| entry.is_changed()) {
+                info!("Cmd id changed: {} {} -> {}", entry.name, previous, entry.cmd_id);
+            }
+        }
+    }
+
+    fn flag_comment(entry: &CmdIdEntry) -> String {
+        let mut flags = Vec::new();
+        if entry.name != entry.original_name {
+            flags.push(format!("was {}", entry.original_name));
+        }
+        if !entry.is_resolved {
+            flags.push("unresolved".to_string());
+        }
+        if let Some(previous) = entry.previous_cmd_id.filter(|_| entry.is_changed()) {
+            flags.push(format!("changed from {}", previous));
+        }
+
+        if flags.is_empty() {
+            String::new()
+        } else {
+            format!(" // {}", flags.join(", "))
+        }
+    }
+
+    pub fn to_proto(&self) -> String {
+        let mut output = "
+            syntax = \"proto3\";
+
+            enum CmdId {
+        ".trim_indent();
+        output.push('\n');
+
+        // Two classes sharing an id would otherwise make the enum invalid
+        if self.entries.iter().map(|entry| entry.cmd_id).duplicates().next().is_some() {
+            output.push_str("    option allow_alias = true;\n");
+        }
+
+        // proto3 enums must start at zero
+        if self.entries.first().is_none_or(|entry| entry.cmd_id != 0) {
+            output.push_str("    CmdNone = 0;\n");
+        }
+
+        for entry in &self.entries {
+            writeln!(output, "    Cmd{} = {};{}", entry.name, entry.cmd_id, Self::flag_comment(entry)).unwrap();
+        }
+
+        output.push_str("}\n");
+        output
+    }
+
+    pub fn to_json(&self) -> String {
+        let map = self.entries.iter()
+            .map(|entry| (entry.name.as_str(), entry.cmd_id))
+            .collect::<BTreeMap<_, _>>();
+
+        serde_json::to_string_pretty(&map).unwrap()
+    }
+
+    pub fn to_rust(&self) -> String {
+        let mut output = "
+            // Generated by `matcher cmd-id`, do not edit
+            pub mod cmd_id {
+        ".trim_indent();
+        output.push('\n');
+
+        for entry in &self.entries {
+            writeln!(output, "    pub const {}: u32 = {};{}", screaming_snake_case(&entry.name), entry.cmd_id, Self::flag_comment(entry)).unwrap();
+        }
+
+        output.push_str("}\n");
+        output
+    }
+}
+
+/// `GetAvatarDataCsReq` -> `GET_AVATAR_DATA_CS_REQ`, obfuscated all-caps names are kept as is
+fn screaming_snake_case(name: &str) -> String {
+    let chars = name.chars().collect::<Vec<_>>();
+    let mut result = String::new();
+
+    for (i, &c) in chars.iter().enumerate() {
+        if i > 0 && c.is_ascii_uppercase() {
+            let prev = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).is_some_and(|next| next.is_ascii_lowercase());
+
+            if prev.is_ascii_lowercase() || prev.is_ascii_digit() || (prev.is_ascii_uppercase() && next_is_lower) {
+                result.push('_');
+            }
+        }
+
+        result.push(c.to_ascii_uppercase());
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_table() {
+        let table = CmdIdTable::parse("
+            Warning: cmdid is not a constant, cannot extract from ref:  <ssa> 0x1234
+            GetAvatarDataCsReq => 301
+            RPG.Network.Proto.GetAvatarDataScRsp => 302
+            PlayerLoginCsReq => 99999999999
+        ");
+
+        assert_eq!(table.entries.len(), 2);
+        assert_eq!(table.entries["GetAvatarDataCsReq"], 301);
+        assert_eq!(table.entries["GetAvatarDataScRsp"], 302);
+    }
+
+    #[test]
+    fn test_to_proto_aliases() {
+        let table = CmdIdTable::parse("
+            GetAvatarDataCsReq => 301
+            GetAvatarDataScRsp => 302
+            LegacyGetAvatarDataCsReq => 301
+        ");
+        let proto_db = crate::util::parse_test_proto("
+            message GetAvatarDataCsReq {}
+            message GetAvatarDataScRsp {}
+        ");
+
+        let proto = CmdIdExport::new(&table, &proto_db, None).to_proto();
+        assert!(proto.contains("option allow_alias = true;"));
+        assert!(proto.contains("CmdGetAvatarDataCsReq = 301;"));
+        assert!(proto.contains("CmdLegacyGetAvatarDataCsReq = 301;"));
+
+        let parsed = crate::parser::parse_proto_into(&mut ProtoDatabase::new(), &proto, "cmd_id.proto").unwrap();
+        assert!(parsed.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_screaming_snake_case() {
+        assert_eq!(screaming_snake_case("GetAvatarDataCsReq"), "GET_AVATAR_DATA_CS_REQ");
+        assert_eq!(screaming_snake_case("JNLOABDHEIH"), "JNLOABDHEIH");
+        assert_eq!(screaming_snake_case("PVEBattleResultCsReq"), "PVE_BATTLE_RESULT_CS_REQ");
+        assert_eq!(screaming_snake_case("Gacha10Info"), "GACHA10_INFO");
+    }
+}
@@ -0,0 +1,450 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use iced_x86::{Decoder, DecoderOptions, Instruction, InstructionInfoFactory, Mnemonic, OpAccess, OpKind, Register};
+use serde_json::Value;
+
+/// `SendPacket` as script.json names it
+pub const SEND_PACKET: &str = "RPG.Client.NetworkManager$$SendPacket";
+
+/// How far data passed in as a parameter is followed through callers
+const MAX_CALLER_DEPTH: usize = 4;
+
+/// Registers a call may overwrite (Windows x64 calling convention)
+const VOLATILE_REGISTERS: [Register; 7] = [Register::RAX, Register::RCX, Register::RDX, Register::R8, Register::R9, Register::R10, Register::R11];
+
+/// Registers of the first four integer arguments, in order
+const PARAMETER_REGISTERS: [Register; 4] = [Register::RCX, Register::RDX, Register::R8, Register::R9];
+
+#[derive(Debug)]
+pub enum ScanError {
+    NotPe,
+    /// Only x86-64 images can be disassembled
+    UnsupportedMachine(u16),
+    Script(serde_json::Error),
+    MissingMethod(String),
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScanError::NotPe => write!(f, "not a PE image"),
+            ScanError::UnsupportedMachine(machine) => write!(f, "unsupported machine type {:#x}, only x86-64 is supported", machine),
+            ScanError::Script(e) => write!(f, "invalid script.json: {}", e),
+            ScanError::MissingMethod(name) => write!(f, "{} is not in script.json", name),
+        }
+    }
+}
+
+/// Why a call site was skipped, warnings and errors are the categories derive_csreq_ids.py counts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    CmdIdNotConstant,
+    /// The message argument is a constant or read from memory
+    DataNotVariable,
+    /// The message argument is never set before the call
+    DataUndefined,
+    /// The message argument is a parameter, but no caller passes an allocated message
+    NoCallSitesMatched,
+    /// The message argument isn't the result of a call
+    NotAllocated,
+    /// The allocation's class argument isn't loaded from a metadata slot
+    ClassNotConstant,
+    /// The slot at this address isn't a `_TypeInfo` in script.json
+    UnknownClass(u64),
+}
+
+impl Problem {
+    pub fn is_error(&self) -> bool {
+        !matches!(self, Problem::CmdIdNotConstant | Problem::DataNotVariable | Problem::DataUndefined)
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::CmdIdNotConstant => write!(f, "cmdid is not a constant"),
+            Problem::DataNotVariable => write!(f, "data is not a variable"),
+            Problem::DataUndefined => write!(f, "data is not defined"),
+            Problem::NoCallSitesMatched => write!(f, "data is a parameter and no call sites matched"),
+            Problem::NotAllocated => write!(f, "data is not the result of a call"),
+            Problem::ClassNotConstant => write!(f, "allocated class is not a constant"),
+            Problem::UnknownClass(address) => write!(f, "allocated class at {:#x} is not a TypeInfo", address),
+        }
+    }
+}
+
+/// The sections of a PE image, to read code by RVA
+pub struct PeImage {
+    data: Vec<u8>,
+    /// Virtual address, virtual size, file offset and file size of each section
+    sections: Vec<(u64, u64, usize, usize)>,
+}
+
+impl PeImage {
+    pub fn parse(data: Vec<u8>) -> Result<Self, ScanError> {
+        let u16_at = |offset: usize| data.get(offset..offset + 2).map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()));
+        let u32_at = |offset: usize| data.get(offset..offset + 4).map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()));
+
+        if data.get(0..2) != Some(b"MZ") {
+            return Err(ScanError::NotPe);
+        }
+
+        let pe = u32_at(0x3c).ok_or(ScanError::NotPe)? as usize;
+        if data.get(pe..pe + 4) != Some(b"PE\0\0") {
+            return Err(ScanError::NotPe);
+        }
+
+        let machine = u16_at(pe + 4).ok_or(ScanError::NotPe)?;
+        if machine != 0x8664 {
+            return Err(ScanError::UnsupportedMachine(machine));
+        }
+
+        let section_count = u16_at(pe + 6).ok_or(ScanError::NotPe)? as usize;
+        let section_table = pe + 24 + u16_at(pe + 20).ok_or(ScanError::NotPe)? as usize;
+
+        let mut sections = Vec::new();
+        for section in (0..section_count).map(|index| section_table + index * 40) {
+            let field = |offset: usize| u32_at(section + offset).ok_or(ScanError::NotPe);
+            sections.push((field(12)? as u64, field(8)? as u64, field(20)? as usize, field(16)? as usize));
+        }
+
+        Ok(Self { data, sections })
+    }
+
+    /// Bytes from `start` up to `end`, cut at the end of the section
+    fn bytes(&self, start: u64, end: u64) -> Option<&[u8]> {
+        let &(address, virtual_size, offset, size) = self.sections.iter()
+            .find(|&&(address, virtual_size, _, _)| (address..address + virtual_size).contains(&start))?;
+
+        let from = offset + (start - address) as usize;
+        let to = (from + (end.min(address + virtual_size) - start) as usize).min(offset + size).min(self.data.len());
+        self.data.get(from..to)
+    }
+}
+
+/// Names Il2CppDumper's script.json gives to addresses
+pub struct ScriptMetadata {
+    /// Method start RVAs with their `Class$$Method` names, sorted by address
+    methods: Vec<(u64, String)>,
+    /// Metadata slots such as `Namespace.Class_TypeInfo` by RVA
+    metadata: HashMap<u64, String>,
+}
+
+impl ScriptMetadata {
+    pub fn parse(source: &str) -> Result<Self, ScanError> {
+        let script: Value = serde_json::from_str(source).map_err(ScanError::Script)?;
+        let entries = |key: &str| script[key].as_array().cloned().unwrap_or_default().into_iter()
+            .filter_map(|entry| Some((entry["Address"].as_u64()?, entry["Name"].as_str()?.to_string())));
+
+        let mut methods = entries("ScriptMethod").collect::<Vec<_>>();
+        methods.sort();
+        // Shared generic code has several names for one address
+        methods.dedup_by_key(|(address, _)| *address);
+
+        Ok(Self {
+            methods,
+            metadata: entries("ScriptMetadata").collect(),
+        })
+    }
+
+    fn method_address(&self, name: &str) -> Option<u64> {
+        self.methods.iter().find(|(_, method)| method == name).map(|(address, _)| *address)
+    }
+
+    /// Index of the method containing `address`
+    fn method_at(&self, address: u64) -> Option<usize> {
+        self.methods.partition_point(|&(start, _)| start <= address).checked_sub(1)
+    }
+
+    /// Class name of a `_TypeInfo` slot
+    fn class_at(&self, address: u64) -> Option<&str> {
+        self.metadata.get(&address).and_then(|name| name.strip_suffix("_TypeInfo"))
+    }
+}
+
+/// What a location was last set to before an instruction
+enum Source {
+    Immediate(u64),
+    /// Read from a global, by RVA
+    Global(u64),
+    /// The return value of the call at this index
+    CallResult(usize),
+    Parameter(Register),
+    Undefined,
+    Other,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Location {
+    Register(Register),
+    /// A stack slot, by base register and displacement
+    Stack(Register, u64),
+}
+
+/// A decoded method, ended by the next method in script.json
+struct Function {
+    start: u64,
+    instructions: Vec<Instruction>,
+}
+
+/// A `SendPacket` call site that was resolved or skipped
+pub struct ScannedCall {
+    pub address: u64,
+    pub caller: String,
+    pub result: Result<(String, u32), Problem>,
+}
+
+/// Recovers `Class => cmdid` pairs from the `SendPacket` call sites of an IL2CPP build, like derive_csreq_ids.py
+///
+/// Data flow is followed backwards through register moves and stack slots, branches are ignored
+pub struct CmdIdScanner<'a> {
+    image: &'a PeImage,
+    script: &'a ScriptMetadata,
+    /// Call site addresses by call target
+    callers: HashMap<u64, Vec<u64>>,
+}
+
+impl<'a> CmdIdScanner<'a> {
+    pub fn new(image: &'a PeImage, script: &'a ScriptMetadata) -> Self {
+        let mut scanner = Self { image, script, callers: HashMap::new() };
+
+        for index in 0..script.methods.len() {
+            for instruction in scanner.decode(index).instructions {
+                if instruction.is_call_near() {
+                    scanner.callers.entry(instruction.near_branch_target()).or_default().push(instruction.ip());
+                }
+            }
+        }
+
+        scanner
+    }
+
+    pub fn scan(&self, send_packet: &str) -> Result<Vec<ScannedCall>, ScanError> {
+        let target = self.script.method_address(send_packet).ok_or_else(|| ScanError::MissingMethod(send_packet.to_string()))?;
+
+        let mut calls = Vec::new();
+        for &address in self.callers.get(&target).into_iter().flatten() {
+            let Some(method) = self.script.method_at(address) else {
+                continue;
+            };
+
+            let function = self.decode(method);
+            let index = function.instructions.iter().position(|instruction| instruction.ip() == address).unwrap();
+
+            let result = match trace(&function.instructions, index, Location::Register(Register::RDX)) {
+                Source::Immediate(cmd_id) => self.trace_message(&function, index, Location::Register(Register::R8), 0).map(|class| (class, cmd_id as u32)),
+                _ => Err(Problem::CmdIdNotConstant),
+            };
+
+            calls.push(ScannedCall { address, caller: self.script.methods[method].1.clone(), result });
+        }
+
+        calls.sort_by_key(|call| call.address);
+        Ok(calls)
+    }
+
+    fn decode(&self, method: usize) -> Function {
+        let start = self.script.methods[method].0;
+        let end = self.script.methods.get(method + 1).map_or(u64::MAX, |(address, _)| *address);
+
+        let bytes = self.image.bytes(start, end).unwrap_or_default();
+        let instructions = Decoder::with_ip(64, bytes, start, DecoderOptions::NONE).into_iter().collect();
+
+        Function { start, instructions }
+    }
+
+    /// Class of the message in `location` at the call at `index`
+    fn trace_message(&self, function: &Function, index: usize, location: Location, depth: usize) -> Result<String, Problem> {
+        match trace(&function.instructions, index, location) {
+            // Messages are allocated with their class as the only argument
+            Source::CallResult(allocation) => match trace(&function.instructions, allocation, Location::Register(Register::RCX)) {
+                Source::Global(address) => self.script.class_at(address).map(str::to_string).ok_or(Problem::UnknownClass(address)),
+                _ => Err(Problem::ClassNotConstant),
+            },
+            // Wrappers pass their own parameter on, follow it into each caller until one allocates the message
+            Source::Parameter(register) if depth < MAX_CALLER_DEPTH => {
+                let callers = self.callers.get(&function.start).into_iter().flatten();
+                for &address in callers {
+                    let Some(method) = self.script.method_at(address) else {
+                        continue;
+                    };
+
+                    let caller = self.decode(method);
+                    let index = caller.instructions.iter().position(|instruction| instruction.ip() == address).unwrap();
+                    if let Ok(class) = self.trace_message(&caller, index, Location::Register(register), depth + 1) {
+                        return Ok(class);
+                    }
+                }
+
+                Err(Problem::NoCallSitesMatched)
+            }
+            Source::Parameter(_) => Err(Problem::NoCallSitesMatched),
+            Source::Immediate(_) | Source::Global(_) => Err(Problem::DataNotVariable),
+            Source::Undefined => Err(Problem::DataUndefined),
+            Source::Other => Err(Problem::NotAllocated),
+        }
+    }
+}
+
+/// Follows `location` backwards from the instruction at `index` to where its value came from
+fn trace(instructions: &[Instruction], index: usize, mut location: Location) -> Source {
+    let mut info_factory = InstructionInfoFactory::new();
+
+    for (position, instruction) in instructions[..index].iter().enumerate().rev() {
+        if instruction.is_call_near() || instruction.is_call_near_indirect() {
+            match location {
+                Location::Register(Register::RAX) => return Source::CallResult(position),
+                Location::Register(register) if VOLATILE_REGISTERS.contains(&register) => return Source::Other,
+                _ => continue,
+            }
+        }
+
+        let writes = match location {
+            Location::Register(register) => instruction.op0_kind() == OpKind::Register
+                && instruction.op0_register().full_register() == register
+                && matches!(info_factory.info(instruction).op0_access(), OpAccess::Write | OpAccess::ReadWrite | OpAccess::CondWrite | OpAccess::ReadCondWrite),
+            Location::Stack(base, displacement) => instruction.op0_kind() == OpKind::Memory
+                && instruction.memory_base().full_register() == base
+                && instruction.memory_index() == Register::None
+                && instruction.memory_displacement64() == displacement,
+        };
+        if !writes {
+            continue;
+        }
+
+        match (instruction.mnemonic(), instruction.op1_kind()) {
+            (Mnemonic::Mov, OpKind::Register) => location = Location::Register(instruction.op1_register().full_register()),
+            (Mnemonic::Mov, OpKind::Memory) if instruction.is_ip_rel_memory_operand() => return Source::Global(instruction.ip_rel_memory_address()),
+            (Mnemonic::Mov, OpKind::Memory) if instruction.memory_index() == Register::None && matches!(instruction.memory_base(), Register::RSP | Register::RBP) => {
+                location = Location::Stack(instruction.memory_base(), instruction.memory_displacement64());
+            }
+            (Mnemonic::Mov, _) if instruction.op_count() == 2 && is_immediate(instruction.op1_kind()) => return Source::Immediate(instruction.immediate(1)),
+            // `xor ecx, ecx` zeroes a register
+            (Mnemonic::Xor, OpKind::Register) if instruction.op1_register() == instruction.op0_register() => return Source::Immediate(0),
+            _ => return Source::Other,
+        }
+    }
+
+    match location {
+        Location::Register(register) if PARAMETER_REGISTERS.contains(&register) => Source::Parameter(register),
+        _ => Source::Undefined,
+    }
+}
+
+fn is_immediate(kind: OpKind) -> bool {
+    matches!(kind, OpKind::Immediate8 | OpKind::Immediate16 | OpKind::Immediate32 | OpKind::Immediate64
+        | OpKind::Immediate8to16 | OpKind::Immediate8to32 | OpKind::Immediate8to64 | OpKind::Immediate32to64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: u64 = 0x1000;
+
+    /// A PE image with a single section holding `code` at RVA 0x1000
+    fn image(code: &[u8]) -> PeImage {
+        let mut data = vec![0; 0x200];
+        data[0..2].copy_from_slice(b"MZ");
+        data[0x3c..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+        data[0x40..0x44].copy_from_slice(b"PE\0\0");
+        data[0x44..0x46].copy_from_slice(&0x8664u16.to_le_bytes());
+        data[0x46..0x48].copy_from_slice(&1u16.to_le_bytes());
+
+        let section = 0x40 + 24;
+        data[section + 8..section + 12].copy_from_slice(&(code.len() as u32).to_le_bytes());
+        data[section + 12..section + 16].copy_from_slice(&(CODE as u32).to_le_bytes());
+        data[section + 16..section + 20].copy_from_slice(&(code.len() as u32).to_le_bytes());
+        data[section + 20..section + 24].copy_from_slice(&0x200u32.to_le_bytes());
+
+        data.extend_from_slice(code);
+        PeImage::parse(data).unwrap()
+    }
+
+    struct Assembler {
+        code: Vec<u8>,
+    }
+
+    impl Assembler {
+        fn at(&mut self, address: u64) {
+            self.code.resize((address - CODE) as usize, 0xcc);
+        }
+
+        fn address(&self) -> u64 {
+            CODE + self.code.len() as u64
+        }
+
+        fn emit(&mut self, bytes: &[u8]) {
+            self.code.extend_from_slice(bytes);
+        }
+
+        fn relative(&mut self, opcode: &[u8], target: u64) {
+            let next = self.address() + opcode.len() as u64 + 4;
+            self.emit(opcode);
+            self.emit(&((target as i64 - next as i64) as i32).to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn test_scan_send_packet_callers() {
+        let (send_packet, object_new, login, wrapper, bag) = (0x1000, 0x1010, 0x1020, 0x1060, 0x1080);
+        let (login_type_info, bag_type_info) = (0x2000, 0x2008);
+
+        let mut asm = Assembler { code: Vec::new() };
+        asm.emit(&[0xc3]);
+        asm.at(object_new);
+        asm.emit(&[0xc3]);
+
+        // Allocates the message, keeps it in rbx across a call and sends it
+        asm.at(login);
+        asm.relative(&[0x48, 0x8b, 0x0d], login_type_info); // mov rcx, [rip+PlayerLoginCsReq_TypeInfo]
+        asm.relative(&[0xe8], object_new); // call object_new
+        asm.emit(&[0x48, 0x89, 0xc3]); // mov rbx, rax
+        asm.emit(&[0x48, 0x89, 0xd9]); // mov rcx, rbx
+        asm.relative(&[0xe8], object_new); // call .ctor
+        asm.emit(&[0xba, 0x15, 0x00, 0x00, 0x00]); // mov edx, 21
+        asm.emit(&[0x49, 0x89, 0xd8]); // mov r8, rbx
+        asm.relative(&[0xe8], send_packet);
+        asm.emit(&[0xc3]);
+
+        // Sends whatever message it is passed
+        asm.at(wrapper);
+        asm.emit(&[0xba, 0x2a, 0x00, 0x00, 0x00]); // mov edx, 42
+        asm.relative(&[0xe8], send_packet);
+        asm.emit(&[0x89, 0xc2]); // mov edx, eax
+        asm.relative(&[0xe8], send_packet);
+        asm.emit(&[0xc3]);
+
+        asm.at(bag);
+        asm.relative(&[0x48, 0x8b, 0x0d], bag_type_info); // mov rcx, [rip+GetBagCsReq_TypeInfo]
+        asm.relative(&[0xe8], object_new); // call object_new
+        asm.emit(&[0x49, 0x89, 0xc0]); // mov r8, rax
+        asm.relative(&[0xe8], wrapper);
+        asm.emit(&[0xc3]);
+
+        let script = ScriptMetadata::parse(&format!(r#"{{
+            "ScriptMethod": [
+                {{ "Address": {send_packet}, "Name": "{SEND_PACKET}" }},
+                {{ "Address": {login}, "Name": "RPG.Client.LoginModule$$Login" }},
+                {{ "Address": {wrapper}, "Name": "RPG.Client.BagModule$$Send" }},
+                {{ "Address": {bag}, "Name": "RPG.Client.BagModule$$Refresh" }}
+            ],
+            "ScriptMetadata": [
+                {{ "Address": {login_type_info}, "Name": "RPG.Network.Proto.PlayerLoginCsReq_TypeInfo" }},
+                {{ "Address": {bag_type_info}, "Name": "RPG.Network.Proto.GetBagCsReq_TypeInfo" }}
+            ]
+        }}"#)).unwrap();
+
+        let image = image(&asm.code);
+        let scanner = CmdIdScanner::new(&image, &script);
+        let results = scanner.scan(SEND_PACKET).unwrap().into_iter().map(|call| call.result).collect::<Vec<_>>();
+
+        assert_eq!(results, [
+            Ok(("RPG.Network.Proto.PlayerLoginCsReq".to_string(), 21)),
+            Ok(("RPG.Network.Proto.GetBagCsReq".to_string(), 42)),
+            Err(Problem::CmdIdNotConstant),
+        ]);
+        assert!(scanner.scan("Missing$$Method").is_err());
+    }
+}
@@ -0,0 +1,330 @@
+use std::fmt::{self, Write};
+
+use itertools::Itertools;
+use serde_json::json;
+
+use crate::diff::{Change, SchemaDiff};
+use crate::prototype::{ProtoDatabase, ProtoLabel, ProtoName};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Compatibility {
+    Breaking,
+    /// Depends on what the new name or type turns out to be
+    Review,
+    Compatible,
+}
+
+impl Compatibility {
+    pub fn key(&self) -> &'static str {
+        match self {
+            Compatibility::Breaking => "breaking",
+            Compatibility::Review => "review",
+            Compatibility::Compatible => "compatible",
+        }
+    }
+}
+
+pub struct CheckedChange {
+    pub change: Change,
+    pub compatibility: Compatibility,
+    pub reason: String,
+}
+
+/// Encodings that can read each other's values, following the protobuf language guide
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WireClass {
+    /// `int32`, `int64`, `uint32`, `uint64`, `bool` and enums
+    Varint,
+    /// `sint32` and `sint64`
+    ZigZag,
+    /// `fixed32` and `sfixed32`
+    Fixed32,
+    /// `fixed64` and `sfixed64`
+    Fixed64,
+    Float,
+    Double,
+    /// `string`, `bytes` and messages
+    LengthDelimited,
+}
+
+impl fmt::Display for WireClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            WireClass::Varint => "varint",
+            WireClass::ZigZag => "zigzag varint",
+            WireClass::Fixed32 => "fixed32",
+            WireClass::Fixed64 => "fixed64",
+            WireClass::Float => "float",
+            WireClass::Double => "double",
+            WireClass::LengthDelimited => "length-delimited",
+        })
+    }
+}
+
+/// How a field's values are encoded, from its type as the diff prints it
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum WireShape {
+    Single(WireClass),
+    Repeated(WireClass),
+    Map(WireClass, WireClass),
+}
+
+impl fmt::Display for WireShape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WireShape::Single(class) => write!(f, "{}", class),
+            WireShape::Repeated(class) => write!(f, "repeated {}", class),
+            WireShape::Map(key, value) => write!(f, "map of {} to {}", key, value),
+        }
+    }
+}
+
+/// Every change of a schema diff, classified by whether peers built against the old schema can still talk to the new one
+pub struct CompatReport {
+    pub changes: Vec<CheckedChange>,
+}
+
+impl CompatReport {
+    pub fn new(diff: &SchemaDiff, proto_db_a: &ProtoDatabase, proto_db_b: &ProtoDatabase) -> Self {
+        let checker = Checker { proto_db_a, proto_db_b };
+
+        let mut changes = diff.changes.iter()
+            .map(|change| {
+                let (compatibility, reason) = checker.check(change);
+                CheckedChange { change: change.clone(), compatibility, reason }
+            })
+            .collect::<Vec<_>>();
+
+        // Stable, so changes keep the diff's order within a class
+        changes.sort_by_key(|checked| checked.compatibility);
+
+        Self { changes }
+    }
+
+    pub fn is_breaking(&self) -> bool {
+        self.changes.iter().any(|checked| checked.compatibility == Compatibility::Breaking)
+    }
+
+    /// Messages and enums with a breaking change, whose handlers need to be looked at
+    pub fn breaking_types(&self) -> Vec<&str> {
+        self.changes.iter()
+            .filter(|checked| checked.compatibility == Compatibility::Breaking)
+            .map(|checked| checked.change.scope())
+            .unique()
+            .collect()
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut output = String::new();
+        for checked in &self.changes {
+            writeln!(output, "[{}] {}: {}", checked.compatibility.key(), checked.change, checked.reason).unwrap();
+        }
+
+        if self.is_breaking() {
+            writeln!(output, "\nBreaking changes in {}", self.breaking_types().join(", ")).unwrap();
+        }
+        output
+    }
+
+    pub fn to_json(&self) -> String {
+        let changes = self.changes.iter()
+            .map(|checked| json!({
+                "compatibility": checked.compatibility.key(),
+                "reason": checked.reason,
+                "change": checked.change.to_json(),
+            }))
+            .collect::<Vec<_>>();
+
+        serde_json::to_string_pretty(&changes).unwrap()
+    }
+}
+
+struct Checker<'a> {
+    proto_db_a: &'a ProtoDatabase,
+    proto_db_b: &'a ProtoDatabase,
+}
+
+impl Checker<'_> {
+    fn check(&self, change: &Change) -> (Compatibility, String) {
+        use Compatibility::*;
+
+        match change {
+            Change::AddedMessage(_) | Change::AddedEnum(_) => (Compatible, "new types are unknown to old peers, but nothing they send changes".to_string()),
+            Change::RemovedMessage(_) | Change::RemovedEnum(_) => (Compatible, "types are not named on the wire, handlers for the message are gone though".to_string()),
+            Change::RenamedMessage { .. } | Change::MovedType { .. } => (Compatible, "type names and places are not on the wire".to_string()),
+            Change::UnresolvedType(_) | Change::UnresolvedField { .. } => (Review, "unresolved, whether it is new cannot be told".to_string()),
+
+            Change::AddedField { message, field, .. } => match self.label(self.proto_db_b, message, field) {
+                Some(ProtoLabel::Required) => (Breaking, "required, old senders do not set it".to_string()),
+                _ => (Compatible, "old readers skip unknown fields".to_string()),
+            },
+            Change::RemovedField { message, field, number, .. } => match self.label(self.proto_db_a, message, field) {
+                Some(ProtoLabel::Required) => (Breaking, "was required, old readers reject messages without it".to_string()),
+                _ => (Compatible, format!("number {} should be reserved so it is not reused", number)),
+            },
+            Change::RenamedField { message, from, to, number } => {
+                let type_a = self.field_type(self.proto_db_a, message, from);
+                let type_b = self.field_type(self.proto_db_b, message, to);
+                match (type_a, type_b) {
+                    (Some(type_a), Some(type_b)) if type_a != type_b => match self.type_change(&type_a, &type_b) {
+                        (Compatible, _) => (Compatible, "field names are not on the wire".to_string()),
+                        (compatibility, reason) => (compatibility, format!("number {} reused for {} {}, {}", number, type_b, to, reason)),
+                    },
+                    _ => (Compatible, "field names are not on the wire".to_string()),
+                }
+            }
+            Change::FieldTypeChanged { from, to, .. } => self.type_change(from, to),
+            Change::FieldNumberChanged { from, to, .. } => (Breaking, format!("old peers still use number {}, not {}", from, to)),
+            Change::LabelChanged { from, to, .. } => match (from.as_str(), to.as_str()) {
+                (_, "required") => (Breaking, "now required, old senders may leave it out".to_string()),
+                ("required", _) => (Breaking, "no longer required, old readers reject messages without it".to_string()),
+                _ => (Review, "same encoding, but a zero value is only sent with explicit presence, so set and unset can't always be told apart".to_string()),
+            },
+
+            Change::AddedEnumValue { .. } => (Compatible, "old readers keep unknown values as numbers".to_string()),
+            Change::RemovedEnumValue { number, .. } => (Compatible, format!("{} becomes an unknown value, it should be reserved", number)),
+            Change::RenamedEnumValue { .. } => (Compatible, "enum values are sent by number".to_string()),
+            Change::EnumValueNumberChanged { from, to, .. } => (Breaking, format!("old peers send {} for what is now {}", from, to)),
+            Change::UnresolvedEnumValue { number, .. } => (Review, format!("unresolved, whether {} still means the same value cannot be told", number)),
+        }
+    }
+
+    fn type_change(&self, from: &str, to: &str) -> (Compatibility, String) {
+        use Compatibility::*;
+
+        let (shape_a, shape_b) = (self.wire_shape(self.proto_db_a, from), self.wire_shape(self.proto_db_b, to));
+        let compatible = match (&shape_a, &shape_b) {
+            (WireShape::Single(a), WireShape::Single(b)) | (WireShape::Repeated(a), WireShape::Repeated(b)) => a == b,
+            // Length-delimited values are never packed, so a repeated field reads a single one, and a single field the last of several (or all of them merged, for messages)
+            (WireShape::Single(a), WireShape::Repeated(b)) | (WireShape::Repeated(a), WireShape::Single(b)) => *a == WireClass::LengthDelimited && a == b,
+            (WireShape::Map(key_a, value_a), WireShape::Map(key_b, value_b)) => key_a == key_b && value_a == value_b,
+            _ => false,
+        };
+
+        if !compatible {
+            return (Breaking, format!("{} and {} are encoded differently", shape_a, shape_b));
+        }
+
+        // Messages share an encoding, but not necessarily a layout
+        let is_message = |proto_db: &ProtoDatabase, name: &str| ProtoName::try_lookup(proto_db, base_type(name)).is_some_and(|name| proto_db.message_db.contains_left(&name));
+        match (is_message(self.proto_db_a, from), is_message(self.proto_db_b, to)) {
+            (true, true) => return (Review, format!("both are messages, compatible only if {} reads {}", to, from)),
+            // A message is read from any string or bytes, but only a valid encoding of it parses
+            (true, false) | (false, true) => return (Review, format!("both are length-delimited, but {} only reads {} values that encode it", to, from)),
+            (false, false) => (),
+        }
+
+        (Compatible, format!("{} on both sides", shape_b))
+    }
+
+    fn wire_shape(&self, proto_db: &ProtoDatabase, type_name: &str) -> WireShape {
+        if let Some((key, value)) = type_name.strip_prefix("map<").and_then(|inner| inner.strip_suffix('>')).and_then(|inner| inner.split_once(", ")) {
+            return WireShape::Map(self.wire_class(proto_db, key), self.wire_class(proto_db, value));
+        }
+
+        match type_name.strip_prefix("repeated ") {
+            Some(inner) => WireShape::Repeated(self.wire_class(proto_db, inner)),
+            None => WireShape::Single(self.wire_class(proto_db, type_name)),
+        }
+    }
+
+    fn wire_class(&self, proto_db: &ProtoDatabase, type_name: &str) -> WireClass {
+        match type_name {
+            "int32" | "int64" | "uint32" | "uint64" | "bool" => WireClass::Varint,
+            "sint32" | "sint64" => WireClass::ZigZag,
+            "fixed32" | "sfixed32" => WireClass::Fixed32,
+            "fixed64" | "sfixed64" => WireClass::Fixed64,
+            "float" => WireClass::Float,
+            "double" => WireClass::Double,
+            "string" | "bytes" => WireClass::LengthDelimited,
+            name if ProtoName::try_lookup(proto_db, name).is_some_and(|name| proto_db.enum_db.contains_left(&name)) => WireClass::Varint,
+            _ => WireClass::LengthDelimited,
+        }
+    }
+
+    fn label(&self, proto_db: &ProtoDatabase, message: &str, field: &str) -> Option<ProtoLabel> {
+        let message = proto_db.get_message(message)?;
+        message.fields.iter().find(|other| other.name.name(proto_db) == field).map(|field| field.label)
+    }
+
+    fn field_type(&self, proto_db: &ProtoDatabase, message: &str, field: &str) -> Option<String> {
+        let message = proto_db.get_message(message)?;
+        message.fields.iter().find(|other| other.name.name(proto_db) == field).map(|field| field.field_type.type_name(proto_db))
+    }
+}
+
+/// `repeated Foo` -> `Foo`, maps are left as they are
+fn base_type(type_name: &str) -> &str {
+    type_name.strip_prefix("repeated ").unwrap_or(type_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::parse_test_proto;
+
+    #[test]
+    fn test_compatibility() {
+        let proto_db_a = parse_test_proto("
+            syntax = \"proto2\";
+
+            message Avatar {
+                optional uint32 level = 1;
+                optional sint32 exp = 2;
+                optional string tags = 3;
+                required uint32 id = 4;
+                optional float old = 5;
+                optional uint32 flags = 6;
+                optional string info = 7;
+            }
+
+            message Info {}
+
+            enum Color {
+                COLOR_RED = 1;
+            }
+        ");
+
+        let proto_db_b = parse_test_proto("
+            syntax = \"proto2\";
+
+            enum Rarity {
+                RARITY_NONE = 0;
+            }
+
+            enum Color {
+                CDEFGHIJKLM = 1;
+            }
+
+            message Avatar {
+                optional Rarity level = 1;
+                optional int32 exp = 2;
+                repeated string tags = 3;
+                optional uint32 rank = 5;
+                required uint32 flags = 6;
+                optional Info info = 7;
+            }
+
+            message Info {}
+        ");
+
+        let diff = SchemaDiff::new(&proto_db_a, &proto_db_b);
+        let report = CompatReport::new(&diff, &proto_db_a, &proto_db_b);
+
+        let classes = report.changes.iter().map(|checked| (checked.change.to_string(), checked.compatibility)).collect::<Vec<_>>();
+        assert_eq!(classes, [
+            ("~ Avatar.exp: type sint32 -> int32".to_string(), Compatibility::Breaking),
+            ("~ Avatar.flags: optional -> required".to_string(), Compatibility::Breaking),
+            ("~ Avatar.old renamed to rank (= 5)".to_string(), Compatibility::Breaking),
+            ("~ Avatar.rank: type float -> uint32".to_string(), Compatibility::Breaking),
+            ("- Avatar.id: uint32 = 4".to_string(), Compatibility::Breaking),
+            ("~ Avatar.info: type string -> Info".to_string(), Compatibility::Review),
+            ("? Color.CDEFGHIJKLM = 1 is unresolved".to_string(), Compatibility::Review),
+            ("~ Avatar.level: type uint32 -> Rarity".to_string(), Compatibility::Compatible),
+            ("~ Avatar.tags: type string -> repeated string".to_string(), Compatibility::Compatible),
+            ("+ enum Rarity".to_string(), Compatibility::Compatible),
+        ]);
+        assert_eq!(report.breaking_types(), ["Avatar"]);
+        assert!(report.changes[2].reason.starts_with("number 5 reused for uint32 rank"));
+    }
+}
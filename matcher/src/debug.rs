@@ -83,3 +83,12 @@ impl<K: DebugWithName + Eq + Hash, V: DebugWithName + Eq + Hash> DebugWithName f
 
     }
 }
+
+impl<V: DebugWithName> DebugWithName for Option<V> {
+    fn debug_with_name(&self, db: &ProtoDatabase) -> String {
+        match self {
+            Some(v) => format!("Some({})", v.debug_with_name(db)),
+            None => "None".to_string(),
+        }
+    }
+}
@@ -0,0 +1,1478 @@
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use itertools::Itertools;
+use tracing::warn;
+
+use crate::prototype::{DefinitionRef, ProtoDatabase, ProtoEnum, ProtoEnumValue, ProtoExtension, ProtoField, ProtoFieldKind, ProtoLabel, ProtoMessage, ProtoName, ProtoOption, ProtoReserved, ProtoRpc, ProtoService, ProtoSyntax, ProtoType, Span, RESERVED_MAX};
+use crate::wire::{decode_string, WireError, WireReader, WireWriter};
+
+// Field types of `FieldDescriptorProto.Type`
+const TYPE_DOUBLE: i32 = 1;
+const TYPE_FLOAT: i32 = 2;
+const TYPE_INT64: i32 = 3;
+const TYPE_UINT64: i32 = 4;
+const TYPE_INT32: i32 = 5;
+const TYPE_FIXED64: i32 = 6;
+const TYPE_FIXED32: i32 = 7;
+const TYPE_BOOL: i32 = 8;
+const TYPE_STRING: i32 = 9;
+const TYPE_MESSAGE: i32 = 11;
+const TYPE_BYTES: i32 = 12;
+const TYPE_UINT32: i32 = 13;
+const TYPE_ENUM: i32 = 14;
+const TYPE_SFIXED32: i32 = 15;
+const TYPE_SFIXED64: i32 = 16;
+const TYPE_SINT32: i32 = 17;
+const TYPE_SINT64: i32 = 18;
+
+const LABEL_OPTIONAL: i32 = 1;
+const LABEL_REQUIRED: i32 = 2;
+const LABEL_REPEATED: i32 = 3;
+
+/// Exclusive end of a message reserved range written as `to max`
+const MESSAGE_RANGE_MAX: i32 = 1 << 29;
+
+/// A `FileDescriptorProto`, with options already in their `.proto` spelling
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileDescriptor {
+    pub name: String,
+    pub package: Option<String>,
+    /// Names of the files this one imports
+    pub dependencies: Vec<String>,
+    pub messages: Vec<MessageDescriptor>,
+    pub enums: Vec<EnumDescriptor>,
+    pub services: Vec<ServiceDescriptor>,
+    pub extensions: Vec<FieldDescriptor>,
+    pub options: Vec<ProtoOption>,
+    pub syntax: Option<String>,
+    pub edition: Option<i32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MessageDescriptor {
+    pub name: String,
+    pub fields: Vec<FieldDescriptor>,
+    pub nested: Vec<MessageDescriptor>,
+    pub enums: Vec<EnumDescriptor>,
+    pub extensions: Vec<FieldDescriptor>,
+    pub oneofs: Vec<String>,
+    /// Start inclusive, end exclusive
+    pub extension_ranges: Vec<(i32, i32)>,
+    /// Start inclusive, end exclusive
+    pub reserved_ranges: Vec<(i32, i32)>,
+    pub reserved_names: Vec<String>,
+    pub options: Vec<ProtoOption>,
+}
+
+impl MessageDescriptor {
+    /// Synthesized `XxxEntry` message of a map field
+    fn is_map_entry(&self) -> bool {
+        self.options.iter().any(|option| option.name == "map_entry" && option.value == "true")
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FieldDescriptor {
+    pub name: String,
+    pub extendee: Option<String>,
+    pub number: i32,
+    pub label: i32,
+    pub field_type: i32,
+    /// Fully qualified for message and enum types, e.g. `.pkg.Outer.Inner`
+    pub type_name: Option<String>,
+    pub default_value: Option<String>,
+    pub oneof_index: Option<i32>,
+    pub proto3_optional: bool,
+    pub options: Vec<ProtoOption>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EnumDescriptor {
+    pub name: String,
+    pub values: Vec<(String, i32, Vec<ProtoOption>)>,
+    /// Both ends inclusive
+    pub reserved_ranges: Vec<(i32, i32)>,
+    pub reserved_names: Vec<String>,
+    pub options: Vec<ProtoOption>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ServiceDescriptor {
+    pub name: String,
+    pub methods: Vec<MethodDescriptor>,
+    pub options: Vec<ProtoOption>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MethodDescriptor {
+    pub name: String,
+    pub input_type: String,
+    pub output_type: String,
+    pub client_streaming: bool,
+    pub server_streaming: bool,
+    pub options: Vec<ProtoOption>,
+}
+
+/// How a known option is spelled in `.proto` source
+#[derive(Debug, Clone, Copy)]
+enum OptionKind {
+    Bool,
+    String,
+    Enum(&'static [(i32, &'static str)]),
+    /// A `FeatureSet`, spelled as `features.<name>` options
+    Features,
+}
+
+/// Options of `descriptor.proto` by number, custom options can't be named without their definitions and are dropped
+type KnownOptions = &'static [(u32, &'static str, OptionKind)];
+
+const OPTIMIZE_MODES: &[(i32, &str)] = &[(1, "SPEED"), (2, "CODE_SIZE"), (3, "LITE_RUNTIME")];
+
+const FILE_OPTIONS: KnownOptions = &[
+    (1, "java_package", OptionKind::String),
+    (8, "java_outer_classname", OptionKind::String),
+    (9, "optimize_for", OptionKind::Enum(OPTIMIZE_MODES)),
+    (10, "java_multiple_files", OptionKind::Bool),
+    (11, "go_package", OptionKind::String),
+    (23, "deprecated", OptionKind::Bool),
+    (31, "cc_enable_arenas", OptionKind::Bool),
+    (36, "objc_class_prefix", OptionKind::String),
+    (37, "csharp_namespace", OptionKind::String),
+    (50, "features", OptionKind::Features),
+];
+
+const MESSAGE_OPTIONS: KnownOptions = &[
+    (3, "deprecated", OptionKind::Bool),
+    (7, "map_entry", OptionKind::Bool),
+    (12, "features", OptionKind::Features),
+];
+
+const FIELD_OPTIONS: KnownOptions = &[
+    (2, "packed", OptionKind::Bool),
+    (3, "deprecated", OptionKind::Bool),
+    (5, "lazy", OptionKind::Bool),
+    (21, "features", OptionKind::Features),
+];
+
+const ENUM_OPTIONS: KnownOptions = &[
+    (2, "allow_alias", OptionKind::Bool),
+    (3, "deprecated", OptionKind::Bool),
+    (7, "features", OptionKind::Features),
+];
+
+const ENUM_VALUE_OPTIONS: KnownOptions = &[
+    (1, "deprecated", OptionKind::Bool),
+    (2, "features", OptionKind::Features),
+];
+
+const SERVICE_OPTIONS: KnownOptions = &[
+    (33, "deprecated", OptionKind::Bool),
+    (34, "features", OptionKind::Features),
+];
+
+const METHOD_OPTIONS: KnownOptions = &[
+    (33, "deprecated", OptionKind::Bool),
+    (35, "features", OptionKind::Features),
+];
+
+const FEATURES: KnownOptions = &[
+    (1, "field_presence", OptionKind::Enum(&[(1, "EXPLICIT"), (2, "IMPLICIT"), (3, "LEGACY_REQUIRED")])),
+    (2, "enum_type", OptionKind::Enum(&[(1, "OPEN"), (2, "CLOSED")])),
+    (3, "repeated_field_encoding", OptionKind::Enum(&[(1, "PACKED"), (2, "EXPANDED")])),
+    (4, "utf8_validation", OptionKind::Enum(&[(2, "VERIFY"), (3, "NONE")])),
+    (5, "message_encoding", OptionKind::Enum(&[(1, "LENGTH_PREFIXED"), (2, "DELIMITED")])),
+    (6, "json_format", OptionKind::Enum(&[(1, "ALLOW"), (2, "LEGACY_BEST_EFFORT")])),
+];
+
+/// `Edition` enum values and the string used in `edition = "..."`
+const EDITIONS: &[(i32, &str)] = &[(998, "proto2"), (999, "proto3"), (1000, "2023"), (1001, "2024")];
+
+/// Decodes a serialized `FileDescriptorSet`
+pub fn decode_descriptor_set(data: &[u8]) -> Result<Vec<FileDescriptor>, WireError> {
+    let mut files = Vec::new();
+
+    let mut reader = WireReader::new(data);
+    while let Some((number, value)) = reader.read_field()? {
+        if number == 1 {
+            files.push(decode_file(value.as_bytes())?);
+        }
+    }
+
+    Ok(files)
+}
+
+fn decode_file(data: &[u8]) -> Result<FileDescriptor, WireError> {
+    let mut file = FileDescriptor::default();
+
+    let mut reader = WireReader::new(data);
+    while let Some((number, value)) = reader.read_field()? {
+        match number {
+            1 => file.name = decode_string(&value)?,
+            2 => file.package = Some(decode_string(&value)?),
+            3 => file.dependencies.push(decode_string(&value)?),
+            4 => file.messages.push(decode_message(value.as_bytes())?),
+            5 => file.enums.push(decode_enum(value.as_bytes())?),
+            6 => file.services.push(decode_service(value.as_bytes())?),
+            7 => file.extensions.push(decode_field(value.as_bytes())?),
+            8 => file.options = decode_options(value.as_bytes(), FILE_OPTIONS)?,
+            12 => file.syntax = Some(decode_string(&value)?),
+            14 => file.edition = Some(value.as_i32()),
+            _ => (),
+        }
+    }
+
+    Ok(file)
+}
+
+fn decode_message(data: &[u8]) -> Result<MessageDescriptor, WireError> {
+    let mut message = MessageDescriptor::default();
+
+    let mut reader = WireReader::new(data);
+    while let Some((number, value)) = reader.read_field()? {
+        match number {
+            1 => message.name = decode_string(&value)?,
+            2 => message.fields.push(decode_field(value.as_bytes())?),
+            3 => message.nested.push(decode_message(value.as_bytes())?),
+            4 => message.enums.push(decode_enum(value.as_bytes())?),
+            5 => message.extension_ranges.push(decode_range(value.as_bytes())?),
+            6 => message.extensions.push(decode_field(value.as_bytes())?),
+            7 => message.options = decode_options(value.as_bytes(), MESSAGE_OPTIONS)?,
+            8 => message.oneofs.push(decode_name(value.as_bytes())?),
+            9 => message.reserved_ranges.push(decode_range(value.as_bytes())?),
+            10 => message.reserved_names.push(decode_string(&value)?),
+            _ => (),
+        }
+    }
+
+    Ok(message)
+}
+
+fn decode_field(data: &[u8]) -> Result<FieldDescriptor, WireError> {
+    let mut field = FieldDescriptor::default();
+
+    let mut reader = WireReader::new(data);
+    while let Some((number, value)) = reader.read_field()? {
+        match number {
+            1 => field.name = decode_string(&value)?,
+            2 => field.extendee = Some(decode_string(&value)?),
+            3 => field.number = value.as_i32(),
+            4 => field.label = value.as_i32(),
+            5 => field.field_type = value.as_i32(),
+            6 => field.type_name = Some(decode_string(&value)?),
+            7 => field.default_value = Some(decode_string(&value)?),
+            8 => field.options = decode_options(value.as_bytes(), FIELD_OPTIONS)?,
+            9 => field.oneof_index = Some(value.as_i32()),
+            17 => field.proto3_optional = value.as_bool(),
+            _ => (),
+        }
+    }
+
+    Ok(field)
+}
+
+fn decode_enum(data: &[u8]) -> Result<EnumDescriptor, WireError> {
+    let mut proto_enum = EnumDescriptor::default();
+
+    let mut reader = WireReader::new(data);
+    while let Some((number, value)) = reader.read_field()? {
+        match number {
+            1 => proto_enum.name = decode_string(&value)?,
+            2 => {
+                let (mut name, mut value_number, mut options) = (String::new(), 0, Vec::new());
+
+                let mut value_reader = WireReader::new(value.as_bytes());
+                while let Some((number, value)) = value_reader.read_field()? {
+                    match number {
+                        1 => name = decode_string(&value)?,
+                        2 => value_number = value.as_i32(),
+                        3 => options = decode_options(value.as_bytes(), ENUM_VALUE_OPTIONS)?,
+                        _ => (),
+                    }
+                }
+
+                proto_enum.values.push((name, value_number, options));
+            }
+            3 => proto_enum.options = decode_options(value.as_bytes(), ENUM_OPTIONS)?,
+            4 => proto_enum.reserved_ranges.push(decode_range(value.as_bytes())?),
+            5 => proto_enum.reserved_names.push(decode_string(&value)?),
+            _ => (),
+        }
+    }
+
+    Ok(proto_enum)
+}
+
+fn decode_service(data: &[u8]) -> Result<ServiceDescriptor, WireError> {
+    let mut service = ServiceDescriptor::default();
+
+    let mut reader = WireReader::new(data);
+    while let Some((number, value)) = reader.read_field()? {
+        match number {
+            1 => service.name = decode_string(&value)?,
+            2 => {
+                let mut method = MethodDescriptor::default();
+
+                let mut method_reader = WireReader::new(value.as_bytes());
+                while let Some((number, value)) = method_reader.read_field()? {
+                    match number {
+                        1 => method.name = decode_string(&value)?,
+                        2 => method.input_type = decode_string(&value)?,
+                        3 => method.output_type = decode_string(&value)?,
+                        4 => method.options = decode_options(value.as_bytes(), METHOD_OPTIONS)?,
+                        5 => method.client_streaming = value.as_bool(),
+                        6 => method.server_streaming = value.as_bool(),
+                        _ => (),
+                    }
+                }
+
+                service.methods.push(method);
+            }
+            3 => service.options = decode_options(value.as_bytes(), SERVICE_OPTIONS)?,
+            _ => (),
+        }
+    }
+
+    Ok(service)
+}
+
+/// `name` (field 1) of a message that has nothing else of interest, like `OneofDescriptorProto`
+fn decode_name(data: &[u8]) -> Result<String, WireError> {
+    let mut name = String::new();
+
+    let mut reader = WireReader::new(data);
+    while let Some((number, value)) = reader.read_field()? {
+        if number == 1 {
+            name = decode_string(&value)?;
+        }
+    }
+
+    Ok(name)
+}
+
+/// `start` and `end` of a reserved or extension range
+fn decode_range(data: &[u8]) -> Result<(i32, i32), WireError> {
+    let mut range = (0, 0);
+
+    let mut reader = WireReader::new(data);
+    while let Some((number, value)) = reader.read_field()? {
+        match number {
+            1 => range.0 = value.as_i32(),
+            2 => range.1 = value.as_i32(),
+            _ => (),
+        }
+    }
+
+    Ok(range)
+}
+
+fn decode_options(data: &[u8], known: KnownOptions) -> Result<Vec<ProtoOption>, WireError> {
+    let mut options = Vec::new();
+
+    let mut reader = WireReader::new(data);
+    while let Some((number, value)) = reader.read_field()? {
+        let Some(&(_, name, kind)) = known.iter().find(|(known_number, _, _)| *known_number == number) else {
+            continue;
+        };
+
+        match kind {
+            OptionKind::Bool => options.push(ProtoOption { name: name.to_string(), value: value.as_bool().to_string() }),
+            OptionKind::String => options.push(ProtoOption { name: name.to_string(), value: quote(&decode_string(&value)?) }),
+            OptionKind::Enum(values) => {
+                if let Some((_, value_name)) = values.iter().find(|(number, _)| *number == value.as_i32()) {
+                    options.push(ProtoOption { name: name.to_string(), value: value_name.to_string() });
+                }
+            }
+            OptionKind::Features => {
+                for feature in decode_options(value.as_bytes(), FEATURES)? {
+                    options.push(ProtoOption { name: format!("{}.{}", name, feature.name), value: feature.value });
+                }
+            }
+        }
+    }
+
+    Ok(options)
+}
+
+/// Serializes files as a `FileDescriptorSet`
+pub fn encode_descriptor_set(files: &[FileDescriptor]) -> Vec<u8> {
+    let mut writer = WireWriter::new();
+    for file in files {
+        writer.message(1, encode_file(file));
+    }
+    writer.finish()
+}
+
+fn encode_file(file: &FileDescriptor) -> WireWriter {
+    let mut writer = WireWriter::new();
+    writer.string(1, &file.name);
+    if let Some(package) = &file.package {
+        writer.string(2, package);
+    }
+    for dependency in &file.dependencies {
+        writer.string(3, dependency);
+    }
+    for message in &file.messages {
+        writer.message(4, encode_message(message));
+    }
+    for proto_enum in &file.enums {
+        writer.message(5, encode_enum(proto_enum));
+    }
+    for service in &file.services {
+        writer.message(6, encode_service(service));
+    }
+    for extension in &file.extensions {
+        writer.message(7, encode_field(extension));
+    }
+    encode_options(&mut writer, 8, &file.options, FILE_OPTIONS);
+    if let Some(syntax) = &file.syntax {
+        writer.string(12, syntax);
+    }
+    if let Some(edition) = file.edition {
+        writer.int32(14, edition);
+    }
+    writer
+}
+
+fn encode_message(message: &MessageDescriptor) -> WireWriter {
+    let mut writer = WireWriter::new();
+    writer.string(1, &message.name);
+    for field in &message.fields {
+        writer.message(2, encode_field(field));
+    }
+    for nested in &message.nested {
+        writer.message(3, encode_message(nested));
+    }
+    for proto_enum in &message.enums {
+        writer.message(4, encode_enum(proto_enum));
+    }
+    for &range in &message.extension_ranges {
+        writer.message(5, encode_range(range));
+    }
+    for extension in &message.extensions {
+        writer.message(6, encode_field(extension));
+    }
+    encode_options(&mut writer, 7, &message.options, MESSAGE_OPTIONS);
+    for oneof in &message.oneofs {
+        let mut oneof_writer = WireWriter::new();
+        oneof_writer.string(1, oneof);
+        writer.message(8, oneof_writer);
+    }
+    for &range in &message.reserved_ranges {
+        writer.message(9, encode_range(range));
+    }
+    for name in &message.reserved_names {
+        writer.string(10, name);
+    }
+    writer
+}
+
+fn encode_field(field: &FieldDescriptor) -> WireWriter {
+    let mut writer = WireWriter::new();
+    writer.string(1, &field.name);
+    if let Some(extendee) = &field.extendee {
+        writer.string(2, extendee);
+    }
+    writer.int32(3, field.number);
+    writer.int32(4, field.label);
+    writer.int32(5, field.field_type);
+    if let Some(type_name) = &field.type_name {
+        writer.string(6, type_name);
+    }
+    if let Some(default_value) = &field.default_value {
+        writer.string(7, default_value);
+    }
+    encode_options(&mut writer, 8, &field.options, FIELD_OPTIONS);
+    if let Some(oneof_index) = field.oneof_index {
+        writer.int32(9, oneof_index);
+    }
+    if field.proto3_optional {
+        writer.bool(17, true);
+    }
+    writer
+}
+
+fn encode_enum(proto_enum: &EnumDescriptor) -> WireWriter {
+    let mut writer = WireWriter::new();
+    writer.string(1, &proto_enum.name);
+    for (name, number, options) in &proto_enum.values {
+        let mut value_writer = WireWriter::new();
+        value_writer.string(1, name);
+        value_writer.int32(2, *number);
+        encode_options(&mut value_writer, 3, options, ENUM_VALUE_OPTIONS);
+        writer.message(2, value_writer);
+    }
+    encode_options(&mut writer, 3, &proto_enum.options, ENUM_OPTIONS);
+    for &range in &proto_enum.reserved_ranges {
+        writer.message(4, encode_range(range));
+    }
+    for name in &proto_enum.reserved_names {
+        writer.string(5, name);
+    }
+    writer
+}
+
+fn encode_service(service: &ServiceDescriptor) -> WireWriter {
+    let mut writer = WireWriter::new();
+    writer.string(1, &service.name);
+    for method in &service.methods {
+        let mut method_writer = WireWriter::new();
+        method_writer.string(1, &method.name);
+        method_writer.string(2, &method.input_type);
+        method_writer.string(3, &method.output_type);
+        encode_options(&mut method_writer, 4, &method.options, METHOD_OPTIONS);
+        if method.client_streaming {
+            method_writer.bool(5, true);
+        }
+        if method.server_streaming {
+            method_writer.bool(6, true);
+        }
+        writer.message(2, method_writer);
+    }
+    encode_options(&mut writer, 3, &service.options, SERVICE_OPTIONS);
+    writer
+}
+
+fn encode_range((start, end): (i32, i32)) -> WireWriter {
+    let mut writer = WireWriter::new();
+    writer.int32(1, start);
+    writer.int32(2, end);
+    writer
+}
+
+/// Writes the options message as field `number`, unless none of the options are known
+fn encode_options(writer: &mut WireWriter, number: u32, options: &[ProtoOption], known: KnownOptions) {
+    let mut options_writer = WireWriter::new();
+    let mut features = Vec::new();
+
+    for option in options {
+        if let Some(feature) = option.name.strip_prefix("features.") {
+            features.push(ProtoOption { name: feature.to_string(), value: option.value.clone() });
+            continue;
+        }
+
+        let Some(&(option_number, _, kind)) = known.iter().find(|(_, name, _)| *name == option.name) else {
+            continue;
+        };
+
+        match kind {
+            OptionKind::Bool => options_writer.bool(option_number, option.value == "true"),
+            OptionKind::String => options_writer.string(option_number, &unquote(&option.value)),
+            OptionKind::Enum(values) => {
+                if let Some((value, _)) = values.iter().find(|(_, name)| *name == option.value) {
+                    options_writer.int32(option_number, *value);
+                }
+            }
+            OptionKind::Features => (),
+        }
+    }
+
+    let features_number = known.iter().find(|(_, _, kind)| matches!(kind, OptionKind::Features));
+    if let (false, Some(&(features_number, _, _))) = (features.is_empty(), features_number) {
+        encode_options(&mut options_writer, features_number, &features, FEATURES);
+    }
+
+    let encoded = options_writer.finish();
+    if !encoded.is_empty() {
+        writer.bytes(number, &encoded);
+    }
+}
+
+/// Options that can be written to a descriptor, feature options last like they are encoded
+fn known_options(options: &[ProtoOption], known: KnownOptions) -> Vec<ProtoOption> {
+    let is_known = |option: &&ProtoOption| match option.name.strip_prefix("features.") {
+        Some(feature) => known.iter().any(|(_, _, kind)| matches!(kind, OptionKind::Features)) && is_known_value(feature, &option.value, FEATURES),
+        None => is_known_value(&option.name, &option.value, known),
+    };
+
+    let (features, options): (Vec<_>, Vec<_>) = options.iter()
+        .filter(is_known)
+        .cloned()
+        .partition(|option| option.name.starts_with("features."));
+
+    options.into_iter().chain(features).collect()
+}
+
+fn is_known_value(name: &str, value: &str, known: KnownOptions) -> bool {
+    match known.iter().find(|(_, known_name, _)| *known_name == name) {
+        Some((_, _, OptionKind::Bool)) => value == "true" || value == "false",
+        Some((_, _, OptionKind::String)) => value.starts_with(['"', '\'']),
+        Some((_, _, OptionKind::Enum(values))) => values.iter().any(|(_, value_name)| *value_name == value),
+        Some((_, _, OptionKind::Features)) | None => false,
+    }
+}
+
+/// Contents of a string literal as written in `.proto` source
+fn unquote(value: &str) -> String {
+    String::from_utf8_lossy(&unescape(strip_quotes(value))).into_owned()
+}
+
+fn strip_quotes(value: &str) -> &str {
+    value.strip_prefix(['"', '\'']).and_then(|value| value.strip_suffix(['"', '\''])).unwrap_or(value)
+}
+
+/// Bytes of a string literal's contents, with every escape sequence `.proto` source allows
+fn unescape(literal: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut chars = literal.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            bytes.extend(c.encode_utf8(&mut [0; 4]).as_bytes());
+            continue;
+        }
+
+        match chars.peek().copied() {
+            Some('0'..='7') => bytes.push(digits(&mut chars, 8, 3) as u8),
+            Some(c) => {
+                chars.next();
+                match c {
+                    'a' => bytes.push(0x07),
+                    'b' => bytes.push(0x08),
+                    'f' => bytes.push(0x0c),
+                    'n' => bytes.push(b'\n'),
+                    'r' => bytes.push(b'\r'),
+                    't' => bytes.push(b'\t'),
+                    'v' => bytes.push(0x0b),
+                    'x' | 'X' => bytes.push(digits(&mut chars, 16, 2) as u8),
+                    'u' | 'U' => {
+                        let c = char::from_u32(digits(&mut chars, 16, if c == 'u' { 4 } else { 8 })).unwrap_or(char::REPLACEMENT_CHARACTER);
+                        bytes.extend(c.encode_utf8(&mut [0; 4]).as_bytes());
+                    }
+                    c => bytes.extend(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                }
+            }
+            None => (),
+        }
+    }
+    bytes
+}
+
+/// Value of up to `max` digits of the given radix
+fn digits(chars: &mut Peekable<Chars>, radix: u32, max: usize) -> u32 {
+    let mut value = 0;
+    for _ in 0..max {
+        let Some(digit) = chars.peek().and_then(|c| c.to_digit(radix)) else {
+            break;
+        };
+        value = value * radix + digit;
+        chars.next();
+    }
+    value
+}
+
+/// C-escaped bytes, the way descriptors keep `bytes` defaults: anything but printable ASCII in octal
+fn escape(bytes: &[u8]) -> String {
+    let mut escaped = String::new();
+    for &byte in bytes {
+        match byte {
+            b'\n' => escaped.push_str("\\n"),
+            b'\r' => escaped.push_str("\\r"),
+            b'\t' => escaped.push_str("\\t"),
+            b'"' => escaped.push_str("\\\""),
+            b'\'' => escaped.push_str("\\'"),
+            b'\\' => escaped.push_str("\\\\"),
+            b' '..=b'~' => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    escaped
+}
+
+/// `.proto` string literal for a string, characters outside ASCII are written as they are
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c.is_ascii() {
+            true => quoted.push_str(&escape(&[c as u8])),
+            false => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// One `FileDescriptor` per source file of the database, using the current (resolved) names
+pub fn export(proto_db: &ProtoDatabase) -> Vec<FileDescriptor> {
+    let exporter = Exporter { proto_db };
+    (0..proto_db.source_files.len()).map(|file| exporter.file(file)).collect()
+}
+
+struct Exporter<'a> {
+    proto_db: &'a ProtoDatabase,
+}
+
+impl Exporter<'_> {
+    fn file(&self, file: usize) -> FileDescriptor {
+        let db = self.proto_db;
+
+        let (syntax, edition) = match db.syntax_of(file) {
+            ProtoSyntax::Proto2 => ("proto2", None),
+            ProtoSyntax::Proto3 => ("proto3", None),
+            ProtoSyntax::Edition(name) => ("editions", EDITIONS.iter().find(|(_, edition)| *edition == name).map(|(number, _)| *number)),
+        };
+
+        let services = db.service_db.right_values()
+            .filter(|service| service.span.file == file)
+            .sorted_by_key(|service| service.name.name(db))
+            .map(|service| self.service(service))
+            .collect();
+
+        let extensions = db.child_extensions(None).into_iter()
+            .filter(|extension| extension.span.file == file)
+            .collect::<Vec<_>>();
+
+        FileDescriptor {
+            name: db.source_files[file].clone(),
+            package: db.package_of(file).map(str::to_string),
+            dependencies: self.dependencies(file),
+            messages: db.child_messages(None).into_iter().filter(|message| message.span.file == file).map(|message| self.message(message)).collect(),
+            enums: db.child_enums(None).into_iter().filter(|proto_enum| proto_enum.span.file == file).map(|proto_enum| self.proto_enum(proto_enum)).collect(),
+            services,
+            extensions: self.extension_fields(&extensions),
+            options: known_options(db.options_of(DefinitionRef::File(file)), FILE_OPTIONS),
+            syntax: Some(syntax.to_string()),
+            edition,
+        }
+    }
+
+    /// Files defining the types that `file` refers to
+    fn dependencies(&self, file: usize) -> Vec<String> {
+        let db = self.proto_db;
+        let mut references = Vec::new();
+
+        for message in db.message_db.right_values().filter(|message| message.span.file == file) {
+            references.extend(message.fields.iter().flat_map(|field| field.field_type.type_refs()));
+        }
+        for extension in db.extensions.iter().filter(|extension| extension.span.file == file) {
+            references.push(extension.extendee);
+            references.extend(extension.fields.iter().flat_map(|field| field.field_type.type_refs()));
+        }
+        for service in db.service_db.right_values().filter(|service| service.span.file == file) {
+            for rpc in &service.rpcs {
+                references.extend([rpc.request, rpc.response].into_iter().filter_map(|proto_type| match proto_type {
+                    ProtoType::Type(name) => Some(name),
+                    _ => None,
+                }));
+            }
+        }
+
+        references.iter()
+            .filter_map(|name| self.file_of(name))
+            .filter(|&other| other != file)
+            .map(|other| db.source_files[other].clone())
+            .sorted()
+            .dedup()
+            .collect()
+    }
+
+    fn file_of(&self, name: &ProtoName) -> Option<usize> {
+        let db = self.proto_db;
+        db.message_db.get_by_left(name).map(|message| message.span.file)
+            .or_else(|| db.enum_db.get_by_left(name).map(|proto_enum| proto_enum.span.file))
+    }
+
+    /// `.package.Outer.Inner`
+    fn qualified(&self, name: ProtoName) -> String {
+        let db = self.proto_db;
+
+        let mut segments = vec![name.name(db)];
+        let mut parent = db.parent_of(&name);
+        while let Some(name) = parent {
+            segments.push(name.name(db));
+            parent = db.parent_of(&name);
+        }
+        segments.extend(self.file_of(&name).and_then(|file| db.package_of(file)).map(str::to_string));
+
+        format!(".{}", segments.iter().rev().join("."))
+    }
+
+    fn message(&self, message: &ProtoMessage) -> MessageDescriptor {
+        let db = self.proto_db;
+        let mut descriptor = MessageDescriptor {
+            name: message.name.name(db),
+            oneofs: message.oneofs.iter().map(|oneof| oneof.name(db)).collect(),
+            options: known_options(db.options_of(DefinitionRef::Definition(message.name)), MESSAGE_OPTIONS),
+            ..MessageDescriptor::default()
+        };
+
+        for field in &message.fields {
+            let mut exported = self.field(message.name, field);
+
+            if let ProtoFieldKind::Map(key, value) = field.field_type {
+                // Map fields are repeated fields of a synthesized entry message
+                let entry_name = map_entry_name(&exported.name);
+                exported.type_name = Some(format!("{}.{}", self.qualified(message.name), entry_name));
+
+                descriptor.nested.push(MessageDescriptor {
+                    name: entry_name,
+                    fields: vec![self.entry_field("key", 1, &key), self.entry_field("value", 2, &value)],
+                    options: vec![ProtoOption { name: "map_entry".to_string(), value: "true".to_string() }],
+                    ..MessageDescriptor::default()
+                });
+            }
+
+            exported.oneof_index = match field.oneof {
+                Some(oneof) => message.oneofs.iter().position(|&other| other == oneof).map(|index| index as i32),
+                None if exported.proto3_optional => {
+                    // Every proto3 `optional` field gets a synthetic oneof, after the real ones
+                    descriptor.oneofs.push(format!("_{}", exported.name));
+                    Some(descriptor.oneofs.len() as i32 - 1)
+                }
+                None => None,
+            };
+
+            descriptor.fields.push(exported);
+        }
+
+        descriptor.nested.extend(db.child_messages(Some(message.name)).into_iter().map(|nested| self.message(nested)));
+        descriptor.enums = db.child_enums(Some(message.name)).into_iter().map(|proto_enum| self.proto_enum(proto_enum)).collect();
+        descriptor.extensions = self.extension_fields(&db.child_extensions(Some(message.name)));
+        descriptor.extension_ranges = db.extension_ranges.get(&message.name)
+            .map(|ranges| export_ranges(ranges, MESSAGE_RANGE_MAX, 1))
+            .unwrap_or_default();
+        (descriptor.reserved_ranges, descriptor.reserved_names) = self.reserved(message.name, MESSAGE_RANGE_MAX, 1);
+
+        descriptor
+    }
+
+    /// `owner` is the message the field is in, or the extendee for extension fields
+    fn field(&self, owner: ProtoName, field: &ProtoField) -> FieldDescriptor {
+        let db = self.proto_db;
+
+        let (label, (field_type, type_name)) = match &field.field_type {
+            ProtoFieldKind::Scalar(proto_type) if field.label == ProtoLabel::Required => (LABEL_REQUIRED, self.field_type(proto_type)),
+            ProtoFieldKind::Scalar(proto_type) => (LABEL_OPTIONAL, self.field_type(proto_type)),
+            ProtoFieldKind::Repeated(proto_type) => (LABEL_REPEATED, self.field_type(proto_type)),
+            // The entry type is filled in by the message
+            ProtoFieldKind::Map(_, _) => (LABEL_REPEATED, (TYPE_MESSAGE, None)),
+        };
+
+        let options = db.options_of(DefinitionRef::Field(owner, field.field_number));
+        let default_value = options.iter()
+            .find(|option| option.name == "default")
+            .map(|option| match field_type {
+                TYPE_STRING => unquote(&option.value),
+                TYPE_BYTES => escape(&unescape(strip_quotes(&option.value))),
+                TYPE_ENUM => db.translate_name(&option.value).unwrap_or_else(|| option.value.clone()),
+                _ => option.value.clone(),
+            });
+
+        FieldDescriptor {
+            name: field.name.name(db),
+            extendee: None,
+            number: field.field_number as i32,
+            label,
+            field_type,
+            type_name,
+            default_value,
+            oneof_index: None,
+            proto3_optional: field.label == ProtoLabel::Optional && db.syntax_of(field.span.file) == ProtoSyntax::Proto3,
+            options: known_options(options, FIELD_OPTIONS),
+        }
+    }
+
+    fn entry_field(&self, name: &str, number: i32, proto_type: &ProtoType) -> FieldDescriptor {
+        let (field_type, type_name) = self.field_type(proto_type);
+        FieldDescriptor {
+            name: name.to_string(),
+            number,
+            label: LABEL_OPTIONAL,
+            field_type,
+            type_name,
+            ..FieldDescriptor::default()
+        }
+    }
+
+    fn field_type(&self, proto_type: &ProtoType) -> (i32, Option<String>) {
+        let field_type = match proto_type {
+            ProtoType::Double => TYPE_DOUBLE,
+            ProtoType::Float => TYPE_FLOAT,
+            ProtoType::Int64 => TYPE_INT64,
+            ProtoType::Uint64 => TYPE_UINT64,
+            ProtoType::Int32 => TYPE_INT32,
+            ProtoType::Fixed64 => TYPE_FIXED64,
+            ProtoType::Fixed32 => TYPE_FIXED32,
+            ProtoType::Bool => TYPE_BOOL,
+            ProtoType::String => TYPE_STRING,
+            ProtoType::Bytes => TYPE_BYTES,
+            ProtoType::Uint32 => TYPE_UINT32,
+            ProtoType::Sfixed32 => TYPE_SFIXED32,
+            ProtoType::Sfixed64 => TYPE_SFIXED64,
+            ProtoType::Sint32 => TYPE_SINT32,
+            ProtoType::Sint64 => TYPE_SINT64,
+            // Undefined types are assumed to be messages
+            ProtoType::Type(name) if self.proto_db.enum_db.contains_left(name) => return (TYPE_ENUM, Some(self.qualified(*name))),
+            ProtoType::Type(name) => return (TYPE_MESSAGE, Some(self.qualified(*name))),
+        };
+
+        (field_type, None)
+    }
+
+    fn extension_fields(&self, extensions: &[&ProtoExtension]) -> Vec<FieldDescriptor> {
+        extensions.iter()
+            .flat_map(|extension| extension.fields.iter().map(|field| FieldDescriptor {
+                extendee: Some(self.qualified(extension.extendee)),
+                ..self.field(extension.extendee, field)
+            }))
+            .collect()
+    }
+
+    fn proto_enum(&self, proto_enum: &ProtoEnum) -> EnumDescriptor {
+        let db = self.proto_db;
+
+        let values = proto_enum.values.iter()
+            .map(|value| {
+                let options = known_options(db.options_of(DefinitionRef::EnumValue(proto_enum.name, value.number)), ENUM_VALUE_OPTIONS);
+                (value.name.name(db), value.number, options)
+            })
+            .collect();
+
+        let (reserved_ranges, reserved_names) = self.reserved(proto_enum.name, i32::MAX, 0);
+
+        EnumDescriptor {
+            name: proto_enum.name.name(db),
+            values,
+            reserved_ranges,
+            reserved_names,
+            options: known_options(db.options_of(DefinitionRef::Definition(proto_enum.name)), ENUM_OPTIONS),
+        }
+    }
+
+    /// `end_offset` is 1 for message ranges, whose end is exclusive
+    fn reserved(&self, name: ProtoName, max: i32, end_offset: i32) -> (Vec<(i32, i32)>, Vec<String>) {
+        let Some(reserved) = self.proto_db.reserved.get(&name) else {
+            return (Vec::new(), Vec::new());
+        };
+
+        (export_ranges(&reserved.ranges, max, end_offset), reserved.names.clone())
+    }
+
+    fn service(&self, service: &ProtoService) -> ServiceDescriptor {
+        let db = self.proto_db;
+
+        let type_name = |proto_type: &ProtoType| match proto_type {
+            ProtoType::Type(name) => self.qualified(*name),
+            _ => proto_type.type_name(db),
+        };
+
+        let methods = service.rpcs.iter()
+            .map(|rpc| MethodDescriptor {
+                name: rpc.name.name(db),
+                input_type: type_name(&rpc.request),
+                output_type: type_name(&rpc.response),
+                client_streaming: rpc.request_stream,
+                server_streaming: rpc.response_stream,
+                options: known_options(db.options_of(DefinitionRef::Rpc(service.name, rpc.name)), METHOD_OPTIONS),
+            })
+            .collect();
+
+        ServiceDescriptor {
+            name: service.name.name(db),
+            methods,
+            options: known_options(db.options_of(DefinitionRef::Definition(service.name)), SERVICE_OPTIONS),
+        }
+    }
+}
+
+/// Name protoc gives the entry message of a map field, `item_counts` becomes `ItemCountsEntry`
+fn map_entry_name(field_name: &str) -> String {
+    let mut name = String::new();
+    let mut upper = true;
+    for c in field_name.chars() {
+        match c {
+            '_' => upper = true,
+            _ if upper => {
+                name.extend(c.to_uppercase());
+                upper = false;
+            }
+            _ => name.push(c),
+        }
+    }
+    name + "Entry"
+}
+
+/// Inclusive ranges in the form descriptors store them, `max` stands for `to max`
+fn export_ranges(ranges: &[(i64, i64)], max: i32, end_offset: i32) -> Vec<(i32, i32)> {
+    ranges.iter()
+        .map(|&(start, end)| (start as i32, if end == RESERVED_MAX { max } else { end as i32 + end_offset }))
+        .collect()
+}
+
+fn import_ranges(ranges: &[(i32, i32)], max: i32, end_offset: i32) -> Vec<(i64, i64)> {
+    ranges.iter()
+        .map(|&(start, end)| (start as i64, if end == max { RESERVED_MAX } else { (end - end_offset) as i64 }))
+        .collect()
+}
+
+/// Registers the definitions of a decoded file, returns the top-level ones
+pub fn register_file(proto_db: &mut ProtoDatabase, file: &FileDescriptor) -> Vec<ProtoName> {
+    let file_index = proto_db.register_source_file(&file.name);
+
+    let syntax = match (file.syntax.as_deref(), file.edition) {
+        (Some("editions"), Some(edition)) => {
+            let name = EDITIONS.iter().find(|(number, _)| *number == edition).map_or(edition.to_string(), |(_, name)| name.to_string());
+            Some(ProtoSyntax::Edition(name))
+        }
+        (Some(syntax), _) => ProtoSyntax::from_syntax(syntax),
+        (None, _) => None,
+    };
+    proto_db.register_syntax(file_index, syntax.clone().unwrap_or_default(), syntax.is_some());
+
+    if let Some(package) = &file.package {
+        proto_db.register_package(file_index, package.clone());
+    }
+    proto_db.register_options(DefinitionRef::File(file_index), file.options.clone());
+
+    let mut registrar = Registrar {
+        proto_db,
+        span: Span { file: file_index, ..Span::default() },
+        syntax: syntax.unwrap_or_default(),
+    };
+
+    let mut definitions = Vec::new();
+    for message in &file.messages {
+        definitions.extend(registrar.message(message, None));
+    }
+    for proto_enum in &file.enums {
+        definitions.extend(registrar.proto_enum(proto_enum, None));
+    }
+    for service in &file.services {
+        definitions.extend(registrar.service(service));
+    }
+    registrar.extensions(&file.extensions, None);
+
+    definitions
+}
+
+struct Registrar<'a> {
+    proto_db: &'a mut ProtoDatabase,
+    /// Descriptors carry no source positions, everything points at the start of the file
+    span: Span,
+    syntax: ProtoSyntax,
+}
+
+impl Registrar<'_> {
+    fn name(&mut self, text: &str) -> ProtoName {
+        self.proto_db.register_identifier(text.to_string());
+        self.proto_db.lookup_name_by_text(text)
+    }
+
+    /// Name of a message, enum or service about to be registered, `None` when one with the name is defined already.
+    /// Nested types are keyed by their name alone, so the first definition is kept
+    fn defined_name(&mut self, text: &str) -> Option<ProtoName> {
+        let name = self.name(text);
+        if let Some(previous) = self.proto_db.span_of(DefinitionRef::Definition(name)) {
+            let files = &self.proto_db.source_files;
+            warn!("{} is defined already in {}, the definition in {} is skipped", text, files[previous.file], files[self.span.file]);
+            return None;
+        }
+        Some(name)
+    }
+
+    /// Identifiers are registered without their scope, so qualified references resolve by their last segment
+    fn type_name(&mut self, qualified: &str) -> ProtoName {
+        self.name(qualified.rsplit('.').next().unwrap_or(qualified))
+    }
+
+    fn message(&mut self, message: &MessageDescriptor, parent: Option<ProtoName>) -> Option<ProtoName> {
+        let name = self.defined_name(&message.name)?;
+
+        // Map entries become map fields instead of nested messages
+        let map_entries = message.nested.iter()
+            .filter(|nested| nested.is_map_entry())
+            .map(|nested| (nested.name.as_str(), nested))
+            .collect::<HashMap<_, _>>();
+
+        // proto3 `optional` fields are wrapped in a synthetic oneof each
+        let synthetic_oneofs = message.fields.iter()
+            .filter(|field| field.proto3_optional)
+            .filter_map(|field| field.oneof_index)
+            .collect::<Vec<_>>();
+
+        let oneofs = message.oneofs.iter().enumerate()
+            .map(|(index, oneof)| (!synthetic_oneofs.contains(&(index as i32))).then(|| self.name(oneof)))
+            .collect::<Vec<_>>();
+
+        let mut fields = Vec::new();
+        for field in &message.fields {
+            let entry = field.type_name.as_deref()
+                .and_then(|type_name| type_name.rsplit('.').next())
+                .and_then(|entry_name| map_entries.get(entry_name));
+
+            let mut parsed = match entry {
+                Some(entry) if field.label == LABEL_REPEATED => {
+                    let key = entry.fields.iter().find(|entry_field| entry_field.number == 1);
+                    let value = entry.fields.iter().find(|entry_field| entry_field.number == 2);
+
+                    let (Some(key), Some(value)) = (key, value) else {
+                        continue;
+                    };
+
+                    let mut parsed = self.field(name, field);
+                    parsed.field_type = ProtoFieldKind::Map(self.field_type(key), self.field_type(value));
+                    parsed
+                }
+                _ => self.field(name, field),
+            };
+
+            parsed.oneof = field.oneof_index.and_then(|index| oneofs.get(index as usize).copied().flatten());
+            // Oneof members are labeled optional in proto2 descriptors, but can't be written with a label
+            if parsed.oneof.is_some() {
+                parsed.label = ProtoLabel::None;
+            }
+            fields.push(parsed);
+        }
+
+        self.proto_db.register_options(DefinitionRef::Definition(name), message.options.iter().filter(|option| option.name != "map_entry").cloned().collect());
+        self.reserved(name, &message.reserved_ranges, &message.reserved_names, MESSAGE_RANGE_MAX, 1);
+        if !message.extension_ranges.is_empty() {
+            self.proto_db.extension_ranges.insert(name, import_ranges(&message.extension_ranges, MESSAGE_RANGE_MAX, 1));
+        }
+
+        self.proto_db.register_message(ProtoMessage {
+            name,
+            fields,
+            oneofs: oneofs.into_iter().flatten().collect(),
+            parent,
+            span: self.span,
+        });
+
+        for nested in message.nested.iter().filter(|nested| !nested.is_map_entry()) {
+            self.message(nested, Some(name));
+        }
+        for proto_enum in &message.enums {
+            self.proto_enum(proto_enum, Some(name));
+        }
+        self.extensions(&message.extensions, Some(name));
+
+        Some(name)
+    }
+
+    /// `owner` is the message the field is in, or the extendee for extension fields
+    fn field(&mut self, owner: ProtoName, field: &FieldDescriptor) -> ProtoField {
+        let field_type = self.field_type(field);
+
+        let label = match field.label {
+            _ if field.proto3_optional => ProtoLabel::Optional,
+            LABEL_REQUIRED => ProtoLabel::Required,
+            // Singular fields are labeled optional in every syntax, but only proto2 writes it out
+            LABEL_OPTIONAL if self.syntax == ProtoSyntax::Proto2 => ProtoLabel::Optional,
+            _ => ProtoLabel::None,
+        };
+
+        let mut options = field.options.clone();
+        if let Some(default_value) = &field.default_value {
+            let value = match field.field_type {
+                TYPE_STRING => quote(default_value),
+                TYPE_BYTES => format!("\"{}\"", escape(&unescape(default_value))),
+                _ => default_value.clone(),
+            };
+            options.insert(0, ProtoOption { name: "default".to_string(), value });
+        }
+        self.proto_db.register_options(DefinitionRef::Field(owner, field.number as u32), options);
+
+        ProtoField {
+            name: self.name(&field.name),
+            label,
+            field_type: match field.label {
+                LABEL_REPEATED => ProtoFieldKind::Repeated(field_type),
+                _ => ProtoFieldKind::Scalar(field_type),
+            },
+            field_number: field.number as u32,
+            oneof: None,
+            span: self.span,
+        }
+    }
+
+    fn field_type(&mut self, field: &FieldDescriptor) -> ProtoType {
+        match field.field_type {
+            TYPE_DOUBLE => ProtoType::Double,
+            TYPE_FLOAT => ProtoType::Float,
+            TYPE_INT64 => ProtoType::Int64,
+            TYPE_UINT64 => ProtoType::Uint64,
+            TYPE_INT32 => ProtoType::Int32,
+            TYPE_FIXED64 => ProtoType::Fixed64,
+            TYPE_FIXED32 => ProtoType::Fixed32,
+            TYPE_BOOL => ProtoType::Bool,
+            TYPE_STRING => ProtoType::String,
+            TYPE_BYTES => ProtoType::Bytes,
+            TYPE_UINT32 => ProtoType::Uint32,
+            TYPE_SFIXED32 => ProtoType::Sfixed32,
+            TYPE_SFIXED64 => ProtoType::Sfixed64,
+            TYPE_SINT32 => ProtoType::Sint32,
+            TYPE_SINT64 => ProtoType::Sint64,
+            // Messages, enums, and groups as the message type they define
+            _ => ProtoType::Type(self.type_name(field.type_name.as_deref().unwrap_or_default())),
+        }
+    }
+
+    fn proto_enum(&mut self, proto_enum: &EnumDescriptor, parent: Option<ProtoName>) -> Option<ProtoName> {
+        let name = self.defined_name(&proto_enum.name)?;
+
+        let mut values = Vec::new();
+        for (value_name, number, options) in &proto_enum.values {
+            self.proto_db.register_options(DefinitionRef::EnumValue(name, *number), options.clone());
+            values.push(ProtoEnumValue {
+                name: self.name(value_name),
+                number: *number,
+                span: self.span,
+            });
+        }
+
+        self.proto_db.register_options(DefinitionRef::Definition(name), proto_enum.options.clone());
+        self.reserved(name, &proto_enum.reserved_ranges, &proto_enum.reserved_names, i32::MAX, 0);
+
+        self.proto_db.register_enum(ProtoEnum {
+            name,
+            values,
+            parent,
+            span: self.span,
+        });
+
+        Some(name)
+    }
+
+    /// `end_offset` is 1 for message ranges, whose end is exclusive
+    fn reserved(&mut self, name: ProtoName, ranges: &[(i32, i32)], names: &[String], max: i32, end_offset: i32) {
+        if ranges.is_empty() && names.is_empty() {
+            return;
+        }
+
+        self.proto_db.reserved.insert(name, ProtoReserved { ranges: import_ranges(ranges, max, end_offset), names: names.to_vec() });
+    }
+
+    fn service(&mut self, service: &ServiceDescriptor) -> Option<ProtoName> {
+        let name = self.defined_name(&service.name)?;
+
+        let mut rpcs = Vec::new();
+        for method in &service.methods {
+            let rpc_name = self.name(&method.name);
+            self.proto_db.register_options(DefinitionRef::Rpc(name, rpc_name), method.options.clone());
+
+            rpcs.push(ProtoRpc {
+                name: rpc_name,
+                request: ProtoType::Type(self.type_name(&method.input_type)),
+                request_stream: method.client_streaming,
+                response: ProtoType::Type(self.type_name(&method.output_type)),
+                response_stream: method.server_streaming,
+                span: self.span,
+            });
+        }
+
+        self.proto_db.register_options(DefinitionRef::Definition(name), service.options.clone());
+        self.proto_db.register_service(ProtoService {
+            name,
+            rpcs,
+            span: self.span,
+        });
+
+        Some(name)
+    }
+
+    /// Extension fields grouped into one `extend` block per extendee, in order of appearance
+    fn extensions(&mut self, fields: &[FieldDescriptor], parent: Option<ProtoName>) {
+        let mut extensions: Vec<ProtoExtension> = Vec::new();
+
+        for field in fields {
+            let extendee = self.type_name(field.extendee.as_deref().unwrap_or_default());
+            let parsed = self.field(extendee, field);
+
+            match extensions.iter_mut().find(|extension| extension.extendee == extendee) {
+                Some(extension) => extension.fields.push(parsed),
+                None => extensions.push(ProtoExtension {
+                    extendee,
+                    fields: vec![parsed],
+                    parent,
+                    span: self.span,
+                }),
+            }
+        }
+
+        self.proto_db.extensions.extend(extensions);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emit::ProtoEmitter;
+    use crate::util::parse_test_proto;
+
+    fn varint(out: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            out.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn message(fields: &[(u32, Vec<u8>)]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (number, bytes) in fields {
+            varint(&mut out, (*number as u64) << 3 | 2);
+            varint(&mut out, bytes.len() as u64);
+            out.extend(bytes);
+        }
+        out
+    }
+
+    fn with_varints(mut bytes: Vec<u8>, fields: &[(u32, u64)]) -> Vec<u8> {
+        for (number, value) in fields {
+            varint(&mut bytes, (*number as u64) << 3);
+            varint(&mut bytes, *value);
+        }
+        bytes
+    }
+
+    fn text(value: &str) -> Vec<u8> {
+        value.as_bytes().to_vec()
+    }
+
+    #[test]
+    fn test_register_map_fields() {
+        let entry = message(&[
+            (1, text("ValuesEntry")),
+            (2, with_varints(message(&[(1, text("key"))]), &[(3, 1), (4, 1), (5, TYPE_STRING as u64)])),
+            (2, with_varints(message(&[(1, text("value"))]), &[(3, 2), (4, 1), (5, TYPE_UINT32 as u64)])),
+            (7, with_varints(Vec::new(), &[(7, 1)])),
+        ]);
+        let values = with_varints(message(&[(1, text("values")), (6, text(".pkg.Foo.ValuesEntry"))]), &[(3, 2), (4, 3), (5, 11)]);
+        let id = with_varints(message(&[(1, text("id")), (8, with_varints(Vec::new(), &[(3, 1)]))]), &[(3, 1), (4, 1), (5, TYPE_INT32 as u64), (9, 0), (17, 1)]);
+
+        let foo = message(&[(1, text("Foo")), (2, id), (2, values), (3, entry), (8, message(&[(1, text("_id"))]))]);
+        let file = message(&[(1, text("foo.proto")), (2, text("pkg")), (4, foo), (12, text("proto3"))]);
+        let set = message(&[(1, file)]);
+
+        let files = decode_descriptor_set(&set).unwrap();
+        let mut proto_db = ProtoDatabase::new();
+        let definitions = register_file(&mut proto_db, &files[0]);
+
+        assert_eq!(definitions.len(), 1);
+        assert_eq!(proto_db.message_db.len(), 1);
+        assert_eq!(proto_db.package.as_deref(), Some("pkg"));
+        assert_eq!(proto_db.syntax, Some(ProtoSyntax::Proto3));
+
+        let foo = proto_db.get_message("Foo").unwrap();
+        assert!(foo.oneofs.is_empty());
+        assert_eq!(foo.fields[0].label, ProtoLabel::Optional);
+        assert_eq!(foo.fields[0].oneof, None);
+        assert_eq!(proto_db.options_of(DefinitionRef::Field(foo.name, 1)), [ProtoOption { name: "deprecated".to_string(), value: "true".to_string() }]);
+        assert_eq!(foo.fields[1].field_type, ProtoFieldKind::Map(ProtoType::String, ProtoType::Uint32));
+    }
+
+    fn option(name: &str, value: &str) -> ProtoOption {
+        ProtoOption { name: name.to_string(), value: value.to_string() }
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        let proto_db = parse_test_proto(r#"
+            syntax = "proto2";
+            package game;
+
+            option java_package = "com.game";
+            option (custom) = 1;
+
+            message Player {
+                optional uint32 level = 1 [deprecated = true];
+                map<string, Item> items = 2;
+                oneof mode {
+                    Status status = 3;
+                    string nickname = 4;
+                }
+                optional string motto = 5 [default = "say \"hi\"\n\001"];
+                optional bytes seed = 6 [default = "\xff\0"];
+                reserved 20 to max;
+                extensions 10 to 19;
+
+                enum Status {
+                    ACTIVE = 0;
+                }
+            }
+
+            message Item {
+            }
+
+            service Shop {
+                rpc Buy (Item) returns (stream Player);
+            }
+
+            extend Item {
+                repeated int32 tags = 100;
+            }
+        "#);
+
+        let files = export(&proto_db);
+        assert_eq!(decode_descriptor_set(&encode_descriptor_set(&files)).unwrap(), files);
+
+        let exported = &files[0];
+        assert_eq!(exported.options, [option("java_package", "\"com.game\"")]);
+        assert_eq!(exported.extensions[0].extendee.as_deref(), Some(".game.Item"));
+        assert_eq!(exported.services[0].methods[0].output_type, ".game.Player");
+
+        let exported_player = &exported.messages[1];
+        assert_eq!(exported_player.oneofs, ["mode"]);
+        assert_eq!(exported_player.fields[1].type_name.as_deref(), Some(".game.Player.ItemsEntry"));
+        assert_eq!(exported_player.fields[2].type_name.as_deref(), Some(".game.Player.Status"));
+        assert_eq!(exported_player.fields[2].field_type, TYPE_ENUM);
+        assert_eq!(exported_player.fields[4].default_value.as_deref(), Some("say \"hi\"\n\u{1}"));
+        assert_eq!(exported_player.fields[5].default_value.as_deref(), Some("\\377\\000"));
+        assert_eq!(exported_player.nested[0].name, "ItemsEntry");
+        assert_eq!(exported_player.reserved_ranges, [(20, MESSAGE_RANGE_MAX)]);
+        assert_eq!(exported_player.extension_ranges, [(10, 20)]);
+
+        // Loading the export back and writing it out as source gives the same schema
+        let mut loaded = ProtoDatabase::new();
+        register_file(&mut loaded, exported);
+        let player = ProtoName::lookup(&loaded, "Player");
+        assert_eq!(loaded.default_value(player, &loaded.get_message("Player").unwrap().fields[4]), Some(r#""say \"hi\"\n\001""#));
+
+        let mut emitter = ProtoEmitter::new(&loaded);
+        emitter.emit_header();
+        emitter.emit_package(loaded.package.as_deref().unwrap());
+        emitter.emit_options(&loaded.file_options());
+        emitter.emit_definitions(&loaded.top_level_definitions());
+        emitter.emit_extensions(&loaded.child_extensions(None));
+        assert_eq!(export(&parse_test_proto(&emitter.finish())), files);
+    }
+
+    #[test]
+    fn test_export_packages() {
+        let mut proto_db = ProtoDatabase::new();
+        crate::parser::parse_proto_into(&mut proto_db, "
+            package common;
+
+            message Item {
+            }
+        ", "common.proto").unwrap();
+        crate::parser::parse_proto_into(&mut proto_db, "
+            package game;
+
+            message Player {
+                optional Item item = 1;
+            }
+        ", "game.proto").unwrap();
+
+        let files = export(&proto_db);
+        assert_eq!(files.iter().map(|file| file.package.as_deref()).collect::<Vec<_>>(), [Some("common"), Some("game")]);
+        assert_eq!(files[1].messages[0].fields[0].type_name.as_deref(), Some(".common.Item"));
+        assert_eq!(files[1].dependencies, ["common.proto"]);
+    }
+
+    #[test]
+    fn test_export_proto3_optional() {
+        let proto_db = parse_test_proto("
+            syntax = \"proto3\";
+
+            message Player {
+                optional uint32 level = 1;
+            }
+        ");
+
+        let exported_player = &export(&proto_db)[0].messages[0];
+        assert_eq!(exported_player.oneofs, ["_level"]);
+        assert_eq!(exported_player.fields[0].oneof_index, Some(0));
+        assert!(exported_player.fields[0].proto3_optional);
+    }
+}
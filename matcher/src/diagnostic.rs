@@ -0,0 +1,73 @@
+use std::fmt;
+
+use tree_sitter::Node;
+
+use crate::util::RawBuffer;
+
+/// A problem in an input file, pointing at the offending source line
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub file: String,
+    /// 1-based
+    pub line: usize,
+    /// 1-based, in characters
+    pub column: usize,
+    /// The whole source line the problem is on
+    pub snippet: String,
+    pub message: String,
+    /// The construct the parser was looking for at this position
+    pub expected: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn at(node: &Node, buffer: &RawBuffer, file: &str, message: String, expected: Option<String>) -> Self {
+        let rope = &buffer.rope;
+        let start_char = rope.byte_to_char(node.start_byte());
+        let line = rope.char_to_line(start_char);
+
+        Self {
+            file: file.to_string(),
+            line: line + 1,
+            column: start_char - rope.line_to_char(line) + 1,
+            snippet: rope.line(line).to_string().trim_end().to_string(),
+            message,
+            expected,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.file, self.line, self.column, self.message)?;
+        if let Some(expected) = &self.expected {
+            write!(f, ", expected {}", expected)?;
+        }
+
+        // Tabs keep their width so the caret lines up
+        let padding = self.snippet.chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+
+        write!(f, "\n    {}\n    {}^", self.snippet, padding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let diagnostic = Diagnostic {
+            file: "test.proto".to_string(),
+            line: 3,
+            column: 12,
+            snippet: "\tuint32 foo 4;".to_string(),
+            message: "incomplete field".to_string(),
+            expected: Some("`=`".to_string()),
+        };
+
+        assert_eq!(diagnostic.to_string(), "test.proto:3:12: incomplete field, expected `=`\n    \tuint32 foo 4;\n    \t          ^");
+    }
+}
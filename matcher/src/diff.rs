@@ -0,0 +1,481 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use itertools::Itertools;
+use serde_json::{json, Value};
+
+use crate::prototype::{FieldPresence, ProtoDatabase, ProtoEnum, ProtoField, ProtoMessage, ProtoName};
+
+/// A single difference between two versions of a schema, named as in the newer one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    AddedMessage(String),
+    RemovedMessage(String),
+    /// Same fields, but declared under another name
+    RenamedMessage { from: String, to: String },
+    /// Message or enum of b the matcher found no name for
+    UnresolvedType(String),
+    /// Parent message or file changed
+    MovedType { name: String, from: String, to: String },
+    AddedEnum(String),
+    RemovedEnum(String),
+    AddedField { message: String, field: String, number: u32, type_name: String },
+    RemovedField { message: String, field: String, number: u32, type_name: String },
+    /// Same number, but declared under another name
+    RenamedField { message: String, from: String, to: String, number: u32 },
+    /// Field of b the matcher found no name for
+    UnresolvedField { message: String, field: String, number: u32, type_name: String },
+    FieldTypeChanged { message: String, field: String, from: String, to: String },
+    FieldNumberChanged { message: String, field: String, from: u32, to: u32 },
+    /// Presence as the label spells it, `required`, `optional` or `implicit`
+    LabelChanged { message: String, field: String, from: String, to: String },
+    AddedEnumValue { proto_enum: String, value: String, number: i32 },
+    RemovedEnumValue { proto_enum: String, value: String, number: i32 },
+    RenamedEnumValue { proto_enum: String, from: String, to: String, number: i32 },
+    EnumValueNumberChanged { proto_enum: String, value: String, from: i32, to: i32 },
+    /// Value of b with an obfuscated name, the matcher does not resolve enum values
+    UnresolvedEnumValue { proto_enum: String, value: String, number: i32 },
+}
+
+impl Change {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Change::AddedMessage(_) => "added_message",
+            Change::RemovedMessage(_) => "removed_message",
+            Change::RenamedMessage { .. } => "renamed_message",
+            Change::UnresolvedType(_) => "unresolved_type",
+            Change::MovedType { .. } => "moved_type",
+            Change::AddedEnum(_) => "added_enum",
+            Change::RemovedEnum(_) => "removed_enum",
+            Change::AddedField { .. } => "added_field",
+            Change::RemovedField { .. } => "removed_field",
+            Change::RenamedField { .. } => "renamed_field",
+            Change::UnresolvedField { .. } => "unresolved_field",
+            Change::FieldTypeChanged { .. } => "field_type_changed",
+            Change::FieldNumberChanged { .. } => "field_number_changed",
+            Change::LabelChanged { .. } => "label_changed",
+            Change::AddedEnumValue { .. } => "added_enum_value",
+            Change::RemovedEnumValue { .. } => "removed_enum_value",
+            Change::RenamedEnumValue { .. } => "renamed_enum_value",
+            Change::EnumValueNumberChanged { .. } => "enum_value_number_changed",
+            Change::UnresolvedEnumValue { .. } => "unresolved_enum_value",
+        }
+    }
+
+    /// Message or enum the change is in, or the type itself
+    pub fn scope(&self) -> &str {
+        match self {
+            Change::AddedMessage(name) | Change::RemovedMessage(name) | Change::UnresolvedType(name) | Change::AddedEnum(name) | Change::RemovedEnum(name) => name,
+            Change::RenamedMessage { to, .. } => to,
+            Change::MovedType { name, .. } => name,
+            Change::AddedField { message, .. }
+            | Change::RemovedField { message, .. }
+            | Change::RenamedField { message, .. }
+            | Change::UnresolvedField { message, .. }
+            | Change::FieldTypeChanged { message, .. }
+            | Change::FieldNumberChanged { message, .. }
+            | Change::LabelChanged { message, .. } => message,
+            Change::AddedEnumValue { proto_enum, .. }
+            | Change::RemovedEnumValue { proto_enum, .. }
+            | Change::RenamedEnumValue { proto_enum, .. }
+            | Change::EnumValueNumberChanged { proto_enum, .. }
+            | Change::UnresolvedEnumValue { proto_enum, .. } => proto_enum,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut value = match self {
+            Change::AddedMessage(name) | Change::RemovedMessage(name) => json!({ "message": name }),
+            Change::UnresolvedType(name) => json!({ "name": name }),
+            Change::AddedEnum(name) | Change::RemovedEnum(name) => json!({ "enum": name }),
+            Change::RenamedMessage { from, to } => json!({ "from": from, "to": to }),
+            Change::MovedType { name, from, to } => json!({ "name": name, "from": from, "to": to }),
+            Change::AddedField { message, field, number, type_name }
+            | Change::RemovedField { message, field, number, type_name }
+            | Change::UnresolvedField { message, field, number, type_name } => json!({ "message": message, "field": field, "number": number, "type": type_name }),
+            Change::RenamedField { message, from, to, number } => json!({ "message": message, "from": from, "to": to, "number": number }),
+            Change::FieldTypeChanged { message, field, from, to } | Change::LabelChanged { message, field, from, to } => json!({ "message": message, "field": field, "from": from, "to": to }),
+            Change::FieldNumberChanged { message, field, from, to } => json!({ "message": message, "field": field, "from": from, "to": to }),
+            Change::AddedEnumValue { proto_enum, value, number }
+            | Change::RemovedEnumValue { proto_enum, value, number }
+            | Change::UnresolvedEnumValue { proto_enum, value, number } => json!({ "enum": proto_enum, "value": value, "number": number }),
+            Change::RenamedEnumValue { proto_enum, from, to, number } => json!({ "enum": proto_enum, "from": from, "to": to, "number": number }),
+            Change::EnumValueNumberChanged { proto_enum, value, from, to } => json!({ "enum": proto_enum, "value": value, "from": from, "to": to }),
+        };
+
+        value["kind"] = json!(self.kind());
+        value
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::AddedMessage(name) => write!(f, "+ message {}", name),
+            Change::RemovedMessage(name) => write!(f, "- message {}", name),
+            Change::RenamedMessage { from, to } => write!(f, "~ message {} renamed to {}", from, to),
+            Change::UnresolvedType(name) => write!(f, "? {} is unresolved", name),
+            Change::MovedType { name, from, to } => write!(f, "~ {} moved from {} to {}", name, from, to),
+            Change::AddedEnum(name) => write!(f, "+ enum {}", name),
+            Change::RemovedEnum(name) => write!(f, "- enum {}", name),
+            Change::AddedField { message, field, number, type_name } => write!(f, "+ {}.{}: {} = {}", message, field, type_name, number),
+            Change::RemovedField { message, field, number, type_name } => write!(f, "- {}.{}: {} = {}", message, field, type_name, number),
+            Change::RenamedField { message, from, to, number } => write!(f, "~ {}.{} renamed to {} (= {})", message, from, to, number),
+            Change::UnresolvedField { message, field, number, type_name } => write!(f, "? {}.{}: {} = {} is unresolved", message, field, type_name, number),
+            Change::FieldTypeChanged { message, field, from, to } => write!(f, "~ {}.{}: type {} -> {}", message, field, from, to),
+            Change::FieldNumberChanged { message, field, from, to } => write!(f, "~ {}.{}: number {} -> {}", message, field, from, to),
+            Change::LabelChanged { message, field, from, to } => write!(f, "~ {}.{}: {} -> {}", message, field, from, to),
+            Change::AddedEnumValue { proto_enum, value, number } => write!(f, "+ {}.{} = {}", proto_enum, value, number),
+            Change::RemovedEnumValue { proto_enum, value, number } => write!(f, "- {}.{} = {}", proto_enum, value, number),
+            Change::RenamedEnumValue { proto_enum, from, to, number } => write!(f, "~ {}.{} renamed to {} (= {})", proto_enum, from, to, number),
+            Change::EnumValueNumberChanged { proto_enum, value, from, to } => write!(f, "~ {}.{}: number {} -> {}", proto_enum, value, from, to),
+            Change::UnresolvedEnumValue { proto_enum, value, number } => write!(f, "? {}.{} = {} is unresolved", proto_enum, value, number),
+        }
+    }
+}
+
+/// Differences between an older schema and a newer one, after the newer one was matched against it
+pub struct SchemaDiff {
+    pub changes: Vec<Change>,
+}
+
+impl SchemaDiff {
+    pub fn new(proto_db_a: &ProtoDatabase, proto_db_b: &ProtoDatabase) -> Self {
+        let mut differ = Differ { proto_db_a, proto_db_b, changes: Vec::new() };
+        differ.messages();
+        differ.enums();
+
+        Self { changes: differ.changes }
+    }
+
+    pub fn to_text(&self) -> String {
+        self.changes.iter().map(|change| format!("{}\n", change)).collect()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.changes.iter().map(Change::to_json).collect::<Vec<_>>()).unwrap()
+    }
+}
+
+struct Differ<'a> {
+    proto_db_a: &'a ProtoDatabase,
+    proto_db_b: &'a ProtoDatabase,
+    changes: Vec<Change>,
+}
+
+impl Differ<'_> {
+    fn messages(&mut self) {
+        let messages_a = self.proto_db_a.message_db.right_values().sorted_by_key(|message| message.name.name(self.proto_db_a)).collect::<Vec<_>>();
+        let messages_b = self.proto_db_b.message_db.right_values().sorted_by_key(|message| message.name.name(self.proto_db_b)).collect::<Vec<_>>();
+
+        let names_b = messages_b.iter().map(|message| message.name.name(self.proto_db_b)).collect::<HashSet<_>>();
+        let names_a = messages_a.iter().map(|message| message.name.name(self.proto_db_a)).collect::<HashSet<_>>();
+
+        let mut removed = messages_a.iter().filter(|message| !names_b.contains(&message.name.name(self.proto_db_a))).copied().collect::<Vec<_>>();
+        let mut added = Vec::new();
+
+        for message_b in &messages_b {
+            let name = message_b.name.name(self.proto_db_b);
+            if !self.proto_db_b.is_resolved(&message_b.name) {
+                self.changes.push(Change::UnresolvedType(name));
+            } else if !names_a.contains(&name) {
+                added.push(*message_b);
+            }
+        }
+
+        // A message only counts as renamed when its layout is unique on both sides
+        let layouts_removed = removed.iter().map(|message| self.layout(self.proto_db_a, message)).collect::<Vec<_>>();
+        let layouts_added = added.iter().map(|message| self.layout(self.proto_db_b, message)).collect::<Vec<_>>();
+        let mut renamed = Vec::new();
+        for (message_a, layout) in removed.iter().zip(&layouts_removed) {
+            if layout.is_empty() || layouts_removed.iter().filter(|other| *other == layout).count() != 1 {
+                continue;
+            }
+
+            let mut candidates = added.iter().zip(&layouts_added).filter(|(_, other)| *other == layout);
+            if let (Some((message_b, _)), None) = (candidates.next(), candidates.next()) {
+                renamed.push((message_a.name, message_b.name));
+            }
+        }
+
+        for (name_a, name_b) in &renamed {
+            self.changes.push(Change::RenamedMessage { from: name_a.name(self.proto_db_a), to: name_b.name(self.proto_db_b) });
+        }
+        removed.retain(|message| !renamed.iter().any(|(name_a, _)| *name_a == message.name));
+        added.retain(|message| !renamed.iter().any(|(_, name_b)| *name_b == message.name));
+
+        self.changes.extend(added.iter().map(|message| Change::AddedMessage(message.name.name(self.proto_db_b))));
+        self.changes.extend(removed.iter().map(|message| Change::RemovedMessage(message.name.name(self.proto_db_a))));
+
+        for message_b in messages_b.iter().filter(|message| self.proto_db_b.is_resolved(&message.name)) {
+            let name = message_b.name.name(self.proto_db_b);
+            let message_a = match renamed.iter().find(|(_, name_b)| *name_b == message_b.name) {
+                Some((name_a, _)) => self.proto_db_a.message_db.get_by_left(name_a),
+                None => messages_a.iter().find(|message| message.name.name(self.proto_db_a) == name).copied(),
+            };
+
+            if let Some(message_a) = message_a {
+                self.moved(&message_a.name, message_a.parent, &message_b.name, message_b.parent);
+                self.fields(&name, message_a, message_b);
+            }
+        }
+    }
+
+    /// Field numbers and types, what is left of a message when its name changes
+    fn layout(&self, proto_db: &ProtoDatabase, message: &ProtoMessage) -> Vec<(u32, String)> {
+        message.fields.iter().map(|field| (field.field_number, field.field_type.type_name(proto_db))).sorted().collect()
+    }
+
+    fn moved(&mut self, name_a: &ProtoName, parent_a: Option<ProtoName>, name_b: &ProtoName, parent_b: Option<ProtoName>) {
+        let name = name_b.name(self.proto_db_b);
+        let parent_name = |proto_db: &ProtoDatabase, parent: Option<ProtoName>| parent.map_or("top level".to_string(), |parent| parent.name(proto_db));
+
+        let (from, to) = (parent_name(self.proto_db_a, parent_a), parent_name(self.proto_db_b, parent_b));
+        if from != to {
+            self.changes.push(Change::MovedType { name: name.clone(), from, to });
+        }
+
+        // Only between files both versions have, a schema read from a single file would move everything
+        let file_a = self.proto_db_a.definition_files.get(name_a);
+        let file_b = self.proto_db_b.definition_files.get(name_b);
+        if let (Some(file_a), Some(file_b)) = (file_a, file_b) {
+            if file_a != file_b && self.proto_db_a.definition_files.values().any(|file| file == file_b) {
+                self.changes.push(Change::MovedType { name, from: file_a.clone(), to: file_b.clone() });
+            }
+        }
+    }
+
+    fn fields(&mut self, message: &str, message_a: &ProtoMessage, message_b: &ProtoMessage) {
+        let field_name_a = |field: &ProtoField| field.name.name(self.proto_db_a);
+        let field_name_b = |field: &ProtoField| field.name.name(self.proto_db_b);
+
+        let mut unpaired_a = message_a.fields.iter().sorted_by_key(|field| field.field_number).collect::<Vec<_>>();
+        let mut unpaired_b = Vec::new();
+
+        for field_b in message_b.fields.iter().sorted_by_key(|field| field.field_number) {
+            let Some(index) = unpaired_a.iter().position(|field_a| field_name_a(field_a) == field_name_b(field_b)) else {
+                unpaired_b.push(field_b);
+                continue;
+            };
+
+            let field_a = unpaired_a.remove(index);
+            let (type_a, type_b) = (field_a.field_type.type_name(self.proto_db_a), field_b.field_type.type_name(self.proto_db_b));
+            if type_a != type_b {
+                self.changes.push(Change::FieldTypeChanged { message: message.to_string(), field: field_name_b(field_b), from: type_a, to: type_b });
+            }
+            if field_a.field_number != field_b.field_number {
+                self.changes.push(Change::FieldNumberChanged { message: message.to_string(), field: field_name_b(field_b), from: field_a.field_number, to: field_b.field_number });
+            }
+            self.label(message, (message_a, field_a), (message_b, field_b));
+        }
+
+        for field_b in unpaired_b {
+            let (field, number, type_name) = (field_name_b(field_b), field_b.field_number, field_b.field_type.type_name(self.proto_db_b));
+
+            // An obfuscated name says nothing about whether the field is new
+            if !self.proto_db_b.is_resolved(&field_b.name) {
+                self.changes.push(Change::UnresolvedField { message: message.to_string(), field, number, type_name });
+                continue;
+            }
+
+            match unpaired_a.iter().position(|field_a| field_a.field_number == number) {
+                Some(index) => {
+                    let field_a = unpaired_a.remove(index);
+                    self.changes.push(Change::RenamedField { message: message.to_string(), from: field_name_a(field_a), to: field.clone(), number });
+
+                    let type_a = field_a.field_type.type_name(self.proto_db_a);
+                    if type_a != type_name {
+                        self.changes.push(Change::FieldTypeChanged { message: message.to_string(), field, from: type_a, to: type_name });
+                    }
+                    self.label(message, (message_a, field_a), (message_b, field_b));
+                }
+                None => self.changes.push(Change::AddedField { message: message.to_string(), field, number, type_name }),
+            }
+        }
+
+        for field_a in unpaired_a {
+            self.changes.push(Change::RemovedField { message: message.to_string(), field: field_name_a(field_a), number: field_a.field_number, type_name: field_a.field_type.type_name(self.proto_db_a) });
+        }
+    }
+
+    /// Compared by presence, so a proto2 field without a label and a proto3 `optional` one are the same
+    fn label(&mut self, message: &str, (message_a, field_a): (&ProtoMessage, &ProtoField), (message_b, field_b): (&ProtoMessage, &ProtoField)) {
+        let presence_a = self.proto_db_a.field_presence(message_a.name, field_a);
+        let presence_b = self.proto_db_b.field_presence(message_b.name, field_b);
+
+        if let (Some(presence_a), Some(presence_b)) = (presence_a, presence_b) {
+            if presence_a != presence_b {
+                self.changes.push(Change::LabelChanged { message: message.to_string(), field: field_b.name.name(self.proto_db_b), from: label_name(presence_a).to_string(), to: label_name(presence_b).to_string() });
+            }
+        }
+    }
+
+    fn enums(&mut self) {
+        let enums_a = self.proto_db_a.enum_db.right_values().sorted_by_key(|proto_enum| proto_enum.name.name(self.proto_db_a)).collect::<Vec<_>>();
+        let enums_b = self.proto_db_b.enum_db.right_values().sorted_by_key(|proto_enum| proto_enum.name.name(self.proto_db_b)).collect::<Vec<_>>();
+
+        for enum_b in &enums_b {
+            let name = enum_b.name.name(self.proto_db_b);
+            if !self.proto_db_b.is_resolved(&enum_b.name) {
+                self.changes.push(Change::UnresolvedType(name));
+                continue;
+            }
+
+            match enums_a.iter().find(|proto_enum| proto_enum.name.name(self.proto_db_a) == name) {
+                Some(enum_a) => {
+                    self.moved(&enum_a.name, enum_a.parent, &enum_b.name, enum_b.parent);
+                    self.enum_values(&name, enum_a, enum_b);
+                }
+                None => self.changes.push(Change::AddedEnum(name)),
+            }
+        }
+
+        for enum_a in enums_a {
+            let name = enum_a.name.name(self.proto_db_a);
+            if !enums_b.iter().any(|proto_enum| proto_enum.name.name(self.proto_db_b) == name) {
+                self.changes.push(Change::RemovedEnum(name));
+            }
+        }
+    }
+
+    fn enum_values(&mut self, proto_enum: &str, enum_a: &ProtoEnum, enum_b: &ProtoEnum) {
+        let mut unpaired_a = enum_a.values.iter().map(|value| (value.name.name(self.proto_db_a), value.number)).collect::<Vec<_>>();
+        let mut unpaired_b = Vec::new();
+        let mut unresolved_b = Vec::new();
+
+        for value in &enum_b.values {
+            let (name, number) = (value.name.name(self.proto_db_b), value.number);
+            if !self.proto_db_b.is_resolved(&value.name) {
+                unresolved_b.push((name, number));
+                continue;
+            }
+
+            match unpaired_a.iter().position(|(other, _)| *other == name) {
+                Some(index) => {
+                    let (_, number_a) = unpaired_a.remove(index);
+                    if number_a != number {
+                        self.changes.push(Change::EnumValueNumberChanged { proto_enum: proto_enum.to_string(), value: name, from: number_a, to: number });
+                    }
+                }
+                None => unpaired_b.push((name, number)),
+            }
+        }
+
+        // An obfuscated name can't be compared, so neither a rename nor a removal of the value with its number can be told
+        for (name, number) in unresolved_b {
+            if let Some(index) = unpaired_a.iter().position(|(_, other)| *other == number) {
+                unpaired_a.remove(index);
+            }
+            self.changes.push(Change::UnresolvedEnumValue { proto_enum: proto_enum.to_string(), value: name, number });
+        }
+
+        for (name, number) in unpaired_b {
+            match unpaired_a.iter().position(|(_, other)| *other == number) {
+                Some(index) => {
+                    let (from, _) = unpaired_a.remove(index);
+                    self.changes.push(Change::RenamedEnumValue { proto_enum: proto_enum.to_string(), from, to: name, number });
+                }
+                None => self.changes.push(Change::AddedEnumValue { proto_enum: proto_enum.to_string(), value: name, number }),
+            }
+        }
+
+        for (value, number) in unpaired_a {
+            self.changes.push(Change::RemovedEnumValue { proto_enum: proto_enum.to_string(), value, number });
+        }
+    }
+}
+
+fn label_name(presence: FieldPresence) -> &'static str {
+    match presence {
+        FieldPresence::LegacyRequired => "required",
+        FieldPresence::Explicit => "optional",
+        FieldPresence::Implicit => "implicit",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::parse_test_proto;
+
+    #[test]
+    fn test_diff() {
+        let proto_db_a = parse_test_proto("
+            message Avatar {
+                uint32 level = 1;
+                uint32 exp = 2;
+                uint32 skins = 3;
+                bool old = 4;
+            }
+
+            message Relic {
+                uint32 id = 1;
+            }
+
+            enum Rarity {
+                RARITY_NONE = 0;
+                RARITY_GOLD = 5;
+            }
+        ");
+
+        let proto_db_b = parse_test_proto("
+            message Avatar {
+                uint64 level = 1;
+                uint32 exp = 5;
+                repeated uint32 skins = 3;
+                bool rank = 4;
+                string ABCDEFGHIJK = 6;
+            }
+
+            message RelicItem {
+                uint32 id = 1;
+            }
+
+            enum Rarity {
+                RARITY_NONE = 0;
+                RARITY_LEGENDARY = 5;
+                RARITY_BLUE = 3;
+            }
+        ");
+
+        let diff = SchemaDiff::new(&proto_db_a, &proto_db_b);
+        assert_eq!(diff.to_text().lines().collect::<Vec<_>>(), [
+            "~ message Relic renamed to RelicItem",
+            "~ Avatar.level: type uint32 -> uint64",
+            "~ Avatar.skins: type uint32 -> repeated uint32",
+            "~ Avatar.exp: number 2 -> 5",
+            "~ Avatar.old renamed to rank (= 4)",
+            "? Avatar.ABCDEFGHIJK: string = 6 is unresolved",
+            "~ Rarity.RARITY_GOLD renamed to RARITY_LEGENDARY (= 5)",
+            "+ Rarity.RARITY_BLUE = 3",
+        ]);
+
+        let json: Value = serde_json::from_str(&diff.to_json()).unwrap();
+        assert_eq!(json[3], json!({ "kind": "field_number_changed", "message": "Avatar", "field": "exp", "from": 2, "to": 5 }));
+    }
+
+    #[test]
+    fn test_diff_unresolved_enum_values() {
+        let proto_db_a = parse_test_proto("
+            enum Color {
+                COLOR_NONE = 0;
+                COLOR_RED = 1;
+                COLOR_BLUE = 2;
+            }
+        ");
+        let proto_db_b = parse_test_proto("
+            enum Color {
+                COLOR_NONE = 0;
+                CDEFGHIJKLM = 1;
+                BCDEFGHIJKL = 3;
+            }
+        ");
+
+        let diff = SchemaDiff::new(&proto_db_a, &proto_db_b);
+        assert_eq!(diff.to_text().lines().collect::<Vec<_>>(), [
+            "? Color.CDEFGHIJKLM = 1 is unresolved",
+            "? Color.BCDEFGHIJKL = 3 is unresolved",
+            "- Color.COLOR_BLUE = 2",
+        ]);
+    }
+}
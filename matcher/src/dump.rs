@@ -0,0 +1,469 @@
+use std::collections::{HashMap, HashSet};
+
+use regex::Regex;
+
+use crate::diagnostic::Diagnostic;
+use crate::prototype::{DefinitionRef, ProtoDatabase, ProtoEnum, ProtoEnumValue, ProtoField, ProtoFieldKind, ProtoLabel, ProtoMessage, ProtoName, ProtoSyntax, ProtoType, Span};
+
+/// What a `dump.cs` contributed to the database
+pub struct ParsedDump {
+    pub definitions: Vec<ProtoName>,
+    /// Fields that were skipped, everything else in the dump is still registered
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// A class or enum printed by Il2CppDumper
+struct DumpType<'a> {
+    kind: &'a str,
+    /// From the `// Namespace:` line above the type, empty for the global namespace
+    namespace: &'a str,
+    /// Including the declaring types, e.g. `Outer.Types.Inner`
+    name: &'a str,
+    bases: &'a str,
+    /// Lines between the braces, with the byte offset and 1-based line number of each
+    body: Vec<(&'a str, usize, usize)>,
+    span: Span,
+}
+
+impl DumpType<'_> {
+    /// Generated messages implement `IMessage<T>`
+    fn is_message(&self) -> bool {
+        self.kind == "class" && self.bases.split(',').any(|base| base.trim().starts_with("IMessage"))
+    }
+
+    /// `RPG.Network.Proto.Outer.Types.Inner`
+    fn full_name(&self) -> String {
+        qualify(self.namespace, self.name)
+    }
+}
+
+fn qualify(namespace: &str, name: &str) -> String {
+    if namespace.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", namespace, name)
+    }
+}
+
+/// Registers the generated message classes of an Il2CppDumper `dump.cs` and the enums they use
+///
+/// Field numbers come from the `XxxFieldNumber` constants and field types from the matching properties.
+/// C# names are kept as they are, the schemas are assumed to be proto3
+pub fn parse_dump_into(proto_db: &mut ProtoDatabase, source: &str, file: &str) -> ParsedDump {
+    let file_index = proto_db.register_source_file(file);
+    proto_db.register_syntax(file_index, ProtoSyntax::Proto3, true);
+
+    let types = dump_types(source, file_index);
+    let mut parser = DumpParser {
+        proto_db,
+        file,
+        source,
+        messages: types.iter().filter(|dump_type| dump_type.is_message()).map(|dump_type| dump_type.name).collect(),
+        namespace: "",
+        referenced: HashSet::new(),
+        diagnostics: Vec::new(),
+        field_number: Regex::new(r"^\s*public const int (\w+)FieldNumber = (\d+);").unwrap(),
+        property: Regex::new(r"^\s*public ([\w.<>, ]+?) (\w+) \{ get;").unwrap(),
+        enum_value: Regex::new(r"^\s*public const [\w.]+ (\w+) = (-?\d+);").unwrap(),
+    };
+
+    // Oneof case enums by the message declaring them
+    let mut oneof_cases: HashMap<&str, Vec<&DumpType>> = HashMap::new();
+    for dump_type in types.iter().filter(|dump_type| dump_type.kind == "enum" && dump_type.name.ends_with("OneofCase")) {
+        if let Some((declaring, _)) = dump_type.name.rsplit_once('.') {
+            oneof_cases.entry(declaring).or_default().push(dump_type);
+        }
+    }
+
+    let mut definitions = Vec::new();
+    for message in types.iter().filter(|dump_type| dump_type.is_message()) {
+        if parser.is_duplicate(message) {
+            continue;
+        }
+
+        let oneof_cases = oneof_cases.get(message.name).map_or(&[][..], Vec::as_slice);
+        let (name, parent) = parser.message(message, oneof_cases);
+        if parent.is_none() {
+            definitions.push(name);
+        }
+    }
+
+    // Other enums in the dump belong to the game or the runtime, not to the schema
+    for proto_enum in types.iter().filter(|dump_type| dump_type.kind == "enum" && !dump_type.name.ends_with("OneofCase")) {
+        let is_nested = parser.parent(proto_enum.name).is_some();
+        if (is_nested || parser.referenced.contains(&proto_enum.full_name())) && !parser.is_duplicate(proto_enum) {
+            let (name, parent) = parser.proto_enum(proto_enum);
+            if parent.is_none() {
+                definitions.push(name);
+            }
+        }
+    }
+
+    ParsedDump { definitions, diagnostics: parser.diagnostics }
+}
+
+/// Splits the dump into its type blocks, which start at the beginning of a line and end with a `}` line
+fn dump_types(source: &str, file_index: usize) -> Vec<DumpType<'_>> {
+    let header = Regex::new(r"^(?:(?:public|internal|protected|private|sealed|static|abstract)\s+)*(class|struct|enum|interface)\s+([^\s:]+)(?:\s*:\s*([^/]*))?").unwrap();
+
+    let mut types: Vec<DumpType> = Vec::new();
+    let mut namespace = "";
+    let mut in_body = false;
+    let mut offset = 0;
+
+    for (index, raw_line) in source.split_inclusive('\n').enumerate() {
+        let line = raw_line.trim_end();
+        let start = offset;
+        offset += raw_line.len();
+
+        if let Some(declared) = line.strip_prefix("// Namespace:") {
+            namespace = declared.trim();
+            continue;
+        }
+
+        if let Some(captures) = header.captures(line) {
+            types.push(DumpType {
+                kind: captures.get(1).unwrap().as_str(),
+                namespace,
+                name: captures.get(2).unwrap().as_str(),
+                bases: captures.get(3).map_or("", |bases| bases.as_str()),
+                body: Vec::new(),
+                span: Span { file: file_index, start_byte: start, end_byte: start + line.len(), line: index + 1, column: 1 },
+            });
+            in_body = false;
+            continue;
+        }
+
+        match (line, types.last_mut()) {
+            ("{", Some(_)) => in_body = true,
+            ("}", Some(_)) => in_body = false,
+            (_, Some(dump_type)) if in_body => dump_type.body.push((line, start, index + 1)),
+            _ => (),
+        }
+    }
+
+    types
+}
+
+/// `Outer.Types.Inner` is `Inner` in the schema
+fn short_name(cs_name: &str) -> &str {
+    cs_name.rsplit('.').next().unwrap_or(cs_name)
+}
+
+struct DumpParser<'a> {
+    proto_db: &'a mut ProtoDatabase,
+    file: &'a str,
+    source: &'a str,
+    /// C# names of all message classes
+    messages: HashSet<&'a str>,
+    /// Namespace of the message being parsed, its field types are looked up there first
+    namespace: &'a str,
+    /// Full C# names the types used by message fields could have
+    referenced: HashSet<String>,
+    diagnostics: Vec<Diagnostic>,
+    field_number: Regex,
+    property: Regex,
+    enum_value: Regex,
+}
+
+impl<'a> DumpParser<'a> {
+    fn name(&mut self, text: &str) -> ProtoName {
+        self.proto_db.register_identifier(text.to_string());
+        self.proto_db.lookup_name_by_text(text)
+    }
+
+    /// Nested types are declared in the `Types` class of their message, oneof case enums directly in it
+    fn parent(&self, cs_name: &str) -> Option<String> {
+        let (declaring, _) = cs_name.rsplit_once('.')?;
+        let declaring = declaring.strip_suffix(".Types").unwrap_or(declaring);
+        self.messages.contains(declaring).then(|| declaring.to_string())
+    }
+
+    fn message(&mut self, message: &DumpType<'a>, oneof_cases: &[&DumpType]) -> (ProtoName, Option<ProtoName>) {
+        self.namespace = message.namespace;
+
+        let properties = message.body.iter()
+            .filter_map(|(line, _, _)| self.property.captures(line))
+            .map(|captures| (captures[2].to_string(), captures[1].to_string()))
+            .collect::<HashMap<_, _>>();
+
+        // Oneof members by field number, from the cases of each `XxxOneofCase` enum
+        let mut oneofs = Vec::new();
+        let mut oneof_members = HashMap::new();
+        for oneof_case in oneof_cases {
+            let oneof = self.name(short_name(oneof_case.name).trim_end_matches("OneofCase"));
+            oneofs.push(oneof);
+
+            for (_, number, _) in self.enum_values(oneof_case).into_iter().filter(|(name, _, _)| name != "None") {
+                oneof_members.insert(number as u32, oneof);
+            }
+        }
+
+        let mut fields = Vec::new();
+        for &(line, start, line_number) in &message.body {
+            let Some(captures) = self.field_number.captures(line) else {
+                continue;
+            };
+
+            // Properties named like the class get an underscore appended
+            let field_name = &captures[1];
+            let Some(cs_type) = properties.get(field_name).or_else(|| properties.get(&format!("{}_", field_name))) else {
+                self.skipped_field(line, line_number, format!("no property for field {} of {}, skipped", field_name, message.name));
+                continue;
+            };
+
+            let Ok(number) = captures[2].parse() else {
+                self.skipped_field(line, line_number, format!("field number {} of {}.{} is out of range, skipped", &captures[2], message.name, field_name));
+                continue;
+            };
+            let field_type = self.field_kind(cs_type);
+
+            // proto3 `optional` fields get a `HasXxx` property, message fields are nullable instead
+            let label = match properties.get(&format!("Has{}", field_name)) {
+                Some(has_type) if has_type == "bool" && !oneof_members.contains_key(&number) => ProtoLabel::Optional,
+                _ => ProtoLabel::None,
+            };
+
+            fields.push(ProtoField {
+                name: self.name(field_name),
+                label,
+                field_type,
+                field_number: number,
+                oneof: oneof_members.get(&number).copied(),
+                span: Span { start_byte: start, end_byte: start + line.len(), line: line_number, ..message.span },
+            });
+        }
+
+        let name = self.name(short_name(message.name));
+        let parent = self.parent(message.name).map(|parent| self.name(short_name(&parent)));
+        self.proto_db.register_message(ProtoMessage {
+            name,
+            fields,
+            oneofs,
+            parent,
+            span: message.span,
+        });
+
+        (name, parent)
+    }
+
+    /// Nested types are keyed by their name alone, a second type with a name that is defined already is reported and skipped
+    fn is_duplicate(&mut self, dump_type: &DumpType) -> bool {
+        let Some(previous) = ProtoName::try_lookup(self.proto_db, short_name(dump_type.name)).and_then(|name| self.proto_db.span_of(DefinitionRef::Definition(name))) else {
+            return false;
+        };
+
+        self.diagnostics.push(Diagnostic {
+            file: self.file.to_string(),
+            line: dump_type.span.line,
+            column: 1,
+            snippet: self.source[dump_type.span.start_byte..dump_type.span.end_byte].to_string(),
+            message: format!("`{}` is defined already at {}, this definition is skipped", dump_type.full_name(), previous.location(self.proto_db)),
+            expected: None,
+        });
+        true
+    }
+
+    fn skipped_field(&mut self, line: &str, line_number: usize, message: String) {
+        self.diagnostics.push(Diagnostic {
+            file: self.file.to_string(),
+            line: line_number,
+            column: 1,
+            snippet: line.to_string(),
+            message,
+            expected: None,
+        });
+    }
+
+    fn field_kind(&mut self, cs_type: &str) -> ProtoFieldKind {
+        let generic_argument = |generic: &str| cs_type.strip_prefix(generic).and_then(|rest| rest.strip_prefix('<')).and_then(|rest| rest.strip_suffix('>'));
+
+        if let Some(element) = generic_argument("RepeatedField") {
+            return ProtoFieldKind::Repeated(self.proto_type(element));
+        }
+
+        if let Some((key, value)) = generic_argument("MapField").and_then(|arguments| arguments.split_once(',')) {
+            return ProtoFieldKind::Map(self.proto_type(key.trim()), self.proto_type(value.trim()));
+        }
+
+        ProtoFieldKind::Scalar(self.proto_type(cs_type))
+    }
+
+    /// Varint encodings are assumed, `fixed32` and `sint32` fields can't be told apart from `uint32` and `int32` by their C# type
+    fn proto_type(&mut self, cs_type: &str) -> ProtoType {
+        match cs_type {
+            "bool" => ProtoType::Bool,
+            "float" => ProtoType::Float,
+            "double" => ProtoType::Double,
+            "int" => ProtoType::Int32,
+            "long" => ProtoType::Int64,
+            "uint" => ProtoType::Uint32,
+            "ulong" => ProtoType::Uint64,
+            "string" => ProtoType::String,
+            "ByteString" => ProtoType::Bytes,
+            _ => {
+                // Relative to the message's namespace, or already qualified
+                self.referenced.insert(qualify(self.namespace, cs_type));
+                self.referenced.insert(cs_type.to_string());
+                ProtoType::Type(self.name(short_name(cs_type)))
+            }
+        }
+    }
+
+    fn proto_enum(&mut self, proto_enum: &DumpType) -> (ProtoName, Option<ProtoName>) {
+        let values = self.enum_values(proto_enum).into_iter()
+            .map(|(value_name, number, span)| ProtoEnumValue {
+                name: self.name(&value_name),
+                number,
+                span,
+            })
+            .collect();
+
+        let name = self.name(short_name(proto_enum.name));
+        let parent = self.parent(proto_enum.name).map(|parent| self.name(short_name(&parent)));
+        self.proto_db.register_enum(ProtoEnum {
+            name,
+            values,
+            parent,
+            span: proto_enum.span,
+        });
+
+        (name, parent)
+    }
+
+    /// `public const Type Name = 1;` constants of an enum
+    fn enum_values(&self, proto_enum: &DumpType) -> Vec<(String, i32, Span)> {
+        proto_enum.body.iter()
+            .filter_map(|&(line, start, line_number)| {
+                let captures = self.enum_value.captures(line)?;
+                let span = Span { start_byte: start, end_byte: start + line.len(), line: line_number, ..proto_enum.span };
+                Some((captures[1].to_string(), captures[2].parse().ok()?, span))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUMP: &str = r#"// Namespace: RPG.Network.Proto
+public sealed class PlayerInfo : IMessage<PlayerInfo>, IMessage, IEquatable<PlayerInfo>, IDeepCloneable<PlayerInfo>, IBufferMessage // TypeDefIndex: 100
+{
+	// Fields
+	private static readonly MessageParser<PlayerInfo> _parser; // 0x0
+	public const int UidFieldNumber = 1;
+	private uint uid_; // 0x18
+	public const int NicknameFieldNumber = 2;
+	public const int ItemsFieldNumber = 3;
+	public const int CountsFieldNumber = 4;
+	public const int StatusFieldNumber = 5;
+	public const int GuildFieldNumber = 6;
+	public const int LevelFieldNumber = 7;
+
+	// Properties
+	public static MessageParser<PlayerInfo> Parser { get; }
+	public uint Uid { get; set; }
+	public string Nickname { get; set; }
+	public RepeatedField<PlayerInfo.Types.Item> Items { get; }
+	public MapField<uint, long> Counts { get; }
+	public PlayerStatus Status { get; set; }
+	public ByteString Guild { get; set; }
+	public uint Level { get; set; }
+	public bool HasLevel { get; }
+	public PlayerInfo.DetailOneofCase DetailCase { get; }
+}
+
+// Namespace: RPG.Network.Proto
+public enum PlayerInfo.DetailOneofCase // TypeDefIndex: 101
+{
+	// Fields
+	public int value__; // 0x0
+	public const PlayerInfo.DetailOneofCase None = 0;
+	public const PlayerInfo.DetailOneofCase Status = 5;
+	public const PlayerInfo.DetailOneofCase Guild = 6;
+}
+
+// Namespace: RPG.Network.Proto
+public sealed class PlayerInfo.Types.Item : IMessage<PlayerInfo.Types.Item>, IMessage // TypeDefIndex: 102
+{
+	public const int IdFieldNumber = 1;
+	public uint Id { get; set; }
+}
+
+// Namespace: RPG.Network.Proto
+public enum PlayerStatus // TypeDefIndex: 103
+{
+	public int value__; // 0x0
+	public const PlayerStatus Offline = 0;
+	public const PlayerStatus Online = 1;
+}
+
+// Namespace: RPG.Client
+public enum CameraMode // TypeDefIndex: 104
+{
+	public int value__; // 0x0
+	public const CameraMode Free = 0;
+}
+
+// Namespace: RPG.Client
+public enum PlayerStatus // TypeDefIndex: 105
+{
+	public int value__; // 0x0
+	public const PlayerStatus Hidden = 0;
+}
+"#;
+
+    #[test]
+    fn test_parse_dump() {
+        let mut proto_db = ProtoDatabase::new();
+        let parsed = parse_dump_into(&mut proto_db, DUMP, "dump.cs");
+
+        assert!(parsed.diagnostics.is_empty());
+        assert_eq!(parsed.definitions.len(), 2);
+        assert!(proto_db.get_message("CameraMode").is_none() && ProtoName::try_lookup(&proto_db, "Free").is_none());
+        assert!(ProtoName::try_lookup(&proto_db, "Hidden").is_none());
+
+        let player = proto_db.get_message("PlayerInfo").unwrap();
+        let item = ProtoName::lookup(&proto_db, "Item");
+        let status = ProtoName::lookup(&proto_db, "PlayerStatus");
+        let detail = ProtoName::lookup(&proto_db, "Detail");
+
+        assert_eq!(player.fields.iter().map(|field| field.field_type).collect::<Vec<_>>(), [
+            ProtoFieldKind::Scalar(ProtoType::Uint32),
+            ProtoFieldKind::Scalar(ProtoType::String),
+            ProtoFieldKind::Repeated(ProtoType::Type(item)),
+            ProtoFieldKind::Map(ProtoType::Uint32, ProtoType::Int64),
+            ProtoFieldKind::Scalar(ProtoType::Type(status)),
+            ProtoFieldKind::Scalar(ProtoType::Bytes),
+            ProtoFieldKind::Scalar(ProtoType::Uint32),
+        ]);
+        assert_eq!(player.oneofs, [detail]);
+        assert_eq!(player.fields[4].oneof, Some(detail));
+        assert_eq!(player.fields[6].label, ProtoLabel::Optional);
+        assert_eq!(player.fields[6].span.line, 13);
+
+        assert_eq!(proto_db.parent_of(&item), Some(player.name));
+        assert_eq!(proto_db.enum_db.get_by_left(&status).unwrap().values.len(), 2);
+    }
+
+    #[test]
+    fn test_field_number_out_of_range() {
+        let dump = r#"// Namespace: RPG.Network.Proto
+public sealed class Avatar : IMessage<Avatar>, IMessage // TypeDefIndex: 100
+{
+	public const int IdFieldNumber = 1;
+	public const int LevelFieldNumber = 99999999999;
+	public uint Id { get; set; }
+	public uint Level { get; set; }
+}
+"#;
+
+        let mut proto_db = ProtoDatabase::new();
+        let parsed = parse_dump_into(&mut proto_db, dump, "dump.cs");
+
+        assert_eq!(parsed.diagnostics.len(), 1);
+        assert_eq!(parsed.diagnostics[0].line, 5);
+        assert_eq!(proto_db.get_message("Avatar").unwrap().fields.len(), 1);
+    }
+}
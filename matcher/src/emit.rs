@@ -0,0 +1,389 @@
+use std::fmt::Write;
+
+use itertools::Itertools;
+use tracing::warn;
+
+use crate::prototype::{DefinitionRef, FieldPresence, ProtoDatabase, ProtoEnum, ProtoExtension, ProtoField, ProtoFieldKind, ProtoLabel, ProtoMessage, ProtoName, ProtoOption, ProtoService, ProtoSyntax, ProtoType, FIELD_PRESENCE_FEATURE, RESERVED_MAX};
+
+const INDENT: &str = "    ";
+
+/// Renders definitions back into proto source, using the current (resolved) names of `proto_db`
+pub struct ProtoEmitter<'a> {
+    proto_db: &'a ProtoDatabase,
+    /// Syntax of the emitted file, fields from files with another syntax have their presence converted to it
+    syntax: ProtoSyntax,
+    output: String,
+}
+
+impl<'a> ProtoEmitter<'a> {
+    pub fn new(proto_db: &'a ProtoDatabase) -> Self {
+        Self {
+            proto_db,
+            syntax: proto_db.syntax.clone().unwrap_or_default(),
+            output: String::new(),
+        }
+    }
+
+    pub fn finish(self) -> String {
+        self.output
+    }
+
+    pub fn emit_header(&mut self) {
+        if self.proto_db.syntax.is_some() {
+            writeln!(self.output, "{}\n", self.syntax.statement()).unwrap();
+        }
+    }
+
+    pub fn emit_package(&mut self, package: &str) {
+        writeln!(self.output, "package {};\n", package).unwrap();
+    }
+
+    pub fn emit_imports<'i>(&mut self, imports: impl IntoIterator<Item = &'i str>) {
+        let mut any = false;
+        for import in imports {
+            writeln!(self.output, "import \"{}\";", import).unwrap();
+            any = true;
+        }
+
+        if any {
+            self.output.push('\n');
+        }
+    }
+
+    /// File-level `option` statements
+    pub fn emit_options(&mut self, options: &[ProtoOption]) {
+        let options = self.supported_options(options);
+        for option in &options {
+            let line = format!("option {} = {};", option.name, self.option_value(option));
+            self.line(0, &line);
+        }
+
+        if !options.is_empty() {
+            self.output.push('\n');
+        }
+    }
+
+    /// Emits top-level messages, enums (with everything nested in them) and services, separated by blank lines
+    pub fn emit_definitions(&mut self, names: &[ProtoName]) {
+        for (i, name) in names.iter().enumerate() {
+            if i > 0 {
+                self.output.push('\n');
+            }
+
+            self.emit_definition(name, 0);
+        }
+    }
+
+    /// Emits top-level `extend` blocks, after the definitions they may refer to
+    pub fn emit_extensions(&mut self, extensions: &[&ProtoExtension]) {
+        for extension in extensions {
+            if !self.is_supported_extension(extension) {
+                continue;
+            }
+
+            if !self.output.is_empty() && !self.output.ends_with("\n\n") {
+                self.output.push('\n');
+            }
+
+            self.emit_extension(extension, 0);
+        }
+    }
+
+    fn emit_definition(&mut self, name: &ProtoName, depth: usize) {
+        if let Some(message) = self.proto_db.message_db.get_by_left(name) {
+            self.emit_message(message, depth);
+        } else if let Some(proto_enum) = self.proto_db.enum_db.get_by_left(name) {
+            self.emit_enum(proto_enum, depth);
+        } else if let Some(service) = self.proto_db.service_db.get_by_left(name) {
+            self.emit_service(service, depth);
+        }
+    }
+
+    fn line(&mut self, depth: usize, text: &str) {
+        writeln!(self.output, "{}{}", INDENT.repeat(depth), text).unwrap();
+    }
+
+    /// Enum values used as option values are renamed along with the enum
+    fn option_value(&self, option: &ProtoOption) -> String {
+        let is_identifier = option.value.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && option.value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+        match is_identifier {
+            true => self.proto_db.translate_name(&option.value).unwrap_or_else(|| option.value.clone()),
+            false => option.value.clone(),
+        }
+    }
+
+    /// `option` statements at the top of a message or enum body
+    fn emit_body_options(&mut self, depth: usize, target: DefinitionRef) {
+        for option in self.supported_options(self.proto_db.options_of(target)) {
+            let line = format!("option {} = {};", option.name, self.option_value(&option));
+            self.line(depth, &line);
+        }
+    }
+
+    /// Feature options only exist in editions
+    fn supported_options(&self, options: &[ProtoOption]) -> Vec<ProtoOption> {
+        options.iter()
+            .filter(|option| self.syntax.is_edition() || !option.name.starts_with("features."))
+            .cloned()
+            .collect()
+    }
+
+    /// ` [name = value, ...]`, empty without options
+    fn bracket_options(&self, options: &[ProtoOption]) -> String {
+        let options = self.supported_options(options);
+        if options.is_empty() {
+            return String::new();
+        }
+
+        format!(" [{}]", options.iter().map(|option| format!("{} = {}", option.name, self.option_value(option))).join(", "))
+    }
+
+    /// A line with the leading comments of `target` above it, the trailing comment goes on `text` itself unless `closed_later`
+    fn commented_line(&mut self, depth: usize, text: &str, target: DefinitionRef, closed_later: bool) {
+        let Some(comments) = self.proto_db.comments.get(&target) else {
+            self.line(depth, text);
+            return;
+        };
+
+        for comment in &comments.leading {
+            self.line(depth, format!("// {}", comment).trim_end());
+        }
+
+        match &comments.trailing {
+            Some(trailing) if !closed_later => self.line(depth, &format!("{} // {}", text, trailing)),
+            _ => self.line(depth, text),
+        }
+    }
+
+    /// Closing brace of a definition, the trailing comment of a definition follows it like in the source
+    fn closing_line(&mut self, depth: usize, target: DefinitionRef) {
+        match self.proto_db.comments.get(&target).and_then(|comments| comments.trailing.as_ref()) {
+            Some(trailing) => self.line(depth, &format!("}} // {}", trailing)),
+            None => self.line(depth, "}"),
+        }
+    }
+
+    fn emit_message(&mut self, message: &ProtoMessage, depth: usize) {
+        let db = self.proto_db;
+        self.commented_line(depth, &format!("message {} {{", message.name.name(db)), DefinitionRef::Definition(message.name), true);
+        self.emit_body_options(depth + 1, DefinitionRef::Definition(message.name));
+
+        let mut emitted_oneofs = Vec::new();
+        for field in &message.fields {
+            match field.oneof {
+                None => self.emit_field(message.name, Some(message.name), field, depth + 1),
+                Some(oneof) if !emitted_oneofs.contains(&oneof) => {
+                    emitted_oneofs.push(oneof);
+
+                    // Oneof fields are emitted together at the position of the first one
+                    self.line(depth + 1, &format!("oneof {} {{", oneof.name(db)));
+                    for oneof_field in message.fields.iter().filter(|f| f.oneof == Some(oneof)) {
+                        self.emit_field(message.name, Some(message.name), oneof_field, depth + 2);
+                    }
+                    self.line(depth + 1, "}");
+                }
+                Some(_) => (),
+            }
+        }
+
+        self.emit_reserved(depth + 1, message.name);
+        if let Some(ranges) = self.proto_db.extension_ranges.get(&message.name) {
+            match self.syntax {
+                ProtoSyntax::Proto3 => self.unsupported(&format!("`extensions {}` of {}", format_ranges(ranges), self.relative_name(message.name, None))),
+                _ => self.line(depth + 1, &format!("extensions {};", format_ranges(ranges))),
+            }
+        }
+
+        let nested = db.child_enums(Some(message.name)).into_iter().map(|e| e.name)
+            .chain(db.child_messages(Some(message.name)).into_iter().map(|m| m.name))
+            .collect::<Vec<_>>();
+
+        for (i, name) in nested.iter().enumerate() {
+            if i > 0 || !message.fields.is_empty() {
+                self.output.push('\n');
+            }
+
+            self.emit_definition(name, depth + 1);
+        }
+
+        for extension in db.child_extensions(Some(message.name)) {
+            if !self.is_supported_extension(extension) {
+                continue;
+            }
+
+            if !self.output.ends_with("{\n") {
+                self.output.push('\n');
+            }
+            self.emit_extension(extension, depth + 1);
+        }
+
+        self.closing_line(depth, DefinitionRef::Definition(message.name));
+    }
+
+    /// `owner` is the message the field is in, or the extendee for extension fields, `scope` is where it is declared
+    fn emit_field(&mut self, owner: ProtoName, scope: Option<ProtoName>, field: &ProtoField, depth: usize) {
+        let db = self.proto_db;
+        let target = DefinitionRef::Field(owner, field.field_number);
+        let mut options = db.options_of(target).to_vec();
+
+        if self.syntax == ProtoSyntax::Proto3 {
+            if field.label == ProtoLabel::Required {
+                self.unsupported(&format!("`required` of {}.{}", self.relative_name(owner, None), field.name.name(db)));
+            }
+            if options.iter().any(|option| option.name == "default") {
+                self.unsupported(&format!("`default` of {}.{}", self.relative_name(owner, None), field.name.name(db)));
+                options.retain(|option| option.name != "default");
+            }
+        }
+
+        let label = if db.syntax_of(field.span.file) == self.syntax {
+            field.label.keyword()
+        } else {
+            // Spell the field's presence the way the emitted file's syntax does
+            options.retain(|option| option.name != FIELD_PRESENCE_FEATURE);
+
+            let presence = db.field_presence(owner, field);
+            if let (ProtoSyntax::Edition(_), Some(presence)) = (&self.syntax, presence) {
+                if presence != self.default_presence() {
+                    options.push(ProtoOption { name: FIELD_PRESENCE_FEATURE.to_string(), value: presence.feature_value().to_string() });
+                }
+            }
+
+            match (&self.syntax, presence) {
+                (ProtoSyntax::Proto2, Some(FieldPresence::LegacyRequired)) => Some("required"),
+                (ProtoSyntax::Proto2, Some(_)) if field.oneof.is_none() => Some("optional"),
+                (ProtoSyntax::Proto3, Some(FieldPresence::Explicit)) if field.oneof.is_none() && !db.is_message_field(field) => Some("optional"),
+                _ => None,
+            }
+        };
+
+        let label = label.map(|keyword| format!("{} ", keyword)).unwrap_or_default();
+        let line = format!("{}{} {} = {}{};", label, self.field_type_name(&field.field_type, scope), field.name.name(db), field.field_number, self.bracket_options(&options));
+        self.commented_line(depth, &line, target, false);
+    }
+
+    fn field_type_name(&self, field_type: &ProtoFieldKind, scope: Option<ProtoName>) -> String {
+        match field_type {
+            ProtoFieldKind::Scalar(a) => self.type_reference(a, scope),
+            ProtoFieldKind::Map(a, b) => format!("map<{}, {}>", self.type_reference(a, scope), self.type_reference(b, scope)),
+            ProtoFieldKind::Repeated(a) => format!("repeated {}", self.type_reference(a, scope)),
+        }
+    }
+
+    fn type_reference(&self, field_type: &ProtoType, scope: Option<ProtoName>) -> String {
+        match field_type {
+            ProtoType::Type(name) => self.relative_name(*name, scope),
+            scalar => scalar.type_name(self.proto_db),
+        }
+    }
+
+    /// A definition's name as written in `scope`, nested ones are prefixed with their enclosing messages up to the first `scope` is in
+    fn relative_name(&self, name: ProtoName, scope: Option<ProtoName>) -> String {
+        let db = self.proto_db;
+        let scopes = std::iter::successors(scope, |scope| db.parent_of(scope)).collect::<Vec<_>>();
+
+        let mut segments = vec![name.name(db)];
+        let mut parent = db.parent_of(&name);
+        while let Some(name) = parent.filter(|parent| !scopes.contains(parent)) {
+            segments.push(name.name(db));
+            parent = db.parent_of(&name);
+        }
+
+        segments.iter().rev().join(".")
+    }
+
+    /// Field presence of the emitted edition file, as set by its file options
+    fn default_presence(&self) -> FieldPresence {
+        self.proto_db.file_options().into_iter()
+            .find(|option| option.name == FIELD_PRESENCE_FEATURE)
+            .map_or(FieldPresence::Explicit, |option| FieldPresence::from_feature(&option.value))
+    }
+
+    /// proto3 only allows extending the descriptor options, for custom options
+    fn is_supported_extension(&self, extension: &ProtoExtension) -> bool {
+        let extendee = self.relative_name(extension.extendee, None);
+        if self.syntax != ProtoSyntax::Proto3 || extendee.trim_start_matches('.').starts_with("google.protobuf.") {
+            return true;
+        }
+
+        self.unsupported(&format!("`extend {}` with field(s) {}", extendee, extension.fields.iter().map(|field| field.name.name(self.proto_db)).join(", ")));
+        false
+    }
+
+    /// A proto2 construct the emitted proto3 file can't express, it is left out
+    fn unsupported(&self, construct: &str) {
+        warn!("{} is left out, proto3 can't express it", construct);
+    }
+
+    fn emit_extension(&mut self, extension: &ProtoExtension, depth: usize) {
+        self.line(depth, &format!("extend {} {{", self.relative_name(extension.extendee, extension.parent)));
+        for field in &extension.fields {
+            self.emit_field(extension.extendee, extension.parent, field, depth + 1);
+        }
+        self.line(depth, "}");
+    }
+
+    fn emit_reserved(&mut self, depth: usize, name: ProtoName) {
+        let Some(reserved) = self.proto_db.reserved.get(&name) else {
+            return;
+        };
+
+        if !reserved.ranges.is_empty() {
+            self.line(depth, &format!("reserved {};", format_ranges(&reserved.ranges)));
+        }
+
+        if !reserved.names.is_empty() {
+            self.line(depth, &format!("reserved {};", reserved.names.iter().map(|name| format!("\"{}\"", name)).join(", ")));
+        }
+    }
+
+    fn emit_enum(&mut self, proto_enum: &ProtoEnum, depth: usize) {
+        let db = self.proto_db;
+        self.commented_line(depth, &format!("enum {} {{", proto_enum.name.name(db)), DefinitionRef::Definition(proto_enum.name), true);
+        self.emit_body_options(depth + 1, DefinitionRef::Definition(proto_enum.name));
+
+        for value in &proto_enum.values {
+            let target = DefinitionRef::EnumValue(proto_enum.name, value.number);
+            let line = format!("{} = {}{};", value.name.name(db), value.number, self.bracket_options(db.options_of(target)));
+            self.commented_line(depth + 1, &line, target, false);
+        }
+        self.emit_reserved(depth + 1, proto_enum.name);
+
+        self.closing_line(depth, DefinitionRef::Definition(proto_enum.name));
+    }
+
+    fn emit_service(&mut self, service: &ProtoService, depth: usize) {
+        let db = self.proto_db;
+        self.commented_line(depth, &format!("service {} {{", service.name.name(db)), DefinitionRef::Definition(service.name), true);
+        self.emit_body_options(depth + 1, DefinitionRef::Definition(service.name));
+
+        for rpc in &service.rpcs {
+            let target = DefinitionRef::Rpc(service.name, rpc.name);
+            let stream = |is_stream: bool| if is_stream { "stream " } else { "" };
+            let signature = format!("rpc {}({}{}) returns ({}{})", rpc.name.name(db), stream(rpc.request_stream), self.type_reference(&rpc.request, None), stream(rpc.response_stream), self.type_reference(&rpc.response, None));
+
+            if db.options_of(target).is_empty() {
+                self.commented_line(depth + 1, &format!("{};", signature), target, false);
+            } else {
+                self.commented_line(depth + 1, &format!("{} {{", signature), target, true);
+                self.emit_body_options(depth + 2, target);
+                self.closing_line(depth + 1, target);
+            }
+        }
+
+        self.closing_line(depth, DefinitionRef::Definition(service.name));
+    }
+}
+
+/// `1, 5 to 9, 100 to max`
+fn format_ranges(ranges: &[(i64, i64)]) -> String {
+    ranges.iter()
+        .map(|&(start, end)| match end {
+            _ if end == start => start.to_string(),
+            RESERVED_MAX => format!("{} to max", start),
+            _ => format!("{} to {}", start, end),
+        })
+        .join(", ")
+}
@@ -0,0 +1,136 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use crate::matcher::{MatchEvent, Matcher};
+use crate::prototype::ProtoName;
+
+/// Why a name of b was resolved, as the chain of decisions back to names both schemas share
+pub fn explain(matcher: &Matcher, identifier: &str) -> String {
+    let mut explainer = Explainer {
+        matcher,
+        visited: HashSet::new(),
+        output: String::new(),
+    };
+
+    explainer.name(identifier, 0);
+    explainer.output
+}
+
+struct Explainer<'a> {
+    matcher: &'a Matcher,
+    /// Names already explained, shared premises are only followed once
+    visited: HashSet<String>,
+    output: String,
+}
+
+impl Explainer<'_> {
+    fn name(&mut self, identifier: &str, depth: usize) {
+        let indent = "  ".repeat(depth);
+        let events = self.matcher.events().iter()
+            .filter(|event| event.name_a == identifier || event.name_b == identifier)
+            .collect::<Vec<_>>();
+
+        if events.is_empty() {
+            writeln!(self.output, "{}{}: {}", indent, identifier, self.anchor(identifier)).unwrap();
+            return;
+        }
+
+        if !self.visited.insert(events[0].name_a.clone()) {
+            writeln!(self.output, "{}{}: see above", indent, events[0].name_a).unwrap();
+            return;
+        }
+
+        for event in events {
+            self.event(event, depth);
+        }
+    }
+
+    fn event(&mut self, event: &MatchEvent, depth: usize) {
+        let indent = "  ".repeat(depth);
+
+        let mut subject = event.name_a.clone();
+        if event.name_b != event.name_a {
+            write!(subject, " (was {})", event.name_b).unwrap();
+        }
+        if let Some(message) = &event.message {
+            write!(subject, " in {}", message).unwrap();
+        }
+
+        writeln!(self.output, "{}{}: {} [{}], round {}", indent, subject, event.rule.description(), event.rule.key(), event.round).unwrap();
+        if !event.considered_a.is_empty() {
+            writeln!(self.output, "{}  considered in a: {}", indent, event.considered_a.join(", ")).unwrap();
+            writeln!(self.output, "{}  considered in b: {}", indent, event.considered_b.join(", ")).unwrap();
+        }
+
+        if !event.depends_on.is_empty() {
+            writeln!(self.output, "{}  depends on:", indent).unwrap();
+            for premise in &event.depends_on {
+                self.name(premise, depth + 2);
+            }
+        }
+    }
+
+    /// Names no rule resolved are either readable in b already or still open
+    fn anchor(&self, identifier: &str) -> &'static str {
+        let proto_db_b = self.matcher.db_b();
+        let current = proto_db_b.translate_name(identifier).unwrap_or_else(|| identifier.to_string());
+
+        match ProtoName::try_lookup(proto_db_b, &current) {
+            Some(name) if proto_db_b.is_resolved(&name) => "known by name in both schemas",
+            Some(_) => "not resolved",
+            None => "not in b",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::parse_test_proto;
+
+    #[test]
+    fn test_explain_chain() {
+        // Avatar's only message field resolves Equipment, whose only field is then resolved in the next round
+        let proto_db_a = parse_test_proto("
+            message Avatar {
+                Equipment equipment = 1;
+            }
+
+            message Equipment {
+                uint32 level = 1;
+            }
+        ");
+
+        let proto_db_b = parse_test_proto("
+            message Avatar {
+                EQUIPMENTXX ABCDEFGHIJK = 3;
+            }
+
+            message EQUIPMENTXX {
+                uint32 BCDEFGHIJKL = 2;
+            }
+        ");
+
+        let mut matcher = Matcher::new(proto_db_a, proto_db_b);
+        matcher.run();
+
+        let explanation = explain(&matcher, "BCDEFGHIJKL");
+        let lines = explanation.lines().map(str::trim).collect::<Vec<_>>();
+        assert_eq!(lines, [
+            "level (was BCDEFGHIJKL) in Equipment: Only field of its type [unique_weak_type], round 2",
+            "considered in a: level",
+            "considered in b: BCDEFGHIJKL",
+            "depends on:",
+            "Equipment (was EQUIPMENTXX): Only field of its type [unique_weak_type], round 1",
+            "depends on:",
+            "Avatar: known by name in both schemas",
+            "equipment (was ABCDEFGHIJK) in Avatar: Only field of its type [unique_weak_type], round 1",
+            "considered in a: equipment",
+            "considered in b: ABCDEFGHIJK",
+            "depends on:",
+            "Avatar: known by name in both schemas",
+        ]);
+
+        assert_eq!(explain(&matcher, "Avatar"), "Avatar: known by name in both schemas\n");
+    }
+}
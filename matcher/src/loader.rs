@@ -0,0 +1,296 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use itertools::Itertools;
+use tracing::warn;
+
+use crate::descriptor;
+use crate::diagnostic::Diagnostic;
+use crate::dump;
+use crate::parser::{self, ImportKind};
+use crate::prototype::ProtoDatabase;
+use crate::wire::WireError;
+
+/// Extensions of serialized `FileDescriptorSet`s, everything else is read as `.proto` source
+const DESCRIPTOR_EXTENSIONS: [&str; 4] = ["pb", "binpb", "desc", "protoset"];
+
+/// Extension of Il2CppDumper's `dump.cs`
+const DUMP_EXTENSION: &str = "cs";
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(PathBuf, io::Error),
+    MissingImport {
+        importer: String,
+        import: String,
+        searched: Vec<PathBuf>,
+    },
+    ImportCycle(Vec<String>),
+    Descriptor(PathBuf, WireError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            LoadError::MissingImport { importer, import, searched } => {
+                write!(f, "{} imports \"{}\", which was not found in {}", importer, import, searched.iter().map(|path| path.display()).join(", "))
+            }
+            LoadError::ImportCycle(chain) => write!(f, "import cycle: {}", chain.join(" -> ")),
+            LoadError::Descriptor(path, e) => write!(f, "{}: invalid descriptor set: {}", path.display(), e),
+        }
+    }
+}
+
+/// Loads `.proto` files into a single database, following their imports through the include paths
+pub struct ProtoLoader {
+    include_paths: Vec<PathBuf>,
+    loaded: HashSet<PathBuf>,
+    /// Files currently being loaded, an import of one of these is a cycle
+    loading: Vec<(PathBuf, String)>,
+    pub proto_db: ProtoDatabase,
+    /// Syntax errors in the loaded files, these don't stop the rest of the schema from loading
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl ProtoLoader {
+    /// Imports are resolved against the include paths in order, like `protoc -I`
+    pub fn new(include_paths: Vec<PathBuf>) -> Self {
+        Self {
+            include_paths,
+            loaded: HashSet::new(),
+            loading: Vec::new(),
+            proto_db: ProtoDatabase::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Loads every `.proto` file and descriptor set below a directory
+    pub fn load_all(&mut self, dir: &Path) -> Result<(), LoadError> {
+        let mut files = Vec::new();
+        collect_proto_files(dir, &mut files).map_err(|e| LoadError::Io(dir.to_path_buf(), e))?;
+        files.sort();
+
+        for file in files {
+            self.load(&file)?;
+        }
+
+        Ok(())
+    }
+
+    /// Loads a file and everything it imports, files that were already loaded are skipped
+    pub fn load(&mut self, path: &Path) -> Result<(), LoadError> {
+        let canonical = fs::canonicalize(path).map_err(|e| LoadError::Io(path.to_path_buf(), e))?;
+        let name = self.display_name(&canonical);
+
+        if let Some(start) = self.loading.iter().position(|(loading, _)| *loading == canonical) {
+            let mut chain = self.loading[start..].iter().map(|(_, name)| name.clone()).collect::<Vec<_>>();
+            chain.push(name);
+            return Err(LoadError::ImportCycle(chain));
+        }
+
+        if self.loaded.contains(&canonical) {
+            return Ok(());
+        }
+
+        if is_descriptor_set(&canonical) {
+            return self.load_descriptor_set(path, canonical);
+        }
+
+        if is_dump(&canonical) {
+            return self.load_dump(path, canonical, name);
+        }
+
+        let source = fs::read_to_string(&canonical).map_err(|e| LoadError::Io(path.to_path_buf(), e))?;
+        let mut parsed = match parser::parse_proto_into(&mut self.proto_db, &source, &name) {
+            Ok(parsed) => parsed,
+            Err(diagnostic) => {
+                self.diagnostics.push(diagnostic);
+                self.loaded.insert(canonical);
+                return Ok(());
+            }
+        };
+        self.diagnostics.append(&mut parsed.diagnostics);
+
+        // The parser reports and skips definitions that are in an earlier file already
+        for definition in parsed.definitions {
+            self.proto_db.definition_files.insert(definition, name.clone());
+        }
+
+        self.loading.push((canonical.clone(), name.clone()));
+
+        for import in parsed.imports {
+            match self.resolve(&import.path) {
+                Some(import_path) => self.load(&import_path)?,
+                // Weak imports are allowed to be missing
+                None if import.kind == ImportKind::Weak => {
+                    warn!("{} weakly imports \"{}\", which was not found", name, import.path);
+                }
+                None => {
+                    return Err(LoadError::MissingImport {
+                        importer: name,
+                        import: import.path,
+                        searched: self.include_paths.clone(),
+                    });
+                }
+            }
+        }
+
+        self.loading.pop();
+        self.loaded.insert(canonical);
+
+        Ok(())
+    }
+
+    /// A descriptor set contains its imports, files that were already loaded from source or another set are skipped
+    fn load_descriptor_set(&mut self, path: &Path, canonical: PathBuf) -> Result<(), LoadError> {
+        let data = fs::read(&canonical).map_err(|e| LoadError::Io(path.to_path_buf(), e))?;
+        let files = descriptor::decode_descriptor_set(&data).map_err(|e| LoadError::Descriptor(path.to_path_buf(), e))?;
+
+        for file in files {
+            if self.proto_db.source_files.contains(&file.name) {
+                continue;
+            }
+
+            for definition in descriptor::register_file(&mut self.proto_db, &file) {
+                self.proto_db.definition_files.insert(definition, file.name.clone());
+            }
+        }
+
+        self.loaded.insert(canonical);
+        Ok(())
+    }
+
+    /// A `dump.cs` has no imports, its message classes are registered directly
+    fn load_dump(&mut self, path: &Path, canonical: PathBuf, name: String) -> Result<(), LoadError> {
+        let source = fs::read_to_string(&canonical).map_err(|e| LoadError::Io(path.to_path_buf(), e))?;
+        let mut parsed = dump::parse_dump_into(&mut self.proto_db, &source, &name);
+        self.diagnostics.append(&mut parsed.diagnostics);
+
+        for definition in parsed.definitions {
+            self.proto_db.definition_files.insert(definition, name.clone());
+        }
+
+        self.loaded.insert(canonical);
+        Ok(())
+    }
+
+    fn resolve(&self, import: &str) -> Option<PathBuf> {
+        self.include_paths.iter()
+            .map(|include_path| include_path.join(import))
+            .find(|path| path.is_file())
+    }
+
+    /// Path relative to the include path containing the file, which is how other files import it.
+    /// When include paths are nested, the innermost one gives the name
+    fn display_name(&self, canonical: &Path) -> String {
+        self.include_paths.iter()
+            .filter_map(|include_path| fs::canonicalize(include_path).ok())
+            .filter_map(|include_path| canonical.strip_prefix(include_path).ok().map(Path::to_path_buf))
+            .min_by_key(|relative| relative.components().count())
+            .unwrap_or_else(|| canonical.to_path_buf())
+            .to_string_lossy()
+            .replace('\\', "/")
+    }
+
+    pub fn into_db(self) -> ProtoDatabase {
+        self.proto_db
+    }
+}
+
+fn collect_proto_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_proto_files(&path, files)?;
+        } else if path.extension().is_some_and(|extension| extension == "proto") || is_descriptor_set(&path) {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn is_descriptor_set(path: &Path) -> bool {
+    path.extension().and_then(|extension| extension.to_str()).is_some_and(|extension| DESCRIPTOR_EXTENSIONS.contains(&extension))
+}
+
+pub fn is_dump(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == DUMP_EXTENSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prototype::ProtoName;
+
+    /// A fresh directory with the given files, named after the test so tests can run in parallel
+    fn temp_tree(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("matcher-loader-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        for (path, source) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn test_import_cycle() {
+        let root = temp_tree("cycle", &[
+            ("a.proto", "import \"b.proto\";\nmessage A {}\n"),
+            ("b.proto", "import \"a.proto\";\nmessage B {}\n"),
+        ]);
+
+        let mut loader = ProtoLoader::new(vec![root.clone()]);
+        match loader.load(&root.join("a.proto")) {
+            Err(LoadError::ImportCycle(chain)) => assert_eq!(chain, ["a.proto", "b.proto", "a.proto"]),
+            other => panic!("expected an import cycle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_missing_import() {
+        let root = temp_tree("missing", &[("a.proto", "import \"missing.proto\";\nmessage A {}\n")]);
+
+        let mut loader = ProtoLoader::new(vec![root.clone()]);
+        match loader.load(&root.join("a.proto")) {
+            Err(LoadError::MissingImport { importer, import, searched }) => {
+                assert_eq!((importer.as_str(), import.as_str()), ("a.proto", "missing.proto"));
+                assert_eq!(searched, [root]);
+            }
+            other => panic!("expected a missing import, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_missing_weak_import() {
+        let root = temp_tree("weak", &[("a.proto", "import weak \"missing.proto\";\nmessage A {}\n")]);
+
+        let mut loader = ProtoLoader::new(vec![root.clone()]);
+        loader.load(&root.join("a.proto")).unwrap();
+        assert!(loader.proto_db.get_message("A").is_some());
+    }
+
+    #[test]
+    fn test_include_path_order() {
+        // Both include paths have a common.proto, the first one listed is the one imported
+        let root = temp_tree("include", &[
+            ("main.proto", "import \"common.proto\";\nmessage Main {}\n"),
+            ("first/common.proto", "message First {}\n"),
+            ("second/common.proto", "message Second {}\n"),
+        ]);
+
+        let mut loader = ProtoLoader::new(vec![root.clone(), root.join("first"), root.join("second")]);
+        loader.load(&root.join("main.proto")).unwrap();
+
+        let db = loader.into_db();
+        assert!(db.get_message("First").is_some() && db.get_message("Second").is_none());
+        assert_eq!(db.definition_files[&ProtoName::lookup(&db, "First")], "common.proto");
+    }
+}
@@ -1,293 +1,575 @@
+mod benchmark;
+mod chain;
+mod cmdid;
+mod cmdscan;
+mod compat;
 mod debug;
+mod descriptor;
+mod diff;
+mod diagnostic;
+mod dump;
+mod emit;
+mod explain;
+mod loader;
+mod matcher;
+mod obfuscate;
 mod parser;
+mod pins;
 mod prototype;
+mod report;
+mod review;
+mod rewrite;
+mod split;
 mod util;
+mod wire;
 
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use cmdid::{CmdIdExport, CmdIdTable};
+use cmdscan::{CmdIdScanner, PeImage, ScriptMetadata};
+use diagnostic::Diagnostic;
+use emit::ProtoEmitter;
 use itertools::Itertools;
-use prototype::{ProtoDatabase, ProtoField, ProtoFieldKind, ProtoMessage, WeakProtoFieldKind};
-use util::TrimIndent;
-use std::collections::HashMap;
-use crate::debug::DebugWithName;
-
-macro_rules! dbg {
-    ($db:expr, $arg:expr) => {
-        ($arg.debug_with_name($db))
-    };
+use loader::ProtoLoader;
+use matcher::Matcher;
+use obfuscate::ObfuscateOptions;
+use pins::{Pin, PinFile};
+use prototype::ProtoDatabase;
+use split::{GroupStrategy, SplitOptions};
+use std::{fs, io::IsTerminal, path::{Path, PathBuf}, process, sync::Mutex};
+use tracing::{error, info, level_filters::LevelFilter, warn};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
+
+#[derive(Parser)]
+#[command(about = "Recover obfuscated protobuf names by matching against a known schema")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    /// Additional directory to resolve imports in (repeatable), searched after the input's own directory
+    #[arg(short = 'I', long = "include", global = true)]
+    include_paths: Vec<PathBuf>,
+    /// Log more on stderr, `-v` for every rule the matcher tries and `-vv` for everything
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+    /// Only log warnings and errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Also write the log as JSON lines to this file, with everything the matcher tries
+    #[arg(long, global = true)]
+    log_file: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Match an obfuscated proto against one with known names and print the translated proto
+    Match {
+        /// Proto file, descriptor set or directory with known names
+        proto_a: PathBuf,
+        /// Obfuscated proto file, descriptor set, Il2CppDumper dump.cs or directory to translate, anything but a proto file is translated into a single file
+        proto_b: PathBuf,
+        /// Write the translated proto to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Also write the name translations (`old -> new`, as read by apply-nt.py)
+        #[arg(long)]
+        translations: Option<PathBuf>,
+        /// Also write the translated schema as a binary FileDescriptorSet
+        #[arg(long)]
+        descriptor_set: Option<PathBuf>,
+        /// Also write a report of what was resolved and what is left for review
+        #[arg(long)]
+        report: Option<PathBuf>,
+        /// Format of the report
+        #[arg(long, value_enum, default_value_t = ReportFormat::Json)]
+        report_format: ReportFormat,
+        /// Manual field mappings (`Message.field_in_b -> field_in_a`) to apply, as written by --review
+        #[arg(long)]
+        pins: Option<PathBuf>,
+        /// Step through the ambiguous fields in the terminal, decisions are added to the pin file
+        #[arg(long, requires = "pins")]
+        review: bool,
+    },
+    /// Match two versions of a schema and list what changed between them
+    Diff {
+        /// Older proto file, descriptor set or directory
+        proto_a: PathBuf,
+        /// Newer proto file, descriptor set, Il2CppDumper dump.cs or directory
+        proto_b: PathBuf,
+        #[arg(long, value_enum, default_value_t = DiffFormat::Text)]
+        format: DiffFormat,
+        /// Write the changes to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Manual field mappings to apply, as for `match`
+        #[arg(long)]
+        pins: Option<PathBuf>,
+    },
+    /// Match two versions of a schema and classify every change as wire-compatible, breaking or to review
+    CheckCompat {
+        /// Older proto file, descriptor set or directory
+        proto_a: PathBuf,
+        /// Newer proto file, descriptor set, Il2CppDumper dump.cs or directory
+        proto_b: PathBuf,
+        #[arg(long, value_enum, default_value_t = DiffFormat::Text)]
+        format: DiffFormat,
+        /// Write the classified changes to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Manual field mappings to apply, as for `match`
+        #[arg(long)]
+        pins: Option<PathBuf>,
+    },
+    /// Match successive versions of a schema, each against all earlier ones, and record the names every identifier had
+    Chain {
+        /// Proto files, descriptor sets, Il2CppDumper dump.cs files or directories, oldest first, the first with known names
+        #[arg(num_args = 2.., required = true)]
+        schemas: Vec<PathBuf>,
+        /// Directory to write the translated versions and history.json to
+        #[arg(short, long, default_value = "chain")]
+        out_dir: PathBuf,
+    },
+    /// Print how the matcher arrived at a name, back to the names both schemas share
+    Explain {
+        /// Proto file, descriptor set or directory with known names
+        proto_a: PathBuf,
+        /// Obfuscated proto file, descriptor set, Il2CppDumper dump.cs or directory
+        proto_b: PathBuf,
+        /// Name to explain, either as it is in b or as it was resolved
+        identifier: String,
+        /// Manual field mappings to apply, as for `match`
+        #[arg(long)]
+        pins: Option<PathBuf>,
+    },
+    /// Write an obfuscated variant of a clean schema together with the true name translation, as ground truth for the matcher
+    Obfuscate {
+        /// Proto file, descriptor set or directory with clean names
+        input: PathBuf,
+        /// Write the obfuscated proto to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// File to write the true translations to (`old -> new`, as written by `match --translations`)
+        #[arg(long)]
+        truth: PathBuf,
+        #[command(flatten)]
+        options: ObfuscateOptions,
+    },
+    /// Obfuscate a clean schema, match it back and report precision and recall per rule
+    Benchmark {
+        /// Proto file, descriptor set or directory with clean names
+        input: PathBuf,
+        /// Number of obfuscated variants, seeded one after another from --seed
+        #[arg(long, default_value_t = 5)]
+        runs: usize,
+        #[arg(long, value_enum, default_value_t = DiffFormat::Text)]
+        format: DiffFormat,
+        /// Write the scores to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        options: ObfuscateOptions,
+    },
+    /// Export the cmd ids of the resolved schema as a proto enum, a JSON map and a Rust const module
+    CmdId {
+        /// Proto file or directory with known names
+        proto_a: PathBuf,
+        /// Obfuscated proto file, directory or Il2CppDumper dump.cs the cmd id table was dumped from
+        proto_b: PathBuf,
+        /// `Class => cmdid` table for proto_b's build, as printed by derive_csreq_ids.py
+        cmd_ids: PathBuf,
+        /// Table of the previous build (named as in proto_a), used to flag changed cmd ids
+        #[arg(long)]
+        previous: Option<PathBuf>,
+        /// Directory to write cmd_id.proto, cmd_id.json and cmd_id.rs to
+        #[arg(short, long, default_value = ".")]
+        out_dir: PathBuf,
+    },
+    /// Recover the `Class => cmdid` table from the SendPacket callers of an x86-64 IL2CPP build, like derive_csreq_ids.py
+    ScanCmdIds {
+        /// GameAssembly.dll of the build
+        binary: PathBuf,
+        /// script.json that Il2CppDumper wrote for the same build
+        script: PathBuf,
+        /// SendPacket method as named in script.json
+        #[arg(long, default_value = cmdscan::SEND_PACKET)]
+        send_packet: String,
+        /// Write the table to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Split a proto file into one file per type, with imports following the type references
+    Split {
+        /// Proto file to split
+        input: PathBuf,
+        /// Output directory for the split files
+        #[arg(short, long, default_value = "proto_split")]
+        out_dir: PathBuf,
+        /// Remove the output directory before splitting
+        #[arg(long)]
+        clean: bool,
+        /// How definitions are grouped into files
+        #[arg(long, value_enum, default_value_t = GroupStrategy::Type)]
+        group: GroupStrategy,
+        /// Feature area prefix for `--group prefix` (repeatable)
+        #[arg(long = "prefix")]
+        prefixes: Vec<String>,
+        /// Package to declare in every file, files are written to the matching directory (default: the input's package)
+        #[arg(long)]
+        package: Option<String>,
+    },
+    /// Merge a directory of proto files (e.g. the output of `split`) back into a single file
+    Merge {
+        /// Directory to load, or a single file whose imports are followed
+        input: PathBuf,
+        /// Write the merged proto to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ReportFormat {
+    Json,
+    Markdown,
+    /// A single page with both schemas side by side, for review in a browser
+    Html,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum DiffFormat {
+    Text,
+    Json,
 }
 
 fn main() {
-    let proto_a = "
-        message SingleField {
-            uint32 number = 1;
-            DupStruct dup_struct = 11;
-        }
+    let cli = Cli::parse();
+    init_logging(&cli);
+
+    match cli.command {
+        Command::Match { proto_a, proto_b, output, translations, descriptor_set, report, report_format, pins, review } => {
+            // A pin file is created by the first review
+            let mut pin_file = match &pins {
+                Some(path) if path.exists() => PinFile::parse(&read_source(path)),
+                _ => PinFile::default(),
+            };
 
-        message TestMessage {
-            uint32 number = 2;
-            uint32 number_2 = 3;
-            repeated string string_list = 4;
-            AnotherInfo another_info = 15;
-            PropExtraInfo extra_info = 10;
-            DupStruct dup_struct = 11;
-            DupStruct dup_struct_2 = 12;
-            map<string, float> float_map = 6;
-        }
-    ".trim_indent();
+            let mut matcher = match_protos(load_schema(&proto_a, &cli.include_paths), load_schema(&proto_b, &cli.include_paths), pin_file.pins.clone());
 
-    let proto_b = "
-        message SingleField {
-            uint32 JNLOABDHEIH = 53;
-            OQUREKAMCNF QWEUIFSDNAX = 4;
-        }
+            if let Some(pin_path) = pins.filter(|_| review) {
+                review::review(&mut matcher, &mut pin_file, &pin_path).unwrap_or_else(|e| fail(&pin_path, e));
+            }
 
-        message TestMessage {
-            uint32 JNLOABDHEIH = 1;
-            uint32 GWFIOREJPIC = 2;
-            OQUREKAMCNF QWEUIFSDNAX = 4;
-            OQUREKAMCNF PQIOSKXMANZ = 5;
-            repeated string PPAMLEBAFPI = 6;
-            QPIWIALSKMX CIEGHGBOIEO = 3;
-            AnotherInfo another_info = 16;
-            map<string, float> APOCINBFAAB = 7;
-            uint64 PDOQWIJLSAM = 9;
-        }
-    ".trim_indent();
+            if let Some(report) = report {
+                let match_report = matcher.report();
+                let contents = match report_format {
+                    ReportFormat::Json => match_report.to_json(),
+                    ReportFormat::Markdown => match_report.to_markdown(),
+                    ReportFormat::Html => match_report.to_html(),
+                };
+                write_output(&report, &contents);
+            }
 
-    let proto_db_a = parser::parse_proto(&proto_a);
-    // println!("{:#?}", proto_db_a);
+            let proto_db_b = matcher.into_db_b();
 
-    let proto_db_b = parser::parse_proto(&proto_b);
-    // println!("{:#?}", proto_db_b);
+            let name_translation = proto_db_b.generate_nametranslation();
 
-    let mut matcher = Matcher::new(proto_db_a, proto_db_b);
+            if let Some(translations) = translations {
+                let lines = name_translation.iter()
+                    .filter(|(old_name, new_name)| old_name != new_name)
+                    .map(|(old_name, new_name)| format!("{} -> {}", old_name, new_name))
+                    .sorted()
+                    .join("\n");
+                write_output(&translations, &lines);
+            }
 
-    // Run match loop until settled
-    // TODO: Maybe can optimize using a dependency graph?
-    // Would need to make sure to include field names in the dependency graph as well since those can cross-reference
-    loop {
-        let mut did_resolve = false;
+            if let Some(descriptor_set) = descriptor_set {
+                let data = descriptor::encode_descriptor_set(&descriptor::export(&proto_db_b));
+                fs::write(&descriptor_set, data).unwrap_or_else(|e| fail(&descriptor_set, e));
+            }
 
-        did_resolve |= matcher.full_static_match("SingleField");
-        did_resolve |= matcher.full_static_match("TestMessage");
+            // Print translated proto_b
+            let translated_proto_b = translate_schema(&proto_b, &proto_db_b);
 
-        if !did_resolve {
-            break;
+            match output {
+                Some(output) => write_output(&output, &translated_proto_b),
+                None => println!("{}", translated_proto_b),
+            }
         }
-    }
+        Command::Chain { schemas, out_dir } => {
+            let versions = schemas.iter()
+                .map(|path| (path.display().to_string(), load_schema(path, &cli.include_paths)))
+                .collect();
+            let chain = chain::Chain::new(versions);
+
+            fs::create_dir_all(&out_dir).unwrap_or_else(|e| fail(&out_dir, e));
+            for (index, (path, version)) in schemas.iter().zip(&chain.versions).enumerate().skip(1) {
+                let stem = path.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+                write_output(&out_dir.join(format!("{}-{}.proto", index, stem)), &translate_schema(path, &version.proto_db));
+            }
+            write_output(&out_dir.join("history.json"), &chain.history_json());
 
-    let name_translation = matcher.into_db_b().generate_nametranslation();
+            info!("Wrote {} translated versions to {}", chain.versions.len() - 1, out_dir.display());
+        }
+        Command::Diff { proto_a, proto_b, format, output, pins } => {
+            let pins = pins.map(|path| PinFile::parse(&read_source(&path)).pins).unwrap_or_default();
+            let (proto_db_a, proto_db_b) = match_protos(load_schema(&proto_a, &cli.include_paths), load_schema(&proto_b, &cli.include_paths), pins).into_dbs();
+
+            let diff = diff::SchemaDiff::new(&proto_db_a, &proto_db_b);
+            let contents = match format {
+                DiffFormat::Text => diff.to_text(),
+                DiffFormat::Json => diff.to_json(),
+            };
 
-    // Print translated proto_b
-    let mut translated_proto_b = proto_b.clone();
-    for (old_name, new_name) in name_translation {
-        translated_proto_b = translated_proto_b.replace(&old_name, &new_name);
-    }
-    println!("{}", translated_proto_b);
-}
+            match output {
+                Some(output) => write_output(&output, &contents),
+                None => print!("{}", contents),
+            }
+        }
+        Command::CheckCompat { proto_a, proto_b, format, output, pins } => {
+            let pins = pins.map(|path| PinFile::parse(&read_source(&path)).pins).unwrap_or_default();
+            let (proto_db_a, proto_db_b) = match_protos(load_schema(&proto_a, &cli.include_paths), load_schema(&proto_b, &cli.include_paths), pins).into_dbs();
+
+            let diff = diff::SchemaDiff::new(&proto_db_a, &proto_db_b);
+            let report = compat::CompatReport::new(&diff, &proto_db_a, &proto_db_b);
+            let contents = match format {
+                DiffFormat::Text => report.to_text(),
+                DiffFormat::Json => report.to_json(),
+            };
 
-struct Matcher {
-    proto_db_a: ProtoDatabase,
-    proto_db_b: ProtoDatabase,
-}
+            match output {
+                Some(output) => write_output(&output, &contents),
+                None => print!("{}", contents),
+            }
+        }
+        Command::Explain { proto_a, proto_b, identifier, pins } => {
+            let pins = pins.map(|path| PinFile::parse(&read_source(&path)).pins).unwrap_or_default();
+            let matcher = match_protos(load_schema(&proto_a, &cli.include_paths), load_schema(&proto_b, &cli.include_paths), pins);
 
-impl Matcher {
-    fn into_db_b(self) -> ProtoDatabase {
-        self.proto_db_b
-    }
-}
+            print!("{}", explain::explain(&matcher, &identifier));
+        }
+        Command::Obfuscate { input, output, truth, options } => {
+            let obfuscated = obfuscate::obfuscate(&load_schema(&input, &cli.include_paths), &options);
+            write_output(&truth, &obfuscated.truth_lines());
+
+            let proto = consolidate(&obfuscated.proto_db);
+            match output {
+                Some(output) => write_output(&output, &proto),
+                None => println!("{}", proto),
+            }
+        }
+        Command::Benchmark { input, runs, format, output, options } => {
+            let benchmark = benchmark::Benchmark::run(&load_schema(&input, &cli.include_paths), &options, runs);
+            let contents = match format {
+                DiffFormat::Text => benchmark.to_text(),
+                DiffFormat::Json => benchmark.to_json(),
+            };
 
-impl Matcher {
-    fn new(proto_db_a: ProtoDatabase, proto_db_b: ProtoDatabase) -> Self {
-        Self {
-            proto_db_a,
-            proto_db_b,
+            match output {
+                Some(output) => write_output(&output, &contents),
+                None => print!("{}", contents),
+            }
         }
-    }
+        Command::CmdId { proto_a, proto_b, cmd_ids, previous, out_dir } => {
+            let proto_db_b = match_protos(load_schema(&proto_a, &cli.include_paths), load_schema(&proto_b, &cli.include_paths), Vec::new()).into_db_b();
+
+            let table = CmdIdTable::parse(&read_source(&cmd_ids));
+            let previous = previous.map(|path| CmdIdTable::parse(&read_source(&path)));
 
-    fn remove_resolved_fields(&self, mut message_a: ProtoMessage, mut message_b: ProtoMessage) -> (ProtoMessage, ProtoMessage) {
-        let mut resolved_field_names = Vec::new();
-        for field in &message_b.fields {
-            if self.proto_db_b.is_resolved(&field.name) {
-                resolved_field_names.push(field.name.name(&self.proto_db_b));
+            let export = CmdIdExport::new(&table, &proto_db_b, previous.as_ref());
+            export.log_flags();
+
+            fs::create_dir_all(&out_dir).unwrap_or_else(|e| fail(&out_dir, e));
+            write_output(&out_dir.join("cmd_id.proto"), &export.to_proto());
+            write_output(&out_dir.join("cmd_id.json"), &export.to_json());
+            write_output(&out_dir.join("cmd_id.rs"), &export.to_rust());
+        }
+        Command::ScanCmdIds { binary, script, send_packet, output } => {
+            let image = PeImage::parse(fs::read(&binary).unwrap_or_else(|e| fail(&binary, e))).unwrap_or_else(|e| fail(&binary, e));
+            let script_metadata = ScriptMetadata::parse(&read_source(&script)).unwrap_or_else(|e| fail(&script, e));
+
+            let calls = CmdIdScanner::new(&image, &script_metadata).scan(&send_packet).unwrap_or_else(|e| fail(&script, e));
+
+            let (mut warnings, mut errors) = (0, 0);
+            let mut table = Vec::new();
+            for call in calls {
+                match call.result {
+                    Ok((class, cmd_id)) => table.push(format!("{} => {}", class, cmd_id)),
+                    Err(problem) if problem.is_error() => {
+                        error!("{}, cannot extract from {} at {:#x}", problem, call.caller, call.address);
+                        errors += 1;
+                    }
+                    Err(problem) => {
+                        warn!("{}, cannot extract from {} at {:#x}", problem, call.caller, call.address);
+                        warnings += 1;
+                    }
+                }
             }
+
+            // A message sent from several places is listed once
+            let table = table.into_iter().unique().join("\n");
+            match output {
+                Some(output) => write_output(&output, &table),
+                None => println!("{}", table),
+            }
+
+            info!("Resolved {} cmd ids, {} warnings, {} errors", table.lines().count(), warnings, errors);
         }
+        Command::Split { input, out_dir, clean, group, prefixes, package } => {
+            let mut proto_db = ProtoDatabase::new();
+            match parser::parse_proto_into(&mut proto_db, &read_source(&input), &input.display().to_string()) {
+                Ok(parsed) => report_diagnostics(&parsed.diagnostics),
+                Err(diagnostic) => {
+                    error!("{}", diagnostic);
+                    process::exit(1);
+                }
+            }
 
-        // TODO: Probably a better way to do this
-        message_a.fields.retain(|field| !resolved_field_names.contains(&field.name.name(&self.proto_db_a)));
-        message_b.fields.retain(|field| !resolved_field_names.contains(&field.name.name(&self.proto_db_b)));
+            if clean && out_dir.exists() {
+                fs::remove_dir_all(&out_dir).unwrap_or_else(|e| fail(&out_dir, e));
+            }
+            fs::create_dir_all(&out_dir).unwrap_or_else(|e| fail(&out_dir, e));
 
-        (message_a, message_b)
-    }
+            let package = package.or_else(|| proto_db.package.clone());
+            let options = SplitOptions { strategy: group, prefixes, package };
 
-    fn full_static_match(&mut self, message_name: &str) -> bool {
-        let mut did_resolve = false;
-        loop {
-            let attempt = self.static_match(message_name);
+            let files = split::split(&proto_db, &options);
+            for file in &files {
+                let path = out_dir.join(file.path());
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).unwrap_or_else(|e| fail(parent, e));
+                }
+                write_output(&path, &file.render(&proto_db));
 
-            if !attempt {
-                return did_resolve;
+                if group != GroupStrategy::Prefix && file.definitions.len() > 1 {
+                    info!("Circular dependency combined into {}", file.path());
+                }
             }
 
-            if attempt {
-                did_resolve = true;
+            info!("Split {} into {} files in {}", input.display(), files.len(), out_dir.display());
+        }
+        Command::Merge { input, output } => {
+            let merged = consolidate(&load_schema(&input, &cli.include_paths));
+
+            match output {
+                Some(output) => write_output(&output, &merged),
+                None => println!("{}", merged),
             }
         }
     }
+}
 
-    fn static_match(&mut self, message_name: &str) -> bool {
-        let message_a = self.proto_db_a.get_message(message_name).unwrap();
-        let message_b = self.proto_db_b.get_message(message_name).unwrap();
-
-        // Remove fields that are already fully resolved in message_b
-        let (message_a, message_b) = self.remove_resolved_fields(message_a, message_b);
+/// Logs to stderr at the level picked on the command line, stdout is left to the outputs
+fn init_logging(cli: &Cli) {
+    let level = match (cli.quiet, cli.verbose) {
+        (true, _) => LevelFilter::WARN,
+        (false, 0) => LevelFilter::INFO,
+        (false, 1) => LevelFilter::DEBUG,
+        (false, _) => LevelFilter::TRACE,
+    };
 
-        // Group fields by their type
-        let fields_by_weak_type_a = self.group_fields_by_weak_type(&message_a.fields);
-        // let fields_by_weak_type_b = self.group_fields_by_weak_type(&message_b.fields);
+    let stderr = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .without_time()
+        .with_target(false)
+        .with_filter(level);
+
+    let file = cli.log_file.as_ref().map(|path| {
+        let file = fs::File::create(path).unwrap_or_else(|e| {
+            eprintln!("Error: {}: {}", path.display(), e);
+            process::exit(1);
+        });
+
+        tracing_subscriber::fmt::layer()
+            .json()
+            .with_writer(Mutex::new(file))
+            .with_filter(level.max(LevelFilter::DEBUG))
+    });
+
+    tracing_subscriber::registry().with(stderr).with(file).init();
+}
 
-        // let fields_by_strong_type_a: Vec<_> = self.group_fields_by_type(&message_a.fields).into_iter().collect();
-        let fields_by_strong_type_b: Vec<_> = self.group_fields_by_type(&message_b.fields).into_iter().collect();
+/// Runs the matcher to a fixpoint, proto_b's database then has every name it could resolve
+fn match_protos(proto_db_a: ProtoDatabase, proto_db_b: ProtoDatabase, pins: Vec<Pin>) -> Matcher {
+    let mut matcher = Matcher::new(proto_db_a, proto_db_b);
+    for pin in pins {
+        matcher.add_pin(pin);
+    }
+    matcher.run();
 
-        // TODO: Even when we have a strong match, we should probably still check sub-type structure 
+    matcher
+}
 
-        let mut did_resolve = false;
+/// Loads a proto file, or every proto file in a directory, together with everything they import
+fn load_schema(path: &Path, include_paths: &[PathBuf]) -> ProtoDatabase {
+    let root = if path.is_dir() {
+        path
+    } else {
+        path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."))
+    };
 
-        macro_rules! resolve {
-            ($a:expr, $b:expr) => {
-                if $a.try_resolve_in(&self.proto_db_a, &mut self.proto_db_b, &$b).is_ok() {
-                    did_resolve = true;
-                }
-            };
-        }
+    let mut loader = ProtoLoader::new(std::iter::once(root.to_path_buf()).chain(include_paths.iter().cloned()).collect());
+    let result = if path.is_dir() {
+        loader.load_all(path)
+    } else {
+        loader.load(path)
+    };
 
-        // Check by weak type first
-        for (type_name, fields_b) in &fields_by_strong_type_b {
-            if let Some(fields_a_weak) = fields_by_weak_type_a.get(&WeakProtoFieldKind::from(*type_name)) {
-                // Check for the simple case where there is only one field of this type in the other proto
-                if fields_b.len() == 1 {
-                    // Can directly match fields that are unique by weak type (only one Message or primitive for this type)
-                    if fields_a_weak.len() == 1 {
-                        println!("Matched unique fields by weak type:");
-                        println!("  {} -> {}", dbg!(&self.proto_db_a, fields_a_weak[0].name), dbg!(&self.proto_db_b, fields_b[0].name));
+    if let Err(e) = result {
+        error!("{}", e);
+        process::exit(1);
+    }
 
-                        resolve!(fields_a_weak[0], fields_b[0]);
+    report_diagnostics(&loader.diagnostics);
+    loader.into_db()
+}
 
-                        continue;
-                    }
-                }
+fn report_diagnostics(diagnostics: &[Diagnostic]) {
+    for diagnostic in diagnostics {
+        warn!("{}", diagnostic);
+    }
 
-                if type_name.is_type_ref() {
-                    // Match occurrence patterns
-                    // e.g. 1 occurrence of type A, 2 occurrences of type B
-                    //   But occurrences count must be unique, otherwise it's ambiguous
-                    //   e.g. 2 occurrences of type A, 2 occurrences of type B -> ambiguous
-                    //     But if in the same message there is only 1 occurrance of type C, then that one can be decided
-
-                    // TODO: This can probably be done outside of the loop
-                    let a_chunks = fields_a_weak
-                        .iter().chunk_by(|el| el.field_type)
-                        .into_iter()
-                        .map(|(_key, chunk)| chunk.map(|el| *el).collect::<Vec<_>>())
-                        .collect::<Vec<_>>();
-
-                    let a_chunks_by_occurrence = a_chunks.into_iter()
-                        .fold(HashMap::new(), |mut map, chunk| {
-                            map.entry(chunk.len())
-                               .or_insert_with(Vec::new)
-                               .push(chunk);
-                            map
-                        });
-
-                    let len_b = fields_b.len();
-                    if let Some(a_chunks) = a_chunks_by_occurrence.get(&len_b) {
-                        if a_chunks.len() == 1 {
-                            if len_b == 1 {
-                                // Direct match
-                                println!("Direct match: {}", dbg!(&self.proto_db_a, a_chunks[0]));
-
-                                resolve!(a_chunks[0][0], fields_b[0]);
-                            } else {
-                                // Can resolve type, but field names can only be resolved by data-match
-                                println!("Occurrence match requires data-match: {}", dbg!(&self.proto_db_a, a_chunks[0]));
-
-                                // Only need to resolve first field's type since they are all the same type
-                                let first_field = &a_chunks[0][0];
-                                let b_type = fields_b[0].field_type.inner_type();
-                                
-                                resolve!(first_field.field_type.inner_type(), b_type);
-                            }
-                        } else {
-                            // TODO: If type names are resolved, we can try to match based on that
-                            let b_fields_type = fields_b[0].field_type;
-                            for a_chunk in a_chunks {
-                                let a_fields_type = a_chunk[0].field_type;
-                                
-                                // TODO: Can maybe try resolve in negative case (1 resolved, 1 not resolved << Matching)
-                                // Ex:
-                                //   TypeA a_field = 1;
-                                //   TypeB b_field = 3;
-                                //
-                                //   TypeA unknown1 = 4;
-                                //   UNK_T unknown3 = 6; << Can be resolved
-                                //
-                                // Theoretically it should resolve on loop, but as an optimization we should detect this
-                                
-                                if a_fields_type.eq_resolved_type(&self.proto_db_a, &b_fields_type, &self.proto_db_b) {
-                                    if len_b == 1 {
-                                        println!("Matched by resolved type: {}", dbg!(&self.proto_db_a, a_chunk));
-                                        resolve!(a_chunk[0], fields_b[0]);
-                                    } else {
-                                        println!("Matched by resolved type, but still ambiguous, requires data-match: {}", dbg!(&self.proto_db_a, a_chunk));
-                                    }
-                                }
-                            }
-
-                            // TODO: When ambiguous, try to match subtype structures to resolve (only if structures are unique)
-                            //       For now, we should not allow variation in structure for resolution. 
-                            //       In the future, we can maybe implement confidence-based fuzzy match for sub-structures
-
-                            println!("Ambiguous match by occurrence: {}", dbg!(&self.proto_db_a, a_chunks));
-                        }
-                    } else {
-                        println!("No match by occurrence: {}", dbg!(&self.proto_db_a, a_chunks_by_occurrence));
-                    }
-                } else {
-                    // Primitive type, can't be matched any further statically
-                    println!("Primitive type with multiple fields, can't be matched any further statically: {}", dbg!(&self.proto_db_b, fields_b));
-                }
+    if !diagnostics.is_empty() {
+        warn!("{} problem(s) found, the affected definitions were skipped", diagnostics.len());
+    }
+}
 
-            } else {
-                // New field in b, nothing we can do
-                println!("Found field(s) with new type in b: {}", dbg!(&self.proto_db_b, fields_b));
-            }
-        }
+/// All definitions of a database as a single file
+fn consolidate(proto_db: &ProtoDatabase) -> String {
+    let packages = proto_db.file_packages.values().sorted().dedup().collect::<Vec<_>>();
+    if packages.len() > 1 {
+        warn!("The files are in {} packages ({}), everything is emitted into {}", packages.len(), packages.iter().join(", "), proto_db.package.as_deref().unwrap_or_default());
+    }
 
-        return did_resolve;
+    let mut emitter = ProtoEmitter::new(proto_db);
+    emitter.emit_header();
+    if let Some(package) = &proto_db.package {
+        emitter.emit_package(package);
     }
+    emitter.emit_options(&proto_db.file_options());
+    emitter.emit_definitions(&proto_db.top_level_definitions());
+    emitter.emit_extensions(&proto_db.child_extensions(None));
+    emitter.finish()
+}
 
-    fn group_fields_by_type(&self, fields: &[ProtoField]) -> HashMap<ProtoFieldKind, Vec<ProtoField>> {
-        let mut grouped = HashMap::new();
-        for field in fields {
-            grouped.entry(field.field_type.clone())
-                .or_insert_with(Vec::new)
-                .push(field.clone());
-        }
-        grouped
+/// The translated schema, as a rewrite of the source when it was a single proto file
+fn translate_schema(path: &Path, proto_db: &ProtoDatabase) -> String {
+    if path.is_dir() || loader::is_descriptor_set(path) || loader::is_dump(path) {
+        // There is no single source to rewrite, so emit the resolved definitions instead
+        consolidate(proto_db)
+    } else {
+        // The file is the first one the loader read
+        rewrite::rewrite_source(&read_source(path), proto_db, 0)
     }
+}
 
-    fn group_fields_by_weak_type(&self, fields: &[ProtoField]) -> HashMap<WeakProtoFieldKind, Vec<ProtoField>> {
-        let mut grouped = HashMap::new();
-        for field in fields {
-            grouped.entry(field.field_type.clone().into())
-                .or_insert_with(Vec::new)
-                .push(field.clone());
-        }
+fn read_source(path: &Path) -> String {
+    fs::read_to_string(path).unwrap_or_else(|e| fail(path, e))
+}
 
-        // Sort by type name
-        for fields in grouped.values_mut() {
-            fields.sort_by_key(|field| field.field_type.inner_type().clone());
-        }
+fn write_output(path: &Path, contents: &str) {
+    fs::write(path, contents).unwrap_or_else(|e| fail(path, e))
+}
 
-        grouped
-    }
+fn fail(path: &Path, error: impl std::fmt::Display) -> ! {
+    error!("{}: {}", path.display(), error);
+    process::exit(1);
 }
@@ -0,0 +1,738 @@
+use itertools::Itertools;
+use crate::prototype::{resolve_name, DefinitionRef, ProtoDatabase, ProtoResolutionError, ProtoField, ProtoFieldKind, ProtoLabel, ProtoMessage, ProtoName, ProtoOption, ProtoRpc, ProtoType, WeakProtoFieldKind};
+use crate::pins::{Pin, PinError};
+use crate::report::MatchReport;
+use std::collections::{hash_map::Entry, HashMap};
+use tracing::{debug, info, info_span, warn};
+use crate::debug::DebugWithName;
+
+macro_rules! dbg {
+    ($db:expr, $arg:expr) => {
+        ($arg.debug_with_name($db))
+    };
+}
+
+/// Field options that survive obfuscation and can tell fields of the same type apart, `default` only for scalar types
+const SIGNAL_OPTIONS: [&str; 3] = ["packed", "deprecated", "default"];
+
+/// The static matching rules, in the order they are tried
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MatchRule {
+    UniqueWeakType,
+    UniqueOccurrence,
+    OccurrenceType,
+    ResolvedType,
+    Signature,
+    ServiceMethods,
+    RpcSignature,
+    Manual,
+}
+
+impl MatchRule {
+    pub fn key(&self) -> &'static str {
+        match self {
+            MatchRule::UniqueWeakType => "unique_weak_type",
+            MatchRule::UniqueOccurrence => "unique_occurrence",
+            MatchRule::OccurrenceType => "occurrence_type",
+            MatchRule::ResolvedType => "resolved_type",
+            MatchRule::Signature => "signature",
+            MatchRule::ServiceMethods => "service_methods",
+            MatchRule::RpcSignature => "rpc_signature",
+            MatchRule::Manual => "manual",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            MatchRule::UniqueWeakType => "Only field of its type",
+            MatchRule::UniqueOccurrence => "Unique number of fields of a type",
+            MatchRule::OccurrenceType => "Type of an occurrence group, fields still need data",
+            MatchRule::ResolvedType => "Only field of an already resolved type",
+            MatchRule::Signature => "Unique label and options",
+            MatchRule::ServiceMethods => "Service by its methods",
+            MatchRule::RpcSignature => "Method by request and response types",
+            MatchRule::Manual => "Pinned by hand",
+        }
+    }
+}
+
+/// A name the matcher resolved, and how
+#[derive(Debug, Clone)]
+pub struct MatchEvent {
+    pub rule: MatchRule,
+    /// Fixpoint round it was resolved in, starting at 1
+    pub round: usize,
+    /// Message the field is in, `None` for types, services and methods
+    pub message: Option<String>,
+    pub name_a: String,
+    /// Name in b before it was resolved
+    pub name_b: String,
+    /// Names of a the rule picked from
+    pub considered_a: Vec<String>,
+    /// Names of b the rule picked from, as they were before resolution
+    pub considered_b: Vec<String>,
+    /// Names of a that had to be resolved already for the rule to apply
+    pub depends_on: Vec<String>,
+}
+
+/// A name the matcher found for b but could not give, another identifier in the same scope has it already
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameConflict {
+    /// Message the field is in, `None` for types, services and methods
+    pub message: Option<String>,
+    pub name_a: String,
+    /// Name in b of the identifier that stayed unresolved
+    pub name_b: String,
+}
+
+pub struct Matcher {
+    proto_db_a: ProtoDatabase,
+    proto_db_b: ProtoDatabase,
+    events: Vec<MatchEvent>,
+    conflicts: Vec<NameConflict>,
+    /// Pins that could not be applied yet
+    pins: Vec<Pin>,
+    round: usize,
+}
+
+impl Matcher {
+    pub fn into_db_b(self) -> ProtoDatabase {
+        self.proto_db_b
+    }
+
+    /// a is only read, b has every name that was resolved
+    pub fn into_dbs(self) -> (ProtoDatabase, ProtoDatabase) {
+        (self.proto_db_a, self.proto_db_b)
+    }
+
+    pub fn db_b(&self) -> &ProtoDatabase {
+        &self.proto_db_b
+    }
+
+    /// Every resolution in the order it was made
+    pub fn events(&self) -> &[MatchEvent] {
+        &self.events
+    }
+
+    /// What was resolved, and what is left for a data match or manual review
+    pub fn report(&self) -> MatchReport {
+        MatchReport::new(&self.proto_db_a, &self.proto_db_b, &self.events, &self.conflicts)
+    }
+}
+
+impl Matcher {
+    pub fn new(proto_db_a: ProtoDatabase, proto_db_b: ProtoDatabase) -> Self {
+        Self {
+            proto_db_a,
+            proto_db_b,
+            events: Vec::new(),
+            conflicts: Vec::new(),
+            pins: Vec::new(),
+            round: 0,
+        }
+    }
+
+    /// Queues a manual mapping, applied on the next `run` once its message is known in b
+    pub fn add_pin(&mut self, pin: Pin) {
+        self.pins.push(pin);
+    }
+
+    fn record(&mut self, rule: MatchRule, message: Option<&str>, (name_a, name_b): (&ProtoName, &ProtoName), (considered_a, considered_b): (&[ProtoName], &[ProtoName]), depends_on: Vec<String>) {
+        self.events.push(MatchEvent {
+            rule,
+            round: self.round,
+            message: message.map(str::to_string),
+            name_a: name_a.name(&self.proto_db_a),
+            name_b: self.proto_db_b.original_name(name_b),
+            considered_a: considered_a.iter().map(|name| name.name(&self.proto_db_a)).collect(),
+            considered_b: considered_b.iter().map(|name| self.proto_db_b.original_name(name)).collect(),
+            depends_on,
+        });
+    }
+
+    /// Whether a resolution went through, one refused for a taken name is kept for the report (once, rules retry every round).
+    /// `pairs` are the names that were being resolved, a field's type is resolved along with it
+    fn check_resolution(&mut self, message: Option<&str>, pairs: &[(ProtoName, ProtoName)], result: Result<(), ProtoResolutionError>) -> bool {
+        let Err(ProtoResolutionError::NameTaken(name)) = result else {
+            return result.is_ok();
+        };
+
+        let name_b = pairs.iter()
+            .find(|(name_a, _)| name_a.name(&self.proto_db_a) == name)
+            .or(pairs.first())
+            .map(|(_, name_b)| name_b.name(&self.proto_db_b))
+            .unwrap_or_default();
+        let conflict = NameConflict { message: message.map(str::to_string), name_a: name, name_b };
+        if !self.conflicts.contains(&conflict) {
+            warn!("Cannot resolve {} to {}{}: the name is taken", conflict.name_b, conflict.name_a, message.map(|message| format!(" in {}", message)).unwrap_or_default());
+            self.conflicts.push(conflict);
+        }
+        false
+    }
+
+    /// Resolves a field of b to a's and records it, along with the field's type when that is resolved with it
+    fn resolve_field(&mut self, rule: MatchRule, message_name: &str, field_a: &ProtoField, field_b: &ProtoField, (considered_a, considered_b): (&[ProtoField], &[ProtoField])) -> bool {
+        let type_a = *field_a.field_type.inner_type();
+        let type_b = *field_b.field_type.inner_type();
+
+        // A type that was resolved before is a premise, one that is resolved now follows from the field
+        let mut depends_on = vec![message_name.to_string()];
+        let type_resolved_before = match type_b {
+            ProtoType::Type(name) => self.proto_db_b.is_resolved(&name),
+            _ => true,
+        };
+        if let (ProtoType::Type(name), true) = (type_b, type_resolved_before) {
+            depends_on.push(name.name(&self.proto_db_b));
+        }
+
+        let result = field_a.try_resolve_in(&self.proto_db_a, &mut self.proto_db_b, field_b);
+        let mut pairs = vec![(field_a.name, field_b.name)];
+        if let (ProtoType::Type(name_a), ProtoType::Type(name_b)) = (type_a, type_b) {
+            pairs.push((name_a, name_b));
+        }
+        if !self.check_resolution(Some(message_name), &pairs, result) {
+            return false;
+        }
+
+        let considered_a = considered_a.iter().map(|field| field.name).collect::<Vec<_>>();
+        let considered_b = considered_b.iter().map(|field| field.name).collect::<Vec<_>>();
+        self.record(rule, Some(message_name), (&field_a.name, &field_b.name), (&considered_a, &considered_b), depends_on);
+
+        if let (ProtoType::Type(name_a), ProtoType::Type(name_b), false) = (type_a, type_b, type_resolved_before) {
+            let depends_on = vec![message_name.to_string(), field_a.name.name(&self.proto_db_a)];
+            self.record(rule, None, (&name_a, &name_b), (&[], &[]), depends_on);
+        }
+
+        true
+    }
+
+    /// Run the static match over every message known to both databases until no more names resolve
+    pub fn run(&mut self) {
+        // TODO: Maybe can optimize using a dependency graph?
+        // Would need to make sure to include field names in the dependency graph as well since those can cross-reference
+        loop {
+            self.round += 1;
+            let _span = info_span!("round", round = self.round).entered();
+            let resolved_before = self.events.len();
+
+            // Decisions made by hand go first, the static match builds on them
+            let mut did_resolve = self.apply_pins();
+
+            // Message names in b only become visible once they are resolved, so re-check every round
+            for message_name in self.shared_message_names() {
+                did_resolve |= self.full_static_match(&message_name);
+            }
+
+            info!("Resolved {} names", self.events.len() - resolved_before);
+            if !did_resolve {
+                break;
+            }
+        }
+
+        for pin in &self.pins {
+            warn!("Cannot apply pin {}: {}", pin, PinError::MessageNotFound);
+        }
+
+        self.match_services();
+        self.carry_comments();
+        info!("Resolved {} names in {} rounds", self.events.len(), self.round);
+    }
+
+    /// Applies the queued pins whose message is known in b, the others are kept for the next round
+    fn apply_pins(&mut self) -> bool {
+        let _span = info_span!("pins").entered();
+
+        let mut did_resolve = false;
+        for pin in std::mem::take(&mut self.pins) {
+            match self.apply_pin(&pin) {
+                Ok(()) => did_resolve = true,
+                Err(PinError::MessageNotFound) => self.pins.push(pin),
+                Err(e) => warn!("Cannot apply pin {}: {}", pin, e),
+            }
+        }
+        did_resolve
+    }
+
+    /// Applies a manual mapping right away, unlike `add_pin` nothing is queued when it fails
+    pub fn apply_pin(&mut self, pin: &Pin) -> Result<(), PinError> {
+        let message_a = self.proto_db_a.get_message(&pin.message).ok_or(PinError::MessageNotFound)?;
+        let message_b = self.proto_db_b.get_message(&pin.message).ok_or(PinError::MessageNotFound)?;
+
+        let field_a = *message_a.fields.iter()
+            .find(|field| field.name.name(&self.proto_db_a) == pin.field_a)
+            .ok_or_else(|| PinError::FieldNotFound(pin.field_a.clone()))?;
+        let field_b = *message_b.fields.iter()
+            .find(|field| self.proto_db_b.original_name(&field.name) == pin.field_b)
+            .ok_or_else(|| PinError::FieldNotFound(pin.field_b.clone()))?;
+
+        if self.proto_db_b.is_resolved(&field_b.name) {
+            let name = field_b.name.name(&self.proto_db_b);
+            // Resolved the same way by the static match in the meantime
+            return if name == pin.field_a { Ok(()) } else { Err(PinError::AlreadyResolved(name)) };
+        }
+
+        field_a.try_resolve_in(&self.proto_db_a, &mut self.proto_db_b, &field_b).map_err(PinError::Resolution)?;
+        debug!("Pinned field: {} -> {}", dbg!(&self.proto_db_a, field_a.name), dbg!(&self.proto_db_b, field_b.name));
+        self.record(MatchRule::Manual, Some(&pin.message), (&field_a.name, &field_b.name), (&[], &[]), vec![pin.message.clone()]);
+
+        Ok(())
+    }
+
+    /// Resolves methods by their request and response types, then services by the methods they contain
+    fn match_services(&mut self) {
+        let _span = info_span!("services").entered();
+
+        let rpcs_a = service_rpcs(&self.proto_db_a);
+        let rpcs_b = service_rpcs(&self.proto_db_b);
+        self.resolve_rpcs(&rpcs_a, &rpcs_b);
+
+        let services_b = self.proto_db_b.service_db.right_values().cloned().sorted_by_key(|service| (service.span.file, service.span.start_byte)).collect::<Vec<_>>();
+        for service_b in services_b {
+            // The service a's counterparts of the resolved methods are in, if they agree
+            let origins = service_b.rpcs.iter()
+                .filter(|rpc_b| self.proto_db_b.is_resolved(&rpc_b.name))
+                .filter_map(|rpc_b| rpcs_a.iter().find(|(_, rpc_a)| rpc_a.name.name(&self.proto_db_a) == rpc_b.name.name(&self.proto_db_b)))
+                .map(|(service_a, _)| *service_a)
+                .unique()
+                .collect::<Vec<_>>();
+
+            let [service_a] = origins[..] else {
+                continue;
+            };
+
+            let result = resolve_name(&self.proto_db_a, &service_a, &mut self.proto_db_b, &service_b.name);
+            if self.check_resolution(None, &[(service_a, service_b.name)], result) {
+                debug!("Matched service by its methods: {} -> {}", dbg!(&self.proto_db_a, service_a), dbg!(&self.proto_db_b, service_b.name));
+                let methods = service_b.rpcs.iter().filter(|rpc| self.proto_db_b.is_resolved(&rpc.name)).map(|rpc| rpc.name.name(&self.proto_db_b)).collect();
+                self.record(MatchRule::ServiceMethods, None, (&service_a, &service_b.name), (&[], &[]), methods);
+            }
+
+            // Within the service, a signature only has to be unique among its own methods
+            let service_rpcs_a = rpcs_a.iter().filter(|(service, _)| *service == service_a).copied().collect::<Vec<_>>();
+            let service_rpcs_b = service_b.rpcs.iter().map(|rpc| (service_b.name, *rpc)).collect::<Vec<_>>();
+            self.resolve_rpcs(&service_rpcs_a, &service_rpcs_b);
+        }
+    }
+
+    /// Resolves methods whose request/response signature is unique on both sides
+    fn resolve_rpcs(&mut self, rpcs_a: &[(ProtoName, ProtoRpc)], rpcs_b: &[(ProtoName, ProtoRpc)]) {
+        let signatures_a = rpcs_a.iter().map(|(_, rpc)| rpc.signature(&self.proto_db_a)).collect::<Vec<_>>();
+        let signatures_b = rpcs_b.iter().map(|(_, rpc)| rpc.signature(&self.proto_db_b)).collect::<Vec<_>>();
+
+        for ((_, rpc_b), signature) in rpcs_b.iter().zip(&signatures_b) {
+            if signature.is_none() || signatures_b.iter().filter(|other| *other == signature).count() != 1 {
+                continue;
+            }
+
+            let mut candidates = rpcs_a.iter().zip(&signatures_a).filter(|(_, other)| *other == signature);
+            if let (Some(((_, rpc_a), _)), None) = (candidates.next(), candidates.next()) {
+                let result = resolve_name(&self.proto_db_a, &rpc_a.name, &mut self.proto_db_b, &rpc_b.name);
+                if self.check_resolution(None, &[(rpc_a.name, rpc_b.name)], result) {
+                    debug!("Matched rpc by request/response types: {} -> {}", dbg!(&self.proto_db_a, rpc_a.name), dbg!(&self.proto_db_b, rpc_b.name));
+                    let types = [rpc_b.request, rpc_b.response].iter().flat_map(|proto_type| match proto_type {
+                        ProtoType::Type(name) => Some(name.name(&self.proto_db_b)),
+                        _ => None,
+                    }).unique().collect();
+                    let considered_a = rpcs_a.iter().map(|(_, rpc)| rpc.name).collect::<Vec<_>>();
+                    let considered_b = rpcs_b.iter().map(|(_, rpc)| rpc.name).collect::<Vec<_>>();
+                    self.record(MatchRule::RpcSignature, None, (&rpc_a.name, &rpc_b.name), (&considered_a, &considered_b), types);
+                }
+            }
+        }
+    }
+
+    /// Copies comments of a's definitions onto the matching definitions of b, comments b already has are kept
+    fn carry_comments(&mut self) {
+        let mut carried = Vec::new();
+
+        for message_name in self.shared_message_names() {
+            let message_a = self.proto_db_a.get_message(&message_name).unwrap();
+            let message_b = self.proto_db_b.get_message(&message_name).unwrap();
+            carried.push((DefinitionRef::Definition(message_a.name), DefinitionRef::Definition(message_b.name)));
+
+            for field_b in message_b.fields.iter().filter(|field| self.proto_db_b.is_resolved(&field.name)) {
+                let field_name = field_b.name.name(&self.proto_db_b);
+                if let Some(field_a) = message_a.fields.iter().find(|field| field.name.name(&self.proto_db_a) == field_name) {
+                    carried.push((DefinitionRef::Field(message_a.name, field_a.field_number), DefinitionRef::Field(message_b.name, field_b.field_number)));
+                }
+            }
+        }
+
+        for enum_a in self.proto_db_a.enum_db.right_values() {
+            let Some(enum_b) = ProtoName::try_lookup(&self.proto_db_b, &enum_a.name.name(&self.proto_db_a)).and_then(|name| self.proto_db_b.enum_db.get_by_left(&name)) else {
+                continue;
+            };
+
+            carried.push((DefinitionRef::Definition(enum_a.name), DefinitionRef::Definition(enum_b.name)));
+            for value in &enum_a.values {
+                carried.push((DefinitionRef::EnumValue(enum_a.name, value.number), DefinitionRef::EnumValue(enum_b.name, value.number)));
+            }
+        }
+
+        for service_a in self.proto_db_a.service_db.right_values() {
+            let Some(service_b) = ProtoName::try_lookup(&self.proto_db_b, &service_a.name.name(&self.proto_db_a)).and_then(|name| self.proto_db_b.service_db.get_by_left(&name)) else {
+                continue;
+            };
+
+            carried.push((DefinitionRef::Definition(service_a.name), DefinitionRef::Definition(service_b.name)));
+            for rpc_b in &service_b.rpcs {
+                let rpc_name = rpc_b.name.name(&self.proto_db_b);
+                if let Some(rpc_a) = service_a.rpcs.iter().find(|rpc| rpc.name.name(&self.proto_db_a) == rpc_name) {
+                    carried.push((DefinitionRef::Rpc(service_a.name, rpc_a.name), DefinitionRef::Rpc(service_b.name, rpc_b.name)));
+                }
+            }
+        }
+
+        for (target_a, target_b) in carried {
+            let Some(comments) = self.proto_db_a.comments.get(&target_a) else {
+                continue;
+            };
+            if let Entry::Vacant(entry) = self.proto_db_b.comments.entry(target_b) {
+                entry.insert(comments.clone());
+                self.proto_db_b.carried_comments.insert(target_b);
+            }
+        }
+    }
+
+    fn shared_message_names(&self) -> Vec<String> {
+        self.proto_db_a.message_db.left_values()
+            .map(|name| name.name(&self.proto_db_a))
+            .filter(|name| self.proto_db_b.get_message(name).is_some())
+            .sorted()
+            .collect()
+    }
+
+    fn remove_resolved_fields(&self, mut message_a: ProtoMessage, mut message_b: ProtoMessage) -> (ProtoMessage, ProtoMessage) {
+        let mut resolved_field_names = Vec::new();
+        for field in &message_b.fields {
+            if self.proto_db_b.is_resolved(&field.name) {
+                resolved_field_names.push(field.name.name(&self.proto_db_b));
+            }
+        }
+
+        // TODO: Probably a better way to do this
+        message_a.fields.retain(|field| !resolved_field_names.contains(&field.name.name(&self.proto_db_a)));
+        message_b.fields.retain(|field| !resolved_field_names.contains(&field.name.name(&self.proto_db_b)));
+
+        (message_a, message_b)
+    }
+
+    fn full_static_match(&mut self, message_name: &str) -> bool {
+        let _span = info_span!("message", name = message_name).entered();
+
+        let mut did_resolve = false;
+        loop {
+            let attempt = self.static_match(message_name);
+
+            if !attempt {
+                return did_resolve;
+            }
+
+            if attempt {
+                did_resolve = true;
+            }
+        }
+    }
+
+    fn static_match(&mut self, message_name: &str) -> bool {
+        let message_a = self.proto_db_a.get_message(message_name).unwrap();
+        let message_b = self.proto_db_b.get_message(message_name).unwrap();
+
+        // Remove fields that are already fully resolved in message_b
+        let (message_a, message_b) = self.remove_resolved_fields(message_a, message_b);
+
+        // Group fields by their type
+        let fields_by_weak_type_a = self.group_fields_by_weak_type(&message_a.fields);
+        // let fields_by_weak_type_b = self.group_fields_by_weak_type(&message_b.fields);
+
+        // let fields_by_strong_type_a: Vec<_> = self.group_fields_by_type(&message_a.fields).into_iter().collect();
+        let fields_by_strong_type_b: Vec<_> = self.group_fields_by_type(&message_b.fields).into_iter().collect();
+
+        // TODO: Even when we have a strong match, we should probably still check sub-type structure 
+
+        let mut did_resolve = false;
+
+        macro_rules! resolve {
+            ($rule:expr, $a:expr, $b:expr, $considered_a:expr, $considered_b:expr) => {
+                if self.resolve_field($rule, message_name, &$a, &$b, (&$considered_a, &$considered_b)) {
+                    did_resolve = true;
+                }
+            };
+        }
+
+        // Check by weak type first
+        for (type_name, fields_b) in &fields_by_strong_type_b {
+            if let Some(fields_a_weak) = fields_by_weak_type_a.get(&WeakProtoFieldKind::from(*type_name)) {
+                // Check for the simple case where there is only one field of this type in the other proto
+                if fields_b.len() == 1 {
+                    // Can directly match fields that are unique by weak type (only one Message or primitive for this type)
+                    if fields_a_weak.len() == 1 {
+                        debug!("Matched unique fields by weak type: {} -> {}", dbg!(&self.proto_db_a, fields_a_weak[0].name), dbg!(&self.proto_db_b, fields_b[0].name));
+
+                        resolve!(MatchRule::UniqueWeakType, fields_a_weak[0], fields_b[0], fields_a_weak, fields_b);
+
+                        continue;
+                    }
+                }
+
+                if type_name.is_type_ref() {
+                    // Match occurrence patterns
+                    // e.g. 1 occurrence of type A, 2 occurrences of type B
+                    //   But occurrences count must be unique, otherwise it's ambiguous
+                    //   e.g. 2 occurrences of type A, 2 occurrences of type B -> ambiguous
+                    //     But if in the same message there is only 1 occurrance of type C, then that one can be decided
+
+                    // TODO: This can probably be done outside of the loop
+                    let a_chunks = fields_a_weak
+                        .iter().chunk_by(|el| el.field_type)
+                        .into_iter()
+                        .map(|(_key, chunk)| chunk.map(|el| *el).collect::<Vec<_>>())
+                        .collect::<Vec<_>>();
+
+                    let a_chunks_by_occurrence = a_chunks.into_iter()
+                        .fold(HashMap::new(), |mut map, chunk| {
+                            map.entry(chunk.len())
+                               .or_insert_with(Vec::new)
+                               .push(chunk);
+                            map
+                        });
+
+                    let len_b = fields_b.len();
+                    if let Some(a_chunks) = a_chunks_by_occurrence.get(&len_b) {
+                        if a_chunks.len() == 1 {
+                            if len_b == 1 {
+                                // Direct match
+                                debug!("Direct match: {}", dbg!(&self.proto_db_a, a_chunks[0]));
+
+                                resolve!(MatchRule::UniqueOccurrence, a_chunks[0][0], fields_b[0], a_chunks[0], fields_b);
+                            } else {
+                                // Can resolve type, but field names can only be resolved by data-match
+                                debug!("Occurrence match requires data-match: {}", dbg!(&self.proto_db_a, a_chunks[0]));
+
+                                // Only need to resolve first field's type since they are all the same type
+                                let a_type = *a_chunks[0][0].field_type.inner_type();
+                                let b_type = *fields_b[0].field_type.inner_type();
+
+                                if let (ProtoType::Type(a_name), ProtoType::Type(b_name)) = (a_type, b_type) {
+                                    let result = a_type.try_resolve_in(&self.proto_db_a, &mut self.proto_db_b, &b_type);
+                                    if self.check_resolution(Some(message_name), &[(a_name, b_name)], result) {
+                                        let considered_a = a_chunks[0].iter().map(|field| field.name).collect::<Vec<_>>();
+                                        let considered_b = fields_b.iter().map(|field| field.name).collect::<Vec<_>>();
+                                        self.record(MatchRule::OccurrenceType, None, (&a_name, &b_name), (&considered_a, &considered_b), vec![message_name.to_string()]);
+                                        did_resolve = true;
+                                    }
+                                }
+                            }
+                        } else {
+                            // TODO: If type names are resolved, we can try to match based on that
+                            let b_fields_type = fields_b[0].field_type;
+                            for a_chunk in a_chunks {
+                                let a_fields_type = a_chunk[0].field_type;
+                                
+                                // TODO: Can maybe try resolve in negative case (1 resolved, 1 not resolved << Matching)
+                                // Ex:
+                                //   TypeA a_field = 1;
+                                //   TypeB b_field = 3;
+                                //
+                                //   TypeA unknown1 = 4;
+                                //   UNK_T unknown3 = 6; << Can be resolved
+                                //
+                                // Theoretically it should resolve on loop, but as an optimization we should detect this
+                                
+                                if a_fields_type.eq_resolved_type(&self.proto_db_a, &b_fields_type, &self.proto_db_b) {
+                                    if len_b == 1 {
+                                        debug!("Matched by resolved type: {}", dbg!(&self.proto_db_a, a_chunk));
+                                        resolve!(MatchRule::ResolvedType, a_chunk[0], fields_b[0], a_chunk, fields_b);
+                                    } else {
+                                        debug!("Matched by resolved type, but still ambiguous, requires data-match: {}", dbg!(&self.proto_db_a, a_chunk));
+
+                                        for (field_a, field_b) in self.match_by_signature(message_a.name, a_chunk, message_b.name, fields_b) {
+                                            debug!("Matched by label and options: {} -> {}", dbg!(&self.proto_db_a, field_a.name), dbg!(&self.proto_db_b, field_b.name));
+                                            resolve!(MatchRule::Signature, field_a, field_b, a_chunk, fields_b);
+                                        }
+                                    }
+                                }
+                            }
+
+                            // TODO: When ambiguous, try to match subtype structures to resolve (only if structures are unique)
+                            //       For now, we should not allow variation in structure for resolution. 
+                            //       In the future, we can maybe implement confidence-based fuzzy match for sub-structures
+
+                            debug!("Ambiguous match by occurrence: {}", dbg!(&self.proto_db_a, a_chunks));
+                        }
+                    } else {
+                        debug!("No match by occurrence: {}", dbg!(&self.proto_db_a, a_chunks_by_occurrence));
+                    }
+                } else {
+                    // Primitive type, only options can still tell the fields apart
+                    let fields_a = fields_a_weak.iter().filter(|field| field.field_type == *type_name).copied().collect::<Vec<_>>();
+                    let matches = self.match_by_signature(message_a.name, &fields_a, message_b.name, fields_b);
+
+                    if matches.is_empty() {
+                        debug!("Primitive type with multiple fields, can't be matched any further statically: {}", dbg!(&self.proto_db_b, fields_b));
+                    }
+
+                    for (field_a, field_b) in matches {
+                        debug!("Matched by label and options: {} -> {}", dbg!(&self.proto_db_a, field_a.name), dbg!(&self.proto_db_b, field_b.name));
+                        resolve!(MatchRule::Signature, field_a, field_b, fields_a, fields_b);
+                    }
+                }
+
+            } else {
+                // New field in b, nothing we can do
+                debug!("Found field(s) with new type in b: {}", dbg!(&self.proto_db_b, fields_b));
+            }
+        }
+
+        return did_resolve;
+    }
+
+    /// Pairs up fields whose label and signal options (e.g. `deprecated = true`) are unique among the fields on both sides
+    fn match_by_signature(&self, message_a: ProtoName, fields_a: &[ProtoField], message_b: ProtoName, fields_b: &[ProtoField]) -> Vec<(ProtoField, ProtoField)> {
+        let signatures_a = fields_a.iter().map(|field| field_signature(&self.proto_db_a, message_a, field)).collect::<Vec<_>>();
+        let signatures_b = fields_b.iter().map(|field| field_signature(&self.proto_db_b, message_b, field)).collect::<Vec<_>>();
+
+        let mut matches = Vec::new();
+        for (field_a, signature) in fields_a.iter().zip(&signatures_a) {
+            if *signature == FieldSignature::default() || signatures_a.iter().filter(|other| *other == signature).count() != 1 {
+                continue;
+            }
+
+            let mut candidates = fields_b.iter().zip(&signatures_b).filter(|(_, other)| *other == signature);
+            if let (Some((field_b, _)), None) = (candidates.next(), candidates.next()) {
+                matches.push((*field_a, *field_b));
+            }
+        }
+
+        matches
+    }
+
+    fn group_fields_by_type(&self, fields: &[ProtoField]) -> HashMap<ProtoFieldKind, Vec<ProtoField>> {
+        let mut grouped = HashMap::new();
+        for field in fields {
+            grouped.entry(field.field_type.clone())
+                .or_insert_with(Vec::new)
+                .push(field.clone());
+        }
+        grouped
+    }
+
+    fn group_fields_by_weak_type(&self, fields: &[ProtoField]) -> HashMap<WeakProtoFieldKind, Vec<ProtoField>> {
+        let mut grouped = HashMap::new();
+        for field in fields {
+            grouped.entry(field.field_type.clone().into())
+                .or_insert_with(Vec::new)
+                .push(field.clone());
+        }
+
+        // Sort by type name
+        for fields in grouped.values_mut() {
+            fields.sort_by_key(|field| field.field_type.inner_type().clone());
+        }
+
+        grouped
+    }
+}
+
+/// What tells a field apart from others of the same type, when the names don't
+#[derive(Debug, Default, PartialEq, Eq)]
+struct FieldSignature {
+    label: ProtoLabel,
+    options: Vec<ProtoOption>,
+}
+
+fn field_signature(proto_db: &ProtoDatabase, message: ProtoName, field: &ProtoField) -> FieldSignature {
+    let options = proto_db.options_of(DefinitionRef::Field(message, field.field_number)).iter()
+        // Enum defaults are named differently in every build
+        .filter(|option| SIGNAL_OPTIONS.contains(&option.name.as_str()) && !(option.name == "default" && field.field_type.is_type_ref()))
+        .cloned()
+        .sorted_by(|a, b| a.name.cmp(&b.name))
+        .collect();
+
+    FieldSignature { label: field.label, options }
+}
+
+/// Every method with the service it is in, in source order
+fn service_rpcs(proto_db: &ProtoDatabase) -> Vec<(ProtoName, ProtoRpc)> {
+    proto_db.service_db.right_values()
+        .sorted_by_key(|service| (service.span.file, service.span.start_byte))
+        .flat_map(|service| service.rpcs.iter().map(|rpc| (service.name, *rpc)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::parse_test_proto;
+
+    #[test]
+    fn test_match_services() {
+        let proto_db_a = parse_test_proto("
+            service AvatarService {
+                rpc GetAvatar (GetAvatarReq) returns (GetAvatarRsp);
+                rpc Ping (Empty) returns (Empty);
+                rpc Pong (Empty) returns (Empty);
+            }
+        ");
+
+        let proto_db_b = parse_test_proto("
+            service JNLOABDHEIH {
+                rpc KDMEPLNBAGC (GetAvatarReq) returns (GetAvatarRsp);
+                rpc OPFHBCGDNIA (Empty) returns (Empty);
+                rpc BFMHECLAKJP (Empty) returns (Empty);
+            }
+        ");
+
+        let mut matcher = Matcher::new(proto_db_a, proto_db_b);
+        matcher.run();
+        let proto_db_b = matcher.into_db_b();
+
+        assert_eq!(proto_db_b.translate_name("JNLOABDHEIH").as_deref(), Some("AvatarService"));
+        assert_eq!(proto_db_b.translate_name("KDMEPLNBAGC").as_deref(), Some("GetAvatar"));
+        // Same signature twice, can't be told apart
+        assert_eq!(proto_db_b.translate_name("OPFHBCGDNIA").as_deref(), Some("OPFHBCGDNIA"));
+    }
+
+    #[test]
+    fn test_shared_field_name() {
+        let proto_db_a = parse_test_proto("
+            message A { uint32 level = 1; string name = 2; }
+            message B { uint32 level = 1; bool ok = 2; }
+        ");
+
+        let proto_db_b = parse_test_proto("
+            message A { uint32 PLKMDJAHNFE = 1; string GHOCNEKMBLA = 2; }
+            message B { uint32 CFJKAOBDLMN = 1; bool NBEPHDKAJOL = 2; }
+        ");
+
+        let mut matcher = Matcher::new(proto_db_a, proto_db_b);
+        matcher.run();
+        let proto_db_b = matcher.into_db_b();
+
+        for (original, name) in [("PLKMDJAHNFE", "level"), ("GHOCNEKMBLA", "name"), ("CFJKAOBDLMN", "level"), ("NBEPHDKAJOL", "ok")] {
+            assert_eq!(proto_db_b.translate_name(original).as_deref(), Some(name));
+        }
+    }
+
+    #[test]
+    fn test_name_conflict_reported() {
+        let proto_db_a = parse_test_proto("
+            message Avatar { Info info = 1; }
+            message Info { uint32 level = 1; }
+        ");
+
+        // b already has a readable `Info`, which is not the type of the field
+        let proto_db_b = parse_test_proto("
+            message Avatar { KDMEPLNBAGC JNLOABDHEIH = 1; }
+            message KDMEPLNBAGC { uint32 level = 1; }
+            message Info { string name = 1; }
+        ");
+
+        let mut matcher = Matcher::new(proto_db_a, proto_db_b);
+        matcher.run();
+
+        let conflict = NameConflict { message: Some("Avatar".to_string()), name_a: "Info".to_string(), name_b: "KDMEPLNBAGC".to_string() };
+        assert_eq!(matcher.report().conflicts, [conflict]);
+        assert!(matcher.report().to_markdown().contains("| Info | KDMEPLNBAGC | Avatar |"));
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
+
+use clap::Args;
+use itertools::Itertools;
+use rand::rngs::StdRng;
+use rand::seq::{IndexedRandom, SliceRandom};
+use rand::{Rng, SeedableRng};
+
+use crate::prototype::{DefinitionRef, ProtoDatabase, ProtoField, ProtoFieldKind, ProtoLabel, ProtoMessage, ProtoName, ProtoType, Span};
+
+const SCALAR_TYPES: [ProtoType; 15] = [
+    ProtoType::Bool, ProtoType::Float, ProtoType::Double, ProtoType::Int32, ProtoType::Int64,
+    ProtoType::Uint32, ProtoType::Uint64, ProtoType::Sint32, ProtoType::Sint64, ProtoType::Fixed32,
+    ProtoType::Fixed64, ProtoType::Sfixed32, ProtoType::Sfixed64, ProtoType::String, ProtoType::Bytes,
+];
+
+/// How far the synthetic version drifts from the clean schema, rates are per field unless noted
+#[derive(Debug, Clone, Args)]
+pub struct ObfuscateOptions {
+    /// Seed of the random choices, the same seed gives the same output
+    #[arg(long, default_value_t = 1)]
+    pub seed: u64,
+    /// Share of message names left readable, the matcher needs some names both schemas share to start from
+    #[arg(long, default_value_t = 0.2)]
+    pub keep_messages: f64,
+    #[arg(long, default_value_t = 0.05)]
+    pub remove_rate: f64,
+    /// Chance of a new field next to each existing one
+    #[arg(long, default_value_t = 0.05)]
+    pub add_rate: f64,
+    /// Chance of a scalar field getting another scalar type
+    #[arg(long, default_value_t = 0.02)]
+    pub type_change_rate: f64,
+}
+
+impl Default for ObfuscateOptions {
+    fn default() -> Self {
+        Self { seed: 1, keep_messages: 0.2, remove_rate: 0.05, add_rate: 0.05, type_change_rate: 0.02 }
+    }
+}
+
+/// An obfuscated copy of a schema, with the answer the matcher should arrive at
+pub struct Obfuscated {
+    pub proto_db: ProtoDatabase,
+    /// Clean name of every obfuscated name the matcher can resolve, added fields have none
+    pub truth: BTreeMap<String, String>,
+    /// Clean names of the enum values, which the matcher leaves alone
+    pub enum_values: BTreeMap<String, String>,
+}
+
+impl Obfuscated {
+    /// The truth as `old -> new` lines, like `match --translations`
+    pub fn truth_lines(&self) -> String {
+        let mut output = String::new();
+        for (obfuscated, clean) in self.truth.iter().chain(&self.enum_values).sorted() {
+            writeln!(output, "{} -> {}", obfuscated, clean).unwrap();
+        }
+        output
+    }
+}
+
+/// Renames every identifier to a random 11 letter name (one name per identifier, shared by all its uses as the matcher expects),
+/// then shuffles field numbers and adds, removes and retypes fields. Definitions are emitted sorted by name, so the new names reorder them too
+pub fn obfuscate(clean: &ProtoDatabase, options: &ObfuscateOptions) -> Obfuscated {
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let mut proto_db = clean.clone();
+
+    // Comments and reserved names would give the answer away, field options are keyed by number which no longer matches
+    proto_db.comments.clear();
+    proto_db.reserved.clear();
+    proto_db.identifier_occurrences.clear();
+    proto_db.options.retain(|target, _| !matches!(target, DefinitionRef::Field(..)));
+
+    // Sorted so that a seed always gives the same names, qualified names refer to other packages and are left alone
+    let messages = clean.message_db.right_values().sorted_by_key(|message| message.name).collect::<Vec<_>>();
+    let kept = messages.iter()
+        .filter(|_| rng.random_bool(options.keep_messages))
+        .map(|message| message.name.name(clean))
+        .collect::<Vec<_>>();
+
+    let mut truth = BTreeMap::new();
+    for name in clean.identifier_db.names().sorted() {
+        if name.contains('.') || kept.contains(name) {
+            continue;
+        }
+
+        let id = proto_db.identifier_db.ids(name)[0];
+        proto_db.identifier_db.remove(id);
+        proto_db.identifier_db_original.remove_by_right(&id);
+
+        // A name another identifier had in the source would make the two indistinguishable, draw again
+        let obfuscated = loop {
+            let obfuscated = random_name(&mut rng, &proto_db);
+            if proto_db.identifier_db_original.insert_no_overwrite(obfuscated.clone(), id).is_ok() {
+                break obfuscated;
+            }
+        };
+        proto_db.identifier_db.insert(obfuscated.clone(), id);
+        proto_db.identifier_resolutions.insert(id, false);
+        truth.insert(obfuscated, name.clone());
+    }
+
+    // Option values are kept as written, those naming an enum value or a type follow the rename
+    let renames = truth.iter().map(|(obfuscated, clean)| (clean.clone(), obfuscated.clone())).collect::<HashMap<_, _>>();
+    for option in proto_db.options.values_mut().flatten() {
+        if let Some(obfuscated) = renames.get(&option.value) {
+            option.value = obfuscated.clone();
+        }
+    }
+
+    for message in messages {
+        let fields = mutate_fields(&mut rng, &mut proto_db, &message.fields, options);
+        proto_db.message_db.insert(message.name, ProtoMessage { fields, ..message.clone() });
+    }
+
+    // A name only some removed fields had is not in the output at all
+    let used = used_names(&proto_db);
+    let enum_value_names = enum_value_names(&proto_db);
+    let enum_values = truth.iter()
+        .filter(|(obfuscated, _)| !used.contains(*obfuscated) && enum_value_names.contains(*obfuscated))
+        .map(|(obfuscated, clean)| (obfuscated.clone(), clean.clone()))
+        .collect();
+    truth.retain(|obfuscated, _| used.contains(obfuscated));
+
+    Obfuscated { proto_db, truth, enum_values }
+}
+
+fn mutate_fields(rng: &mut StdRng, proto_db: &mut ProtoDatabase, fields: &[ProtoField], options: &ObfuscateOptions) -> Vec<ProtoField> {
+    let mut next_number = fields.iter().map(|field| field.field_number).max().unwrap_or(0) + 1;
+
+    let mut mutated = Vec::new();
+    for field in fields {
+        if !rng.random_bool(options.remove_rate) {
+            let mut field = *field;
+            if let ProtoFieldKind::Scalar(field_type) = &mut field.field_type {
+                if !matches!(field_type, ProtoType::Type(_)) && rng.random_bool(options.type_change_rate) {
+                    let others = SCALAR_TYPES.into_iter().filter(|other| other != field_type).collect::<Vec<_>>();
+                    *field_type = *others.choose(rng).unwrap();
+                }
+            }
+            mutated.push(field);
+        }
+
+        if rng.random_bool(options.add_rate) {
+            let name = random_name(rng, proto_db);
+            proto_db.register_identifier(name.clone());
+            mutated.push(ProtoField {
+                name: proto_db.lookup_name_by_text(&name),
+                label: ProtoLabel::None,
+                field_type: ProtoFieldKind::Scalar(*SCALAR_TYPES.choose(rng).unwrap()),
+                field_number: next_number,
+                oneof: None,
+                span: Span::default(),
+            });
+            next_number += 1;
+        }
+    }
+
+    let mut numbers = mutated.iter().map(|field| field.field_number).collect::<Vec<_>>();
+    numbers.shuffle(rng);
+    for (field, number) in mutated.iter_mut().zip(numbers) {
+        field.field_number = number;
+    }
+
+    mutated.sort_by_key(|field| field.field_number);
+    mutated
+}
+
+/// Names of every definition and member except enum values, and the types they refer to
+fn used_names(proto_db: &ProtoDatabase) -> HashSet<String> {
+    let mut names = Vec::new();
+    for message in proto_db.message_db.right_values() {
+        names.push(message.name);
+        names.extend(&message.oneofs);
+        field_names(&mut names, &message.fields);
+    }
+    for proto_enum in proto_db.enum_db.right_values() {
+        names.push(proto_enum.name);
+    }
+    for service in proto_db.service_db.right_values() {
+        names.push(service.name);
+        for rpc in &service.rpcs {
+            names.push(rpc.name);
+            names.extend([rpc.request, rpc.response].into_iter().filter_map(type_reference));
+        }
+    }
+    for extension in &proto_db.extensions {
+        names.push(extension.extendee);
+        field_names(&mut names, &extension.fields);
+    }
+
+    names.iter().map(|name| name.name(proto_db)).collect()
+}
+
+fn field_names(names: &mut Vec<ProtoName>, fields: &[ProtoField]) {
+    for field in fields {
+        names.push(field.name);
+        let types = match field.field_type {
+            ProtoFieldKind::Scalar(field_type) | ProtoFieldKind::Repeated(field_type) => vec![field_type],
+            ProtoFieldKind::Map(key, value) => vec![key, value],
+        };
+        names.extend(types.into_iter().filter_map(type_reference));
+    }
+}
+
+fn enum_value_names(proto_db: &ProtoDatabase) -> HashSet<String> {
+    proto_db.enum_db.right_values()
+        .flat_map(|proto_enum| proto_enum.values.iter().map(|value| value.name.name(proto_db)))
+        .collect()
+}
+
+fn type_reference(field_type: ProtoType) -> Option<ProtoName> {
+    match field_type {
+        ProtoType::Type(name) => Some(name),
+        _ => None,
+    }
+}
+
+fn random_name(rng: &mut StdRng, proto_db: &ProtoDatabase) -> String {
+    loop {
+        let name = (0..11).map(|_| rng.random_range('A'..='Z')).collect::<String>();
+        if !proto_db.identifier_db.contains_name(&name) && !proto_db.identifier_db_original.contains_left(&name) {
+            return name;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::parse_test_proto;
+
+    #[test]
+    fn test_obfuscate() {
+        let proto_db = parse_test_proto("
+            message Avatar {
+                uint32 level = 1;
+                string name = 2;
+                int64 exp = 3;
+                Skill skill = 4;
+                Rarity rarity = 5;
+            }
+
+            enum Rarity {
+                option (default_rarity) = RARITY_RARE;
+                RARITY_NONE = 0;
+                RARITY_RARE = 1;
+            }
+        ");
+
+        let options = ObfuscateOptions { keep_messages: 0.0, remove_rate: 0.0, add_rate: 0.0, type_change_rate: 0.0, ..Default::default() };
+        let obfuscated = obfuscate(&proto_db, &options);
+        // Skill is only referenced, enum values are kept apart since the matcher never resolves them
+        assert_eq!(obfuscated.truth.values().sorted().collect::<Vec<_>>(), ["Avatar", "Rarity", "Skill", "exp", "level", "name", "rarity", "skill"]);
+        assert_eq!(obfuscated.enum_values.values().sorted().collect::<Vec<_>>(), ["RARITY_NONE", "RARITY_RARE"]);
+
+        let option = obfuscated.proto_db.options.values().flatten().exactly_one().unwrap();
+        assert_eq!(obfuscated.enum_values[&option.value], "RARITY_RARE");
+
+        // Every field keeps its type under the new name, only the numbers moved
+        let message = obfuscated.proto_db.message_db.right_values().next().unwrap();
+        let mut fields = message.fields.iter()
+            .map(|field| (obfuscated.truth[&field.name.name(&obfuscated.proto_db)].as_str(), field.field_type.type_name(&obfuscated.proto_db)))
+            .collect::<Vec<_>>();
+        fields.sort();
+        assert_eq!(fields[..3], [("exp", "int64".to_string()), ("level", "uint32".to_string()), ("name", "string".to_string())]);
+        assert!(message.fields.iter().all(|field| !obfuscated.proto_db.is_resolved(&field.name)));
+
+        assert_eq!(obfuscate(&proto_db, &options).truth, obfuscated.truth);
+        assert!(ProtoName::try_lookup(&obfuscated.proto_db, "Avatar").is_none());
+    }
+}
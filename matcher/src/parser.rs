@@ -1,4 +1,5 @@
-use crate::prototype::{ProtoDatabase, ProtoField, ProtoFieldKind, ProtoMessage, ProtoType};
+use crate::diagnostic::Diagnostic;
+use crate::prototype::{DefinitionRef, ProtoComments, ProtoDatabase, ProtoEnum, ProtoEnumValue, ProtoExtension, ProtoField, ProtoFieldKind, ProtoLabel, ProtoMessage, ProtoName, ProtoOption, ProtoReserved, ProtoRpc, ProtoService, ProtoSyntax, ProtoType, Span, RESERVED_MAX};
 use crate::util::{ExtractText, QueryExecutor, RawBuffer};
 use tree_sitter::{Node, Parser};
 use streaming_iterator::StreamingIterator;
@@ -6,9 +7,19 @@ use matcher_macros::tree_sitter_query;
 
 tree_sitter_query! {
     IdentifierQuery("(identifier) @name")
+    SyntaxQuery("(syntax) @node")
+    EditionQuery("(edition) @node")
+    PackageQuery("(package) @node")
+    ImportQuery("(import) @node")
     MessageQuery("(message (message_name) @name) @node")
+    EnumQuery("(enum (enum_name) @name) @node")
+    ExtendQuery("(extend) @node")
+    ServiceQuery("(service (service_name) @name) @node")
+    RpcQuery("(rpc (rpc_name) @name) @node")
+    EnumValueQuery("(enum_field (identifier) @name \"-\"? @negative (int_lit) @number) @node")
     FieldQuery("
         (field
+            [\"optional\" \"required\"]? @label
             \"repeated\"? @repeated
             (type _ @typ)
             (identifier) @name
@@ -21,6 +32,15 @@ tree_sitter_query! {
             (identifier) @name
             (field_number) @number
         ) @node
+
+        (oneof
+            (identifier) @oneof
+            (oneof_field
+                (type _ @typ)
+                (identifier) @name
+                (field_number) @number
+            ) @node
+        )
     ")
 }
 
@@ -30,63 +50,714 @@ impl<'tree> FieldQuery<'tree> {
     }
 }
 
-pub fn parse_proto(source_code: &str) -> ProtoDatabase {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportKind {
+    Default,
+    Public,
+    Weak,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProtoImport {
+    pub path: String,
+    pub kind: ImportKind,
+}
+
+/// What a single file contributed to the database
+pub struct ParsedFile {
+    pub imports: Vec<ProtoImport>,
+    pub definitions: Vec<ProtoName>,
+    /// Syntax errors and definitions that were skipped, everything else in the file is still registered
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+struct ParseContext<'a> {
+    buffer: &'a RawBuffer,
+    file: &'a str,
+    file_index: usize,
+}
+
+impl ParseContext<'_> {
+    fn span(&self, node: &Node) -> Span {
+        let rope = &self.buffer.rope;
+        let start_char = rope.byte_to_char(node.start_byte());
+        let line = rope.char_to_line(start_char);
+
+        Span {
+            file: self.file_index,
+            start_byte: node.start_byte(),
+            end_byte: node.end_byte(),
+            line: line + 1,
+            column: start_char - rope.line_to_char(line) + 1,
+        }
+    }
+
+    fn diagnostic(&self, node: &Node, message: String, expected: Option<&str>) -> Diagnostic {
+        Diagnostic::at(node, self.buffer, self.file, message, expected.map(str::to_string))
+    }
+
+    /// A message, enum or service whose name is defined already, nested types are keyed by their name alone so the first one is kept
+    fn duplicate(&self, node: &Node, name: ProtoName, proto_db: &ProtoDatabase) -> Option<Diagnostic> {
+        let previous = proto_db.span_of(DefinitionRef::Definition(name))?;
+        let message = format!("`{}` is defined already at {}, this definition is skipped", name.name(proto_db), previous.location(proto_db));
+        Some(self.diagnostic(node, message, None))
+    }
+
+    fn lookup(&self, node: &Node, proto_db: &ProtoDatabase) -> Result<ProtoName, Diagnostic> {
+        let text = node.text(self.buffer);
+        ProtoName::try_lookup(proto_db, text.trim())
+            .ok_or_else(|| self.diagnostic(node, format!("unknown identifier `{}`", text), Some("an identifier")))
+    }
+
+    fn int_lit(&self, node: &Node) -> Result<u64, Diagnostic> {
+        let text = node.text(self.buffer);
+        parse_int_lit(&text).ok_or_else(|| match text.trim().chars().all(|c| c.is_ascii_digit()) {
+            true => self.diagnostic(node, format!("number `{}` is out of range", text.trim()), Some("a 64-bit integer")),
+            false => self.diagnostic(node, format!("invalid number `{}`", text), Some("an integer literal")),
+        })
+    }
+
+    /// Field numbers go up to 2^29 - 1, and 19000 to 19999 are reserved for the protobuf implementation
+    fn field_number(&self, node: &Node) -> Result<u32, Diagnostic> {
+        let number = self.int_lit(node)?;
+        match u32::try_from(number) {
+            Ok(number @ 1..=MAX_FIELD_NUMBER) if !(19000..=19999).contains(&number) => Ok(number),
+            _ => Err(self.diagnostic(node, format!("field number {} is out of range", number), Some("a field number from 1 to 536870911, except 19000 to 19999"))),
+        }
+    }
+
+    fn enum_number(&self, node: &Node, negative: bool) -> Result<i32, Diagnostic> {
+        let number = self.int_lit(node)?;
+        let signed = i64::try_from(number).ok().map(|number| if negative { -number } else { number });
+        signed.and_then(|number| i32::try_from(number).ok())
+            .ok_or_else(|| self.diagnostic(node, format!("enum value {}{} is out of range", if negative { "-" } else { "" }, number), Some("a 32-bit integer")))
+    }
+}
+
+const MAX_FIELD_NUMBER: u32 = (1 << 29) - 1;
+
+/// Parses a file into an existing database, only fails if the file couldn't be parsed at all
+pub fn parse_proto_into(proto_db: &mut ProtoDatabase, source_code: &str, file: &str) -> Result<ParsedFile, Diagnostic> {
     let mut parser = Parser::new();
     parser.set_language(&tree_sitter_proto::LANGUAGE.into()).expect("Error loading protobuf grammar");
 
     let buffer = RawBuffer::from(source_code.to_owned());
+    let file_index = proto_db.register_source_file(file);
+    let context = ParseContext { buffer: &buffer, file, file_index };
 
-    let tree = parser.parse(&source_code, None).expect("Error parsing file");
+    let tree = parser.parse(&source_code, None).ok_or_else(|| Diagnostic {
+        file: file.to_string(),
+        line: 1,
+        column: 1,
+        snippet: source_code.lines().next().unwrap_or_default().to_string(),
+        message: "parser gave up on this file".to_string(),
+        expected: None,
+    })?;
     let root_node = tree.root_node();
 
-    let mut proto_db = ProtoDatabase::new();
+    let mut diagnostics = Vec::new();
+    collect_syntax_errors(&root_node, &context, &mut diagnostics);
 
     // Register all identifiers first
     let identifiers = IdentifierQuery::execute(root_node, &buffer);
     for identifier in identifiers {
-        if let Some(name) = identifier.name {
-            proto_db.register_identifier(name.text(&buffer));
+        if let Some(name) = identifier.name.filter(|name| !name.is_missing()) {
+            let id = proto_db.register_identifier(name.text(&buffer));
+            proto_db.register_occurrence(id, context.span(&name));
         }
     }
 
+    let syntax = SyntaxQuery::execute(root_node, &buffer).first().and_then(|syntax| syntax.node);
+    let edition = EditionQuery::execute(root_node, &buffer).first().and_then(|edition| edition.node);
+    match (syntax, edition) {
+        (_, Some(edition)) => {
+            let version = string_literal(&edition.text(&buffer)).unwrap_or_default();
+            proto_db.register_syntax(file_index, ProtoSyntax::Edition(version), true);
+        }
+        (Some(syntax), None) => {
+            let version = string_literal(&syntax.text(&buffer)).unwrap_or_default();
+            match ProtoSyntax::from_syntax(&version) {
+                Some(parsed) => proto_db.register_syntax(file_index, parsed, true),
+                None => {
+                    diagnostics.push(context.diagnostic(&syntax, format!("unknown syntax `{}`, reading the file as proto2", version), Some("`proto2` or `proto3`")));
+                    proto_db.register_syntax(file_index, ProtoSyntax::Proto2, false);
+                }
+            }
+        }
+        (None, None) => proto_db.register_syntax(file_index, ProtoSyntax::Proto2, false),
+    }
+
+    if let Some(package) = PackageQuery::execute(root_node, &buffer).first().and_then(|package| package.node) {
+        let package = package.text(&buffer);
+        proto_db.register_package(file_index, package.trim().trim_start_matches("package").trim_end_matches(';').trim().to_string());
+    }
+
+    let imports = ImportQuery::execute(root_node, &buffer).into_iter()
+        .filter_map(|import| {
+            let statement = import.node?.text(&buffer);
+            let modifier = statement.trim().trim_start_matches("import").trim_start();
+
+            Some(ProtoImport {
+                path: string_literal(&statement)?,
+                kind: match modifier {
+                    _ if modifier.starts_with("public") => ImportKind::Public,
+                    _ if modifier.starts_with("weak") => ImportKind::Weak,
+                    _ => ImportKind::Default,
+                },
+            })
+        })
+        .collect();
+
+    proto_db.register_options(DefinitionRef::File(file_index), statement_options(&root_node, &buffer));
+
+    let mut definitions = Vec::new();
+
     let messages = MessageQuery::execute(root_node, &buffer);
     for message in messages {
+        let message_node = message.node.unwrap();
+
+        let name = match context.lookup(&message.name.unwrap(), proto_db) {
+            Ok(name) => name,
+            Err(diagnostic) => {
+                diagnostics.push(diagnostic);
+                continue;
+            }
+        };
+
+        if let Some(diagnostic) = context.duplicate(&message_node, name, proto_db) {
+            diagnostics.push(diagnostic);
+            continue;
+        }
+
         let mut result = ProtoMessage {
-            name: proto_db.lookup_name_by_text(message.name.unwrap().text(&buffer).as_str()),
+            name,
             fields: Vec::new(),
+            oneofs: Vec::new(),
+            parent: parent_message(&message_node, &buffer, proto_db),
+            span: context.span(&message_node),
         };
 
-        let fields = FieldQuery::execute(message.node.unwrap(), &buffer);
+        let fields = FieldQuery::execute(message_node, &buffer);
         for field in fields {
-            result.fields.push(ProtoField {
-                name: proto_db.lookup_name_by_text(field.name.unwrap().text(&buffer).as_str()),
-                field_type: {
-                    if field.is_map_field() {
-                        let key_type = get_simple_field_type(&field.key_type.unwrap(), &buffer, &proto_db);
-                        let value_type = get_simple_field_type(&field.value_type.unwrap(), &buffer, &proto_db);
-
-                        ProtoFieldKind::Map(key_type, value_type)
-                    } else {
-                        let field_type_scalar = get_simple_field_type(&field.typ.unwrap(), &buffer, &proto_db);
-                        
-                        match field.repeated.is_some() {
-                            true => ProtoFieldKind::Repeated(field_type_scalar),
-                            false => ProtoFieldKind::Scalar(field_type_scalar),
+            let field_node = field.node.unwrap();
+
+            // The query also matches fields of nested messages, those are registered with their own message
+            if owner(&field_node) != Some(message_node) {
+                continue;
+            }
+
+            // A broken field is dropped on its own, the rest of the message is still usable
+            match parse_field(&field, &context, proto_db) {
+                Ok(field) => {
+                    proto_db.register_comments(DefinitionRef::Field(name, field.field_number), attached_comments(&field_node, &buffer));
+                    proto_db.register_options(DefinitionRef::Field(name, field.field_number), bracket_options(&field_node, &buffer));
+
+                    if let Some(oneof) = field.oneof {
+                        if !result.oneofs.contains(&oneof) {
+                            result.oneofs.push(oneof);
                         }
                     }
-                },
-                field_number: field.number.unwrap().text(&buffer).parse().expect("Failed to parse field number"),
-            });
+
+                    result.fields.push(field);
+                }
+                Err(diagnostic) => diagnostics.push(diagnostic),
+            }
+        }
+
+        definitions.push(result.name);
+        proto_db.register_comments(DefinitionRef::Definition(result.name), attached_comments(&message_node, &buffer));
+        proto_db.register_options(DefinitionRef::Definition(result.name), statement_options(&message_node, &buffer));
+        proto_db.reserved.extend(reserved_statements(&message_node, &context, &mut diagnostics).map(|reserved| (result.name, reserved)));
+        let extension_ranges = extension_ranges(&message_node, &context, &mut diagnostics);
+        if !extension_ranges.is_empty() {
+            proto_db.extension_ranges.insert(result.name, extension_ranges);
         }
-        
         proto_db.register_message(result);
     }
 
-    proto_db
+    let enums = EnumQuery::execute(root_node, &buffer);
+    for proto_enum in enums {
+        let enum_node = proto_enum.node.unwrap();
+
+        let name = match context.lookup(&proto_enum.name.unwrap(), proto_db) {
+            Ok(name) => name,
+            Err(diagnostic) => {
+                diagnostics.push(diagnostic);
+                continue;
+            }
+        };
+
+        if let Some(diagnostic) = context.duplicate(&enum_node, name, proto_db) {
+            diagnostics.push(diagnostic);
+            continue;
+        }
+
+        let mut values = Vec::new();
+        for value in EnumValueQuery::execute(enum_node, &buffer) {
+            // A value with a syntax error in it (`X = 0x;`) is reported already, its number can't be trusted
+            if value.node.unwrap().has_error() {
+                continue;
+            }
+
+            let parsed = context.lookup(&value.name.unwrap(), proto_db).and_then(|name| {
+                Ok(ProtoEnumValue {
+                    name,
+                    number: context.enum_number(&value.number.unwrap(), value.negative.is_some())?,
+                    span: context.span(&value.node.unwrap()),
+                })
+            });
+
+            match parsed {
+                Ok(parsed) => {
+                    proto_db.register_comments(DefinitionRef::EnumValue(name, parsed.number), attached_comments(&value.node.unwrap(), &buffer));
+                    proto_db.register_options(DefinitionRef::EnumValue(name, parsed.number), bracket_options(&value.node.unwrap(), &buffer));
+                    values.push(parsed);
+                }
+                Err(diagnostic) => diagnostics.push(diagnostic),
+            }
+        }
+
+        definitions.push(name);
+        proto_db.register_comments(DefinitionRef::Definition(name), attached_comments(&enum_node, &buffer));
+        proto_db.register_options(DefinitionRef::Definition(name), statement_options(&enum_node, &buffer));
+        proto_db.reserved.extend(reserved_statements(&enum_node, &context, &mut diagnostics).map(|reserved| (name, reserved)));
+
+        proto_db.register_enum(ProtoEnum {
+            name,
+            values,
+            parent: parent_message(&enum_node, &buffer, proto_db),
+            span: context.span(&enum_node),
+        });
+    }
+
+    for extend in ExtendQuery::execute(root_node, &buffer) {
+        let extend_node = extend.node.unwrap();
+
+        let extendee = match extendee(&extend_node, &context, proto_db) {
+            Ok(extendee) => extendee,
+            Err(diagnostic) => {
+                diagnostics.push(diagnostic);
+                continue;
+            }
+        };
+
+        let mut fields = Vec::new();
+        for field in FieldQuery::execute(extend_node, &buffer) {
+            let field_node = field.node.unwrap();
+            if owner(&field_node) != Some(extend_node) {
+                continue;
+            }
+
+            match parse_field(&field, &context, proto_db) {
+                Ok(field) => {
+                    // Extension numbers can't collide with the extendee's own fields, so they share its targets
+                    proto_db.register_comments(DefinitionRef::Field(extendee, field.field_number), attached_comments(&field_node, &buffer));
+                    proto_db.register_options(DefinitionRef::Field(extendee, field.field_number), bracket_options(&field_node, &buffer));
+                    fields.push(field);
+                }
+                Err(diagnostic) => diagnostics.push(diagnostic),
+            }
+        }
+
+        proto_db.extensions.push(ProtoExtension {
+            extendee,
+            fields,
+            parent: parent_message(&extend_node, &buffer, proto_db),
+            span: context.span(&extend_node),
+        });
+    }
+
+    for service in ServiceQuery::execute(root_node, &buffer) {
+        let service_node = service.node.unwrap();
+
+        let name = match context.lookup(&service.name.unwrap(), proto_db) {
+            Ok(name) => name,
+            Err(diagnostic) => {
+                diagnostics.push(diagnostic);
+                continue;
+            }
+        };
+
+        if let Some(diagnostic) = context.duplicate(&service_node, name, proto_db) {
+            diagnostics.push(diagnostic);
+            continue;
+        }
+
+        let mut rpcs = Vec::new();
+        for rpc in RpcQuery::execute(service_node, &buffer) {
+            let rpc_node = rpc.node.unwrap();
+
+            match parse_rpc(&rpc, &context, proto_db) {
+                Ok(parsed) => {
+                    proto_db.register_comments(DefinitionRef::Rpc(name, parsed.name), attached_comments(&rpc_node, &buffer));
+                    proto_db.register_options(DefinitionRef::Rpc(name, parsed.name), statement_options(&rpc_node, &buffer));
+                    rpcs.push(parsed);
+                }
+                Err(diagnostic) => diagnostics.push(diagnostic),
+            }
+        }
+
+        definitions.push(name);
+        proto_db.register_comments(DefinitionRef::Definition(name), attached_comments(&service_node, &buffer));
+        proto_db.register_options(DefinitionRef::Definition(name), statement_options(&service_node, &buffer));
+
+        proto_db.register_service(ProtoService {
+            name,
+            rpcs,
+            span: context.span(&service_node),
+        });
+    }
+
+    Ok(ParsedFile { imports, definitions, diagnostics })
 }
 
-fn get_simple_field_type(type_node: &Node, buffer: &RawBuffer, proto_db: &ProtoDatabase) -> ProtoType {
-    match type_node.kind() {
+fn parse_field(field: &FieldQuery, context: &ParseContext, proto_db: &ProtoDatabase) -> Result<ProtoField, Diagnostic> {
+    let field_type = if field.is_map_field() {
+        let key_type = get_simple_field_type(&field.key_type.unwrap(), context, proto_db)?;
+        let value_type = get_simple_field_type(&field.value_type.unwrap(), context, proto_db)?;
+
+        ProtoFieldKind::Map(key_type, value_type)
+    } else {
+        let field_type_scalar = get_simple_field_type(&field.typ.unwrap(), context, proto_db)?;
+
+        match field.repeated.is_some() {
+            true => ProtoFieldKind::Repeated(field_type_scalar),
+            false => ProtoFieldKind::Scalar(field_type_scalar),
+        }
+    };
+
+    let label = match field.label.map(|label| label.text(context.buffer)) {
+        Some(label) if label.trim() == "optional" => ProtoLabel::Optional,
+        Some(label) if label.trim() == "required" => ProtoLabel::Required,
+        _ => ProtoLabel::None,
+    };
+
+    Ok(ProtoField {
+        name: context.lookup(&field.name.unwrap(), proto_db)?,
+        label,
+        field_type,
+        field_number: context.field_number(&field.number.unwrap())?,
+        oneof: field.oneof.map(|oneof| context.lookup(&oneof, proto_db)).transpose()?,
+        span: context.span(&field.node.unwrap()),
+    })
+}
+
+/// Reports ERROR and MISSING nodes, tree-sitter has already recovered around them
+fn collect_syntax_errors(node: &Node, context: &ParseContext, diagnostics: &mut Vec<Diagnostic>) {
+    if node.is_missing() {
+        let expected = if node.is_named() {
+            node.kind().replace('_', " ")
+        } else {
+            format!("`{}`", node.kind())
+        };
+
+        let statement = node.parent().map_or("file".to_string(), |parent| parent.kind().replace('_', " "));
+        diagnostics.push(context.diagnostic(node, format!("incomplete {}", statement), Some(&expected)));
+        return;
+    }
+
+    if node.is_error() {
+        let text = node.text(context.buffer);
+        let found = text.lines().next().unwrap_or_default().trim();
+        let found = if found.chars().count() > 40 { format!("{}...", found.chars().take(40).collect::<String>()) } else { found.to_string() };
+
+        // The grammar has no groups, which are deprecated since proto2, so say so instead of pointing at the syntax
+        let mut words = found.split_whitespace().skip_while(|word| matches!(*word, "optional" | "required" | "repeated"));
+        if words.next() == Some("group") {
+            diagnostics.push(context.diagnostic(node, "`group` fields are not supported".to_string(), Some("a nested message and a field of that type")));
+            return;
+        }
+
+        let expected = node.parent().map(|parent| expected_in(parent.kind()));
+        diagnostics.push(context.diagnostic(node, format!("unexpected `{}`", found), expected.as_deref()));
+        return;
+    }
+
+    if node.has_error() {
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            collect_syntax_errors(&child, context, diagnostics);
+        }
+    }
+}
+
+/// What can appear inside a node of the given kind, for error messages
+fn expected_in(kind: &str) -> String {
+    match kind {
+        "source_file" => "a top-level statement (message, enum, service, import, package or option)".to_string(),
+        "message_body" => "a message element (field, oneof, nested message or enum, option, reserved or extensions)".to_string(),
+        "enum_body" => "an enum value (`NAME = number;`)".to_string(),
+        "oneof" => "a oneof field (`type name = number;`)".to_string(),
+        "field" | "oneof_field" | "map_field" => "a field definition (`type name = number;`)".to_string(),
+        "service" => "an rpc definition".to_string(),
+        _ => format!("a valid {}", kind.replace('_', " ")),
+    }
+}
+
+/// Request and response types are the first and second type in the method, each possibly preceded by `stream`
+fn parse_rpc(rpc: &RpcQuery, context: &ParseContext, proto_db: &ProtoDatabase) -> Result<ProtoRpc, Diagnostic> {
+    let rpc_node = rpc.node.unwrap();
+
+    let mut types = Vec::new();
+    let mut stream = false;
+
+    let mut cursor = rpc_node.walk();
+    for child in rpc_node.children(&mut cursor) {
+        match child.kind() {
+            "stream" => stream = true,
+            "message_or_enum_type" => {
+                types.push((get_simple_field_type(&child, context, proto_db)?, stream));
+                stream = false;
+            }
+            _ => (),
+        }
+    }
+
+    let [(request, request_stream), (response, response_stream)] = types[..] else {
+        return Err(context.diagnostic(&rpc_node, "incomplete rpc".to_string(), Some("a request and a response type")));
+    };
+
+    Ok(ProtoRpc {
+        name: context.lookup(&rpc.name.unwrap(), proto_db)?,
+        request,
+        request_stream,
+        response,
+        response_stream,
+        span: context.span(&rpc_node),
+    })
+}
+
+/// Message extended by an `extend` block, resolved by its last segment like field types
+fn extendee(node: &Node, context: &ParseContext, proto_db: &ProtoDatabase) -> Result<ProtoName, Diagnostic> {
+    let mut cursor = node.walk();
+    let text = node.children(&mut cursor)
+        .filter(|child| !matches!(child.kind(), "extend" | "message_body" | "comment"))
+        .map(|child| child.text(context.buffer).trim().to_string())
+        .collect::<String>();
+
+    let name = text.rsplit('.').next().unwrap_or_default();
+    ProtoName::try_lookup(proto_db, name)
+        .ok_or_else(|| context.diagnostic(node, format!("unknown extendee `{}`", text), Some("a message type")))
+}
+
+/// `reserved` ranges and names in the body of a message or enum, `None` without any
+fn reserved_statements(node: &Node, context: &ParseContext, diagnostics: &mut Vec<Diagnostic>) -> Option<ProtoReserved> {
+    let mut cursor = node.walk();
+    let body = node.children(&mut cursor).find(|child| matches!(child.kind(), "message_body" | "enum_body"))?;
+    let max = if body.kind() == "message_body" { MAX_FIELD_NUMBER } else { i32::MAX as u32 };
+
+    let mut reserved = ProtoReserved::default();
+
+    let mut cursor = body.walk();
+    for statement in body.children(&mut cursor).filter(|child| child.kind() == "reserved") {
+        let mut ranges = Vec::new();
+        collect_kind(&statement, "range", &mut ranges);
+
+        if ranges.is_empty() {
+            let text = statement.text(context.buffer);
+            let names = text.trim().trim_start_matches("reserved").trim_end_matches(';');
+            reserved.names.extend(names.split(',')
+                .map(|name| name.trim().trim_matches(|c| c == '"' || c == '\'').to_string())
+                .filter(|name| !name.is_empty()));
+            continue;
+        }
+
+        reserved.ranges.extend(parse_ranges(&ranges, max, context, diagnostics));
+    }
+
+    (!reserved.ranges.is_empty() || !reserved.names.is_empty()).then_some(reserved)
+}
+
+/// `extensions` ranges in the body of a message
+fn extension_ranges(node: &Node, context: &ParseContext, diagnostics: &mut Vec<Diagnostic>) -> Vec<(i64, i64)> {
+    let mut cursor = node.walk();
+    let Some(body) = node.children(&mut cursor).find(|child| child.kind() == "message_body") else {
+        return Vec::new();
+    };
+
+    let mut ranges = Vec::new();
+    let mut cursor = body.walk();
+    for statement in body.children(&mut cursor).filter(|child| child.kind() == "extensions") {
+        collect_kind(&statement, "range", &mut ranges);
+    }
+
+    parse_ranges(&ranges, MAX_FIELD_NUMBER, context, diagnostics)
+}
+
+/// Inclusive bounds of `range` nodes, `to max` becomes `RESERVED_MAX`. A range with a bound above `max` is reported and left out
+fn parse_ranges(ranges: &[Node], max: u32, context: &ParseContext, diagnostics: &mut Vec<Diagnostic>) -> Vec<(i64, i64)> {
+    let bound = |node: &Node| {
+        let number = context.int_lit(node)?;
+        match u32::try_from(number) {
+            Ok(number) if number <= max => Ok(number as i64),
+            _ => Err(context.diagnostic(node, format!("range bound {} is out of range", number), Some(&format!("a number up to {} or `max`", max)))),
+        }
+    };
+
+    ranges.iter()
+        .filter_map(|range| {
+            let mut bounds = Vec::new();
+            collect_kind(range, "int_lit", &mut bounds);
+
+            let parsed = bounds.first().map(bound)?.and_then(|start| {
+                let end = match bounds.get(1) {
+                    Some(end) => bound(end)?,
+                    None if range.text(context.buffer).trim_end().ends_with("max") => RESERVED_MAX,
+                    None => start,
+                };
+                Ok((start, end))
+            });
+
+            parsed.map_err(|diagnostic| diagnostics.push(diagnostic)).ok()
+        })
+        .collect()
+}
+
+/// Descendants of a given kind, without looking inside matches
+fn collect_kind<'tree>(node: &Node<'tree>, kind: &str, found: &mut Vec<Node<'tree>>) {
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        if child.kind() == kind {
+            found.push(child);
+        } else {
+            collect_kind(&child, kind, found);
+        }
+    }
+}
+
+/// `option` statements of a file, service or method, or directly in the body of a message or enum
+fn statement_options(node: &Node, buffer: &RawBuffer) -> Vec<ProtoOption> {
+    let mut cursor = node.walk();
+    let body = node.children(&mut cursor)
+        .find(|child| matches!(child.kind(), "message_body" | "enum_body"))
+        .unwrap_or(*node);
+
+    let mut cursor = body.walk();
+    let options = body.children(&mut cursor)
+        .filter(|child| child.is_named() && child.kind() == "option")
+        .filter_map(|option| parse_option(&option, buffer))
+        .collect();
+    options
+}
+
+/// `[name = value, ...]` options of a field or enum value
+fn bracket_options(node: &Node, buffer: &RawBuffer) -> Vec<ProtoOption> {
+    let mut options = Vec::new();
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        match child.kind() {
+            "field_options" => options.extend(bracket_options(&child, buffer)),
+            "field_option" | "enum_value_option" => options.extend(parse_option(&child, buffer)),
+            _ => (),
+        }
+    }
+
+    options
+}
+
+/// Everything before the `=` is the name (without the `option` keyword), everything after it the value
+fn parse_option(node: &Node, buffer: &RawBuffer) -> Option<ProtoOption> {
+    let mut cursor = node.walk();
+    let children = node.children(&mut cursor)
+        .filter(|child| !matches!(child.kind(), "option" | ";" | "comment"))
+        .collect::<Vec<_>>();
+    let equals = children.iter().position(|child| child.kind() == "=")?;
+
+    let name = children[..equals].iter().map(|child| child.text(buffer).trim().to_string()).collect::<String>();
+    let value = children[equals + 1..].iter().map(|child| child.text(buffer).trim().to_string()).collect::<String>();
+    if name.is_empty() || value.is_empty() {
+        return None;
+    }
+
+    Some(ProtoOption { name, value })
+}
+
+/// Comment lines directly above a node (without a blank line in between) and a comment after it on the same line
+fn attached_comments(node: &Node, buffer: &RawBuffer) -> ProtoComments {
+    let mut leading = Vec::new();
+
+    let mut next_row = node.start_position().row;
+    let mut current = node.prev_sibling();
+    while let Some(comment) = current.filter(|sibling| sibling.kind() == "comment") {
+        if comment.end_position().row + 1 < next_row {
+            break;
+        }
+
+        // A comment on the same line as the previous statement trails that statement instead
+        if comment.prev_sibling().is_some_and(|prev| prev.kind() != "comment" && prev.end_position().row == comment.start_position().row) {
+            break;
+        }
+
+        leading.splice(0..0, comment_lines(&comment.text(buffer)));
+        next_row = comment.start_position().row;
+        current = comment.prev_sibling();
+    }
+
+    let trailing = node.next_sibling()
+        .filter(|sibling| sibling.kind() == "comment" && sibling.start_position().row == node.end_position().row)
+        .map(|comment| comment_lines(&comment.text(buffer)).join(" "));
+
+    ProtoComments { leading, trailing }
+}
+
+/// Comment text without the `//` or `/* */` markers
+fn comment_lines(comment: &str) -> Vec<String> {
+    if let Some(line) = comment.strip_prefix("//") {
+        return vec![line.trim().to_string()];
+    }
+
+    let mut lines = comment.trim_start_matches("/*").trim_end_matches("*/").lines()
+        .map(|line| line.trim().trim_start_matches('*').trim().to_string())
+        .skip_while(String::is_empty)
+        .collect::<Vec<_>>();
+
+    while lines.last().is_some_and(String::is_empty) {
+        lines.pop();
+    }
+
+    lines
+}
+
+/// Closest definition (message, enum, extend or service) enclosing a node, oneofs are transparent
+fn owner<'tree>(node: &Node<'tree>) -> Option<Node<'tree>> {
+    let mut current = node.parent();
+    while let Some(node) = current {
+        if matches!(node.kind(), "message" | "enum" | "extend" | "service") {
+            return Some(node);
+        }
+        current = node.parent();
+    }
+    None
+}
+
+fn parent_message(node: &Node, buffer: &RawBuffer, proto_db: &ProtoDatabase) -> Option<ProtoName> {
+    let parent = owner(node).filter(|parent| parent.kind() == "message")?;
+    let mut cursor = parent.walk();
+    let name = parent.children(&mut cursor).find(|child| child.kind() == "message_name")?;
+
+    ProtoName::try_lookup(proto_db, name.text(buffer).trim())
+}
+
+/// Contents of the only string literal in a statement, like the version in `syntax = "proto3";`
+fn string_literal(statement: &str) -> Option<String> {
+    statement.split(['"', '\'']).nth(1).map(str::to_string)
+}
+
+/// Parses decimal, hex and octal integer literals
+fn parse_int_lit(text: &str) -> Option<u64> {
+    let text = text.trim();
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()
+    } else if text.len() > 1 && text.starts_with('0') {
+        u64::from_str_radix(&text[1..], 8).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+fn get_simple_field_type(type_node: &Node, context: &ParseContext, proto_db: &ProtoDatabase) -> Result<ProtoType, Diagnostic> {
+    Ok(match type_node.kind() {
         "bool" => ProtoType::Bool,
         "float" => ProtoType::Float,
         "double" => ProtoType::Double,
@@ -102,7 +773,186 @@ fn get_simple_field_type(type_node: &Node, buffer: &RawBuffer, proto_db: &ProtoD
         "sfixed64" => ProtoType::Sfixed64,
         "string" => ProtoType::String,
         "bytes" => ProtoType::Bytes,
-        "message_or_enum_type" => ProtoType::Type(proto_db.lookup_name_by_text(type_node.text(&buffer).as_str())),
-        _ => panic!("Unknown type: {}", type_node.kind()),
+        "message_or_enum_type" => {
+            // Identifiers are registered without their scope, so qualified references resolve by their last segment
+            let text = type_node.text(context.buffer);
+            let name = text.rsplit('.').next().unwrap().trim();
+            ProtoType::Type(ProtoName::try_lookup(proto_db, name).ok_or_else(|| {
+                context.diagnostic(type_node, format!("unknown type `{}`", text), Some("a scalar, message or enum type"))
+            })?)
+        }
+        kind => return Err(context.diagnostic(type_node, format!("unknown type `{}`", kind), Some("a scalar, message or enum type"))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emit::ProtoEmitter;
+
+    #[test]
+    fn test_numbers_out_of_range() {
+        let mut proto_db = ProtoDatabase::new();
+        let parsed = parse_proto_into(&mut proto_db, "
+            message Avatar {
+                uint32 id = 1;
+                uint32 level = 4294967297;
+                uint32 exp = 536870912;
+                uint32 rank = 19500;
+            }
+
+            enum Color {
+                COLOR_NONE = 0;
+                COLOR_MIN = -2147483648;
+                COLOR_RED = 2147483648;
+            }
+        ", "test.proto").unwrap();
+
+        let messages = parsed.diagnostics.iter().map(|diagnostic| diagnostic.message.as_str()).collect::<Vec<_>>();
+        assert_eq!(messages, [
+            "field number 4294967297 is out of range",
+            "field number 536870912 is out of range",
+            "field number 19500 is out of range",
+            "enum value 2147483648 is out of range",
+        ]);
+        assert_eq!(proto_db.get_message("Avatar").unwrap().fields.len(), 1);
+
+        let color = proto_db.enum_db.right_values().next().unwrap();
+        assert_eq!(color.values.iter().map(|value| value.number).collect::<Vec<_>>(), [0, -2147483648]);
+    }
+
+    #[test]
+    fn test_broken_enum_value() {
+        let mut proto_db = ProtoDatabase::new();
+        let parsed = parse_proto_into(&mut proto_db, "
+            enum Color {
+                COLOR_NONE = 0;
+                COLOR_RED = 0x;
+                COLOR_BLUE = 2;
+            }
+        ", "test.proto").unwrap();
+
+        assert_eq!(parsed.diagnostics.len(), 1);
+        let color = proto_db.enum_db.right_values().next().unwrap();
+        assert_eq!(color.values.iter().map(|value| value.name.name(&proto_db)).collect::<Vec<_>>(), ["COLOR_NONE", "COLOR_BLUE"]);
+    }
+
+    #[test]
+    fn test_extension_ranges() {
+        let mut proto_db = ProtoDatabase::new();
+        let parsed = parse_proto_into(&mut proto_db, "
+            syntax = \"proto2\";
+
+            message Avatar {
+                optional uint32 id = 1;
+                extensions 100 to 199, 300 to max;
+            }
+        ", "test.proto").unwrap();
+
+        assert!(parsed.diagnostics.is_empty());
+        let avatar = ProtoName::lookup(&proto_db, "Avatar");
+        assert_eq!(proto_db.extension_ranges[&avatar], [(100, 199), (300, RESERVED_MAX)]);
+
+        let mut emitter = ProtoEmitter::new(&proto_db);
+        emitter.emit_definitions(&[avatar]);
+        assert!(emitter.finish().contains("    extensions 100 to 199, 300 to max;\n"));
+    }
+
+    #[test]
+    fn test_emit_proto2_into_proto3() {
+        let mut proto_db = ProtoDatabase::new();
+        parse_proto_into(&mut proto_db, "
+            syntax = \"proto3\";
+
+            message Player {
+                uint32 id = 1;
+            }
+        ", "player.proto").unwrap();
+        parse_proto_into(&mut proto_db, "
+            syntax = \"proto2\";
+
+            message Avatar {
+                required uint32 id = 1;
+                optional int32 level = 2 [default = 5, deprecated = true];
+                extensions 100 to max;
+            }
+
+            extend Avatar {
+                optional int32 ext = 100;
+            }
+        ", "avatar.proto").unwrap();
+
+        let mut emitter = ProtoEmitter::new(&proto_db);
+        emitter.emit_header();
+        emitter.emit_definitions(&proto_db.top_level_definitions());
+        emitter.emit_extensions(&proto_db.child_extensions(None));
+        let output = emitter.finish();
+
+        assert!(output.starts_with("syntax = \"proto3\";\n"));
+        assert!(output.contains("    uint32 id = 1;\n    optional int32 level = 2 [deprecated = true];\n}\n"));
+        assert!(!output.contains("extensions") && !output.contains("extend"));
+    }
+
+    #[test]
+    fn test_duplicate_definition() {
+        let mut proto_db = ProtoDatabase::new();
+        let parsed = parse_proto_into(&mut proto_db, "
+            message A {
+                enum Kind { X = 0; }
+                Kind kind = 1;
+            }
+            message B {
+                enum Kind { Y = 0; }
+                Kind kind = 1;
+            }
+            message Outer {
+                message Outer {}
+            }
+        ", "test.proto").unwrap();
+
+        let messages = parsed.diagnostics.iter().map(|diagnostic| diagnostic.message.as_str()).collect::<Vec<_>>();
+        assert_eq!(messages, ["`Outer` is defined already at test.proto:10:13, this definition is skipped", "`Kind` is defined already at test.proto:3:17, this definition is skipped"]);
+
+        // The first definitions are kept as they were
+        let kind = proto_db.enum_db.get_by_left(&ProtoName::lookup(&proto_db, "Kind")).unwrap();
+        assert_eq!(kind.parent, Some(ProtoName::lookup(&proto_db, "A")));
+        assert_eq!(kind.values.len(), 1);
+        assert_eq!(proto_db.parent_of(&ProtoName::lookup(&proto_db, "Outer")), None);
+    }
+
+    #[test]
+    fn test_group_unsupported() {
+        let parsed = parse_proto_into(&mut ProtoDatabase::new(), "
+            syntax = \"proto2\";
+
+            message Avatar {
+                repeated group Skill = 1 {
+                    optional uint32 level = 1;
+                }
+            }
+        ", "test.proto").unwrap();
+
+        assert_eq!(parsed.diagnostics[0].message, "`group` fields are not supported");
+    }
+
+    #[test]
+    fn test_range_out_of_range() {
+        let mut proto_db = ProtoDatabase::new();
+        let parsed = parse_proto_into(&mut proto_db, "
+            message Avatar {
+                reserved 1 to 99999999999999999999, 536870912, 4 to 5;
+            }
+        ", "test.proto").unwrap();
+
+        let messages = parsed.diagnostics.iter().map(|diagnostic| diagnostic.message.as_str()).collect::<Vec<_>>();
+        assert_eq!(messages, ["number `99999999999999999999` is out of range", "range bound 536870912 is out of range"]);
+        assert_eq!(proto_db.reserved[&ProtoName::lookup(&proto_db, "Avatar")].ranges, [(4, 5)]);
+    }
+
+    #[test]
+    fn test_comment_lines() {
+        assert_eq!(comment_lines("//   Obf: JNLOABDHEIH  "), vec!["Obf: JNLOABDHEIH"]);
+        assert_eq!(comment_lines("/*\n * first\n *\n * second\n */"), vec!["first", "", "second"]);
+        assert_eq!(comment_lines("/* inline */"), vec!["inline"]);
     }
 }
@@ -0,0 +1,99 @@
+use std::fmt::{self, Write};
+
+use regex::Regex;
+
+use crate::prototype::ProtoResolutionError;
+
+/// A field mapping decided by hand, applied by the matcher like any other resolution
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pin {
+    /// Message name as it is in a
+    pub message: String,
+    /// Field name in b before it was resolved
+    pub field_b: String,
+    pub field_a: String,
+}
+
+impl fmt::Display for Pin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{} -> {}", self.message, self.field_b, self.field_a)
+    }
+}
+
+#[derive(Debug)]
+pub enum PinError {
+    /// Not in both schemas (yet), b's message may only be resolved later
+    MessageNotFound,
+    FieldNotFound(String),
+    /// b's field is already resolved to another name
+    AlreadyResolved(String),
+    Resolution(ProtoResolutionError),
+}
+
+impl fmt::Display for PinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PinError::MessageNotFound => write!(f, "message is not in both schemas"),
+            PinError::FieldNotFound(name) => write!(f, "no field {} in the message", name),
+            PinError::AlreadyResolved(name) => write!(f, "field is already resolved to {}", name),
+            PinError::Resolution(e) => write!(f, "cannot resolve: {:?}", e),
+        }
+    }
+}
+
+/// Pins as written by `match --review`, one `Message.FIELDB -> field_a` per line
+#[derive(Debug, Default)]
+pub struct PinFile {
+    pub pins: Vec<Pin>,
+}
+
+impl PinFile {
+    pub fn parse(source: &str) -> Self {
+        let re = Regex::new(r"^\s*(\w+)\.(\w+)\s*->\s*(\w+)\s*$").unwrap();
+
+        // Comments and anything else are skipped
+        let pins = source.lines()
+            .filter_map(|line| re.captures(line))
+            .map(|cap| Pin {
+                message: cap[1].to_string(),
+                field_b: cap[2].to_string(),
+                field_a: cap[3].to_string(),
+            })
+            .collect();
+
+        Self { pins }
+    }
+
+    /// Adds a pin, replacing an earlier decision for the same field
+    pub fn add(&mut self, pin: Pin) {
+        self.pins.retain(|other| !(other.message == pin.message && other.field_b == pin.field_b));
+        self.pins.push(pin);
+    }
+
+    pub fn render(&self) -> String {
+        let mut output = String::from("# Manual field mappings, `Message.field_in_b -> field_in_a`\n");
+        for pin in &self.pins {
+            writeln!(output, "{}", pin).unwrap();
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pin_file() {
+        let mut pin_file = PinFile::parse("
+            # Manual field mappings
+            Avatar.BCDEFGHIJKL -> exp
+            Avatar.CDEFGHIJKLM -> exp
+        ");
+        assert_eq!(pin_file.pins.len(), 2);
+
+        pin_file.add(Pin { message: "Avatar".to_string(), field_b: "CDEFGHIJKLM".to_string(), field_a: "rank".to_string() });
+        assert_eq!(PinFile::parse(&pin_file.render()).pins, pin_file.pins);
+        assert_eq!(pin_file.pins[1].to_string(), "Avatar.CDEFGHIJKLM -> rank");
+    }
+}
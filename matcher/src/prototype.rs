@@ -1,9 +1,10 @@
 #![allow(dead_code)] // TODO: Remove this
 
-use std::{collections::HashMap, fmt::{self, Debug}, hash::Hash};
+use std::{collections::{HashMap, HashSet}, fmt::{self, Debug}, hash::Hash};
 
 use bimap::BiHashMap;
 use matcher_macros::DebugWithName;
+use tracing::{debug, warn};
 
 use crate::debug::DebugWithName;
 
@@ -15,17 +16,90 @@ pub struct ProtoName {
 
 impl DebugWithName for ProtoName {
     fn debug_with_name(&self, db: &ProtoDatabase) -> String {
-        format!("ProtoName({} => {})", self.id, db.identifier_db.get_by_right(&self.id).unwrap_or(&"ERROR".to_string()))
+        format!("ProtoName({} => {})", self.id, db.identifier_db.name(self.id).unwrap_or(&"ERROR".to_string()))
     }
 }
 
 impl ProtoName {
     pub fn lookup(db: &ProtoDatabase, name: &str) -> Self {
-        Self { id: *db.identifier_db.get_by_left(name).unwrap() }
+        Self::try_lookup(db, name).unwrap()
+    }
+
+    /// Identifier with this name, a message, enum or service before fields that were resolved to the same name
+    pub fn try_lookup(db: &ProtoDatabase, name: &str) -> Option<Self> {
+        let ids = db.identifier_db.ids(name);
+        ids.iter()
+            .map(|&id| Self { id })
+            .find(|name| db.is_definition(name))
+            .or_else(|| ids.first().map(|&id| Self { id }))
     }
 
     pub fn name(&self, db: &ProtoDatabase) -> String {
-        db.identifier_db.get_by_right(&self.id).unwrap().clone()
+        db.identifier_db.name(self.id).unwrap().clone()
+    }
+}
+
+/// Location of a definition or identifier in one of the database's source files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    /// Index into `ProtoDatabase::source_files`
+    pub file: usize,
+    pub start_byte: usize,
+    pub end_byte: usize,
+    /// 1-based
+    pub line: usize,
+    /// 1-based, in characters
+    pub column: usize,
+}
+
+impl Span {
+    pub fn location(&self, db: &ProtoDatabase) -> String {
+        let file = db.source_files.get(self.file).map_or("<unknown>", String::as_str);
+        format!("{}:{}:{}", file, self.line, self.column)
+    }
+}
+
+impl DebugWithName for Span {
+    fn debug_with_name(&self, db: &ProtoDatabase) -> String {
+        self.location(db)
+    }
+}
+
+/// What comments and options are attached to, fields are identified by number since field names are shared between messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DefinitionRef {
+    /// Index into `ProtoDatabase::source_files`
+    File(usize),
+    Definition(ProtoName),
+    Field(ProtoName, u32),
+    EnumValue(ProtoName, i32),
+    /// Service and method name
+    Rpc(ProtoName, ProtoName),
+}
+
+/// `name = value` of an option statement or a `[...]` option, both kept as written (e.g. `(ext).name`)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProtoOption {
+    pub name: String,
+    pub value: String,
+}
+
+impl ProtoOption {
+    pub fn is_custom(&self) -> bool {
+        self.name.starts_with('(')
+    }
+}
+
+/// Comment lines directly above a definition, and a comment after it on the same line
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProtoComments {
+    pub leading: Vec<String>,
+    pub trailing: Option<String>,
+}
+
+impl ProtoComments {
+    pub fn is_empty(&self) -> bool {
+        self.leading.is_empty() && self.trailing.is_none()
     }
 }
 
@@ -33,12 +107,158 @@ impl ProtoName {
 pub struct ProtoMessage {
     pub name: ProtoName,
     pub fields: Vec<ProtoField>,
+    pub oneofs: Vec<ProtoName>,
+    pub parent: Option<ProtoName>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, DebugWithName)]
+pub struct ProtoEnum {
+    pub name: ProtoName,
+    pub values: Vec<ProtoEnumValue>,
+    pub parent: Option<ProtoName>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, DebugWithName)]
+pub struct ProtoEnumValue {
+    pub name: ProtoName,
+    pub number: i32,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, DebugWithName)]
+pub struct ProtoService {
+    pub name: ProtoName,
+    pub rpcs: Vec<ProtoRpc>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, DebugWithName)]
+pub struct ProtoRpc {
+    pub name: ProtoName,
+    pub request: ProtoType,
+    pub request_stream: bool,
+    pub response: ProtoType,
+    pub response_stream: bool,
+    pub span: Span,
+}
+
+impl ProtoRpc {
+    /// `(request, stream) -> (response, stream)` by resolved type names, `None` while either type is unresolved
+    pub fn signature(&self, db: &ProtoDatabase) -> Option<(String, bool, String, bool)> {
+        let resolved_name = |typ: &ProtoType| match typ {
+            ProtoType::Type(name) if !db.is_resolved(name) => None,
+            typ => Some(typ.type_name(db)),
+        };
+
+        Some((resolved_name(&self.request)?, self.request_stream, resolved_name(&self.response)?, self.response_stream))
+    }
+}
+
+/// Renames `other` in `other_db` to the name of `source` in `source_db`, for names that aren't tied to a type
+pub fn resolve_name(source_db: &ProtoDatabase, source: &ProtoName, other_db: &mut ProtoDatabase, other: &ProtoName) -> Result<(), ProtoResolutionError> {
+    if !source_db.is_resolved(source) {
+        return Err(ProtoResolutionError::SourceNotResolved);
+    }
+
+    if other_db.is_resolved(other) {
+        return Err(ProtoResolutionError::TargetAlreadyResolved);
+    }
+
+    debug!(from = %other.debug_with_name(other_db), to = %source.debug_with_name(source_db), "Resolving name");
+    other_db.resolve_identifier(other.id, source.name(source_db))
+}
+
+/// `syntax` or `edition` of a file, files without either are proto2
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub enum ProtoSyntax {
+    #[default]
+    Proto2,
+    Proto3,
+    Edition(String),
+}
+
+impl ProtoSyntax {
+    /// From the string in a `syntax` statement
+    pub fn from_syntax(version: &str) -> Option<Self> {
+        match version {
+            "proto2" => Some(ProtoSyntax::Proto2),
+            "proto3" => Some(ProtoSyntax::Proto3),
+            _ => None,
+        }
+    }
+
+    pub fn is_edition(&self) -> bool {
+        matches!(self, ProtoSyntax::Edition(_))
+    }
+
+    /// `syntax = "..."` or `edition = "..."` statement
+    pub fn statement(&self) -> String {
+        match self {
+            ProtoSyntax::Proto2 => "syntax = \"proto2\";".to_string(),
+            ProtoSyntax::Proto3 => "syntax = \"proto3\";".to_string(),
+            ProtoSyntax::Edition(edition) => format!("edition = \"{}\";", edition),
+        }
+    }
+}
+
+/// Whether a singular field tracks if it was set, spelled as labels before editions and as the `field_presence` feature since
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FieldPresence {
+    Explicit,
+    Implicit,
+    LegacyRequired,
+}
+
+impl FieldPresence {
+    /// From the value of `features.field_presence`, unknown values are the edition 2023 default
+    pub fn from_feature(value: &str) -> Self {
+        match value {
+            "IMPLICIT" => FieldPresence::Implicit,
+            "LEGACY_REQUIRED" => FieldPresence::LegacyRequired,
+            _ => FieldPresence::Explicit,
+        }
+    }
+
+    /// Value of `features.field_presence`
+    pub fn feature_value(&self) -> &'static str {
+        match self {
+            FieldPresence::Explicit => "EXPLICIT",
+            FieldPresence::Implicit => "IMPLICIT",
+            FieldPresence::LegacyRequired => "LEGACY_REQUIRED",
+        }
+    }
+}
+
+pub const FIELD_PRESENCE_FEATURE: &str = "features.field_presence";
+
+/// `reserved` statements of a message or enum
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProtoReserved {
+    /// Inclusive, `to max` is stored as `RESERVED_MAX`
+    pub ranges: Vec<(i64, i64)>,
+    pub names: Vec<String>,
+}
+
+pub const RESERVED_MAX: i64 = i64::MAX;
+
+/// An `extend` block, adding fields to a message that is usually defined elsewhere
+#[derive(Debug, Clone, PartialEq, Eq, Hash, DebugWithName)]
+pub struct ProtoExtension {
+    pub extendee: ProtoName,
+    pub fields: Vec<ProtoField>,
+    /// Message the block is nested in
+    pub parent: Option<ProtoName>,
+    pub span: Span,
 }
 
 #[derive(Debug)]
 pub enum ProtoResolutionError {
     TypeIsPrimitive,
     TargetAlreadyResolved,
+    /// Another identifier of the target already has the name, the target keeps its own
+    NameTaken(String),
     SourceNotResolved, // TODO: Probably shouldn't be an error
 }
 
@@ -49,7 +269,28 @@ pub trait LogIfErr {
 impl <T> LogIfErr for Result<T, ProtoResolutionError> {
     fn log_if_err(&self) {
         if let Err(e) = self {
-            println!("Warning: {:?}", e);
+            warn!("{:?}", e);
+        }
+    }
+}
+
+/// Label before a field's type, `repeated` is part of `ProtoFieldKind`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, DebugWithName)]
+pub enum ProtoLabel {
+    /// Implicit presence in proto3, the same as `optional` in proto2
+    #[default]
+    None,
+    /// Explicit presence, in proto2 as well as proto3
+    Optional,
+    Required,
+}
+
+impl ProtoLabel {
+    pub fn keyword(&self) -> Option<&'static str> {
+        match self {
+            ProtoLabel::None => None,
+            ProtoLabel::Optional => Some("optional"),
+            ProtoLabel::Required => Some("required"),
         }
     }
 }
@@ -57,8 +298,11 @@ impl <T> LogIfErr for Result<T, ProtoResolutionError> {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, DebugWithName)]
 pub struct ProtoField {
     pub name: ProtoName,
+    pub label: ProtoLabel,
     pub field_type: ProtoFieldKind,
     pub field_number: u32,
+    pub oneof: Option<ProtoName>,
+    pub span: Span,
 }
 
 impl ProtoField {
@@ -82,11 +326,12 @@ impl ProtoField {
         }
 
         // Update target field name
-        println!("Resolving field {} -> {}", self.name.debug_with_name(self_db), other.name.debug_with_name(other_db));
-        other_db.identifier_db.insert(self_db.identifier_db.get_by_right(&self.name.id).unwrap().clone(), other.name.id);
+        debug!(from = %other.name.debug_with_name(other_db), to = %self.name.debug_with_name(self_db), "Resolving field");
+        other_db.resolve_identifier(other.name.id, self.name.name(self_db))?;
 
-        // Mark the target field as resolved
-        other_db.identifier_resolutions.insert(other.name.id, true);
+        if self.label != other.label {
+            debug!(from = ?self.label, to = ?other.label, "Label changed");
+        }
 
         Ok(())
     }
@@ -113,6 +358,29 @@ impl ProtoFieldKind {
         matches!(self.inner_type(), ProtoType::Type(_))
     }
 
+    /// Message and enum types referenced by this field
+    pub fn type_refs(&self) -> Vec<ProtoName> {
+        let types = match self {
+            ProtoFieldKind::Scalar(a) | ProtoFieldKind::Repeated(a) => vec![a],
+            ProtoFieldKind::Map(a, b) => vec![a, b],
+        };
+
+        types.into_iter()
+            .filter_map(|typ| match typ {
+                ProtoType::Type(name) => Some(*name),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn type_name(&self, db: &ProtoDatabase) -> String {
+        match self {
+            ProtoFieldKind::Scalar(a) => a.type_name(db),
+            ProtoFieldKind::Map(a, b) => format!("map<{}, {}>", a.type_name(db), b.type_name(db)),
+            ProtoFieldKind::Repeated(a) => format!("repeated {}", a.type_name(db)),
+        }
+    }
+
     pub fn eq_resolved_type(&self, self_db: &ProtoDatabase, other: &Self, other_db: &ProtoDatabase) -> bool {
         // TODO: This is hacky, but it works for now
         if std::mem::discriminant(self) != std::mem::discriminant(other) {
@@ -154,6 +422,27 @@ pub enum ProtoType {
 }
 
 impl ProtoType {
+    pub fn type_name(&self, db: &ProtoDatabase) -> String {
+        match self {
+            ProtoType::Bool => "bool".to_string(),
+            ProtoType::Float => "float".to_string(),
+            ProtoType::Double => "double".to_string(),
+            ProtoType::Int32 => "int32".to_string(),
+            ProtoType::Int64 => "int64".to_string(),
+            ProtoType::Uint32 => "uint32".to_string(),
+            ProtoType::Uint64 => "uint64".to_string(),
+            ProtoType::Sint32 => "sint32".to_string(),
+            ProtoType::Sint64 => "sint64".to_string(),
+            ProtoType::Fixed32 => "fixed32".to_string(),
+            ProtoType::Fixed64 => "fixed64".to_string(),
+            ProtoType::Sfixed32 => "sfixed32".to_string(),
+            ProtoType::Sfixed64 => "sfixed64".to_string(),
+            ProtoType::String => "string".to_string(),
+            ProtoType::Bytes => "bytes".to_string(),
+            ProtoType::Type(name) => name.name(db),
+        }
+    }
+
     pub fn try_resolve_in(&self, self_db: &ProtoDatabase, other_db: &mut ProtoDatabase, other: &ProtoType) -> Result<(), ProtoResolutionError> {
         match (self, other) {
             (ProtoType::Type(name), ProtoType::Type(other_name)) => {
@@ -168,13 +457,8 @@ impl ProtoType {
                 }
 
                 // Update target type name
-                println!("Resolving type {} -> {}", name.debug_with_name(self_db), other_name.debug_with_name(other_db));
-                other_db.identifier_db.insert(self_db.identifier_db.get_by_right(&name.id).unwrap().clone(), other_name.id);
-
-                // Mark the target type as resolved
-                other_db.identifier_resolutions.insert(other_name.id, true);
-
-                return Ok(());
+                debug!(from = %other_name.debug_with_name(other_db), to = %name.debug_with_name(self_db), "Resolving type");
+                return other_db.resolve_identifier(other_name.id, name.name(self_db));
             }
             _ => Err(ProtoResolutionError::TypeIsPrimitive),
         }
@@ -255,17 +539,98 @@ impl From<ProtoFieldKind> for WeakProtoFieldKind {
     }
 }
 
+/// Current name of every identifier, several identifiers can share one (e.g. fields of different messages)
+#[derive(Debug, Clone, Default)]
+pub struct IdentifierTable {
+    names: HashMap<usize, String>,
+    ids: HashMap<String, Vec<usize>>,
+}
+
+impl IdentifierTable {
+    pub fn name(&self, id: usize) -> Option<&String> {
+        self.names.get(&id)
+    }
+
+    /// Identifiers that have this name, in the order they got it
+    pub fn ids(&self, name: &str) -> &[usize] {
+        self.ids.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn contains_name(&self, name: &str) -> bool {
+        self.ids.contains_key(name)
+    }
+
+    /// Distinct names
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.ids.keys()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ProtoName, &String)> {
+        self.names.iter().map(|(&id, name)| (ProtoName { id }, name))
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Gives an identifier a name, replacing the one it had
+    pub fn insert(&mut self, name: String, id: usize) {
+        self.remove(id);
+        self.ids.entry(name.clone()).or_default().push(id);
+        self.names.insert(id, name);
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<String> {
+        let name = self.names.remove(&id)?;
+        let ids = self.ids.get_mut(&name).unwrap();
+        ids.retain(|&other| other != id);
+        if ids.is_empty() {
+            self.ids.remove(&name);
+        }
+        Some(name)
+    }
+}
+
+#[derive(Clone)]
 pub struct ProtoDatabase {
     pub identifier_counter: usize,
-    pub identifier_db: BiHashMap<String, usize>,
+    pub identifier_db: IdentifierTable,
     pub identifier_db_original: BiHashMap<String, usize>,
     pub identifier_resolutions: HashMap<usize, bool>,
     pub message_db: BiHashMap<ProtoName, ProtoMessage>,
+    pub enum_db: BiHashMap<ProtoName, ProtoEnum>,
+    pub service_db: BiHashMap<ProtoName, ProtoService>,
+    /// Syntax of the first file that declared one, used for the header of emitted files
+    pub syntax: Option<ProtoSyntax>,
+    /// Syntax of every source file, by index into `source_files`
+    pub file_syntax: HashMap<usize, ProtoSyntax>,
+    /// Package of the first file that declared one, used for the `package` statement of emitted files
+    pub package: Option<String>,
+    /// Package of every source file that declares one, by index into `source_files`
+    pub file_packages: HashMap<usize, String>,
+    /// File each message and enum was loaded from, when loaded through `ProtoLoader`
+    pub definition_files: HashMap<ProtoName, String>,
+    /// Files the spans point into
+    pub source_files: Vec<String>,
+    /// Every place an identifier appears in the sources, by identifier id
+    pub identifier_occurrences: HashMap<usize, Vec<Span>>,
+    pub comments: HashMap<DefinitionRef, ProtoComments>,
+    /// Comments the matcher copied over from the other schema, the sources don't have them
+    pub carried_comments: HashSet<DefinitionRef>,
+    pub options: HashMap<DefinitionRef, Vec<ProtoOption>>,
+    pub reserved: HashMap<ProtoName, ProtoReserved>,
+    /// `extensions` ranges of a message, inclusive like reserved ranges
+    pub extension_ranges: HashMap<ProtoName, Vec<(i64, i64)>>,
+    pub extensions: Vec<ProtoExtension>,
 }
 
 impl Debug for ProtoDatabase {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "ProtoDatabase {{ identifier_counter: {}, identifier_db: {}, identifier_db_original: {}, message_db: {} }}", self.identifier_counter, self.identifier_db.len(), self.identifier_db_original.len(), self.message_db.len())
+        write!(f, "ProtoDatabase {{ identifier_counter: {}, identifier_db: {}, identifier_db_original: {}, message_db: {}, enum_db: {}, service_db: {} }}", self.identifier_counter, self.identifier_db.len(), self.identifier_db_original.len(), self.message_db.len(), self.enum_db.len(), self.service_db.len())
     }
 }
 
@@ -273,10 +638,25 @@ impl ProtoDatabase {
     pub fn new() -> Self {
         Self {
             identifier_counter: 0,
-            identifier_db: BiHashMap::new(),
+            identifier_db: IdentifierTable::default(),
             identifier_db_original: BiHashMap::new(),
             identifier_resolutions: HashMap::new(),
             message_db: BiHashMap::new(),
+            enum_db: BiHashMap::new(),
+            service_db: BiHashMap::new(),
+            syntax: None,
+            file_syntax: HashMap::new(),
+            package: None,
+            file_packages: HashMap::new(),
+            definition_files: HashMap::new(),
+            source_files: Vec::new(),
+            identifier_occurrences: HashMap::new(),
+            comments: HashMap::new(),
+            carried_comments: HashSet::new(),
+            options: HashMap::new(),
+            reserved: HashMap::new(),
+            extension_ranges: HashMap::new(),
+            extensions: Vec::new(),
         }
     }
 
@@ -288,7 +668,7 @@ impl ProtoDatabase {
     }
 
     pub fn register_identifier(&mut self, text: String) -> usize {
-        if let Some(&id) = self.identifier_db.get_by_left(&text) {
+        if let Some(&id) = self.identifier_db.ids(&text).first() {
             id
         } else {
             let id = self.identifier_counter;
@@ -300,6 +680,79 @@ impl ProtoDatabase {
         }
     }
 
+    /// Renames an identifier and marks it resolved, unless another identifier in its scope already has the name
+    pub fn resolve_identifier(&mut self, id: usize, name: String) -> Result<(), ProtoResolutionError> {
+        assert!(self.identifier_db.name(id).is_some(), "Identifier is registered");
+        let taken = self.identifier_db.ids(&name).iter()
+            .any(|&other| other != id && self.share_scope(ProtoName { id }, ProtoName { id: other }));
+        if taken {
+            return Err(ProtoResolutionError::NameTaken(name));
+        }
+
+        self.identifier_db.insert(name, id);
+        self.identifier_resolutions.insert(id, true);
+        Ok(())
+    }
+
+    /// Whether two identifiers would clash with the same name: messages, enums and services are looked up by name everywhere,
+    /// fields and oneofs only within their message, enum values within their enum and methods within their service
+    fn share_scope(&self, a: ProtoName, b: ProtoName) -> bool {
+        if self.is_definition(&a) && self.is_definition(&b) {
+            return true;
+        }
+
+        let both = |names: &mut dyn Iterator<Item = ProtoName>| {
+            let names = names.collect::<Vec<_>>();
+            names.contains(&a) && names.contains(&b)
+        };
+        self.message_db.right_values().any(|message| both(&mut message.fields.iter().map(|field| field.name).chain(message.oneofs.iter().copied())))
+            || self.enum_db.right_values().any(|proto_enum| both(&mut proto_enum.values.iter().map(|value| value.name)))
+            || self.service_db.right_values().any(|service| both(&mut service.rpcs.iter().map(|rpc| rpc.name)))
+            || self.extensions.iter().any(|extension| both(&mut extension.fields.iter().map(|field| field.name)))
+    }
+
+    pub fn register_occurrence(&mut self, id: usize, span: Span) {
+        self.identifier_occurrences.entry(id).or_default().push(span);
+    }
+
+    pub fn occurrences(&self, proto_name: &ProtoName) -> &[Span] {
+        self.identifier_occurrences.get(&proto_name.id).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn register_comments(&mut self, target: DefinitionRef, comments: ProtoComments) {
+        if !comments.is_empty() {
+            self.comments.insert(target, comments);
+        }
+    }
+
+    pub fn register_options(&mut self, target: DefinitionRef, options: Vec<ProtoOption>) {
+        if !options.is_empty() {
+            self.options.insert(target, options);
+        }
+    }
+
+    pub fn options_of(&self, target: DefinitionRef) -> &[ProtoOption] {
+        self.options.get(&target).map_or(&[], Vec::as_slice)
+    }
+
+    /// Options of every loaded file, the first file to set an option wins
+    pub fn file_options(&self) -> Vec<ProtoOption> {
+        let mut options: Vec<ProtoOption> = Vec::new();
+        for file in 0..self.source_files.len() {
+            for option in self.options_of(DefinitionRef::File(file)) {
+                if !options.iter().any(|existing| existing.name == option.name) {
+                    options.push(option.clone());
+                }
+            }
+        }
+        options
+    }
+
+    pub fn register_source_file(&mut self, name: &str) -> usize {
+        self.source_files.push(name.to_string());
+        self.source_files.len() - 1
+    }
+
     pub fn is_resolved(&self, proto_name: &ProtoName) -> bool {
         *self.identifier_resolutions.get(&proto_name.id).unwrap_or(&false)
     }
@@ -312,12 +765,185 @@ impl ProtoDatabase {
         self.message_db.insert(message.name.clone(), message);
     }
 
+    pub fn register_enum(&mut self, proto_enum: ProtoEnum) {
+        self.enum_db.insert(proto_enum.name, proto_enum);
+    }
+
+    pub fn register_service(&mut self, service: ProtoService) {
+        self.service_db.insert(service.name, service);
+    }
+
+    /// Whether the identifier names a message, enum or service
+    pub fn is_definition(&self, name: &ProtoName) -> bool {
+        self.is_defined(name) || self.service_db.contains_left(name)
+    }
+
+    /// Whether a message or enum with this name is defined, as opposed to only being referenced
+    pub fn is_defined(&self, name: &ProtoName) -> bool {
+        self.message_db.contains_left(name) || self.enum_db.contains_left(name)
+    }
+
+    /// Parent message of a nested message or enum, `None` for top-level and undefined types
+    pub fn parent_of(&self, name: &ProtoName) -> Option<ProtoName> {
+        match self.message_db.get_by_left(name) {
+            Some(message) => message.parent,
+            None => self.enum_db.get_by_left(name).and_then(|proto_enum| proto_enum.parent),
+        }
+    }
+
+    /// Outermost message containing a definition, or the definition itself if it is top-level
+    pub fn top_level_of(&self, name: &ProtoName) -> ProtoName {
+        let mut name = *name;
+        while let Some(parent) = self.parent_of(&name) {
+            name = parent;
+        }
+        name
+    }
+
+    /// Messages nested directly in `parent`, or all top-level messages for `None`, sorted by name
+    pub fn child_messages(&self, parent: Option<ProtoName>) -> Vec<&ProtoMessage> {
+        let mut messages = self.message_db.right_values()
+            .filter(|message| message.parent == parent)
+            .collect::<Vec<_>>();
+        messages.sort_by_key(|message| message.name.name(self));
+        messages
+    }
+
+    /// Enums nested directly in `parent`, or all top-level enums for `None`, sorted by name
+    pub fn child_enums(&self, parent: Option<ProtoName>) -> Vec<&ProtoEnum> {
+        let mut enums = self.enum_db.right_values()
+            .filter(|proto_enum| proto_enum.parent == parent)
+            .collect::<Vec<_>>();
+        enums.sort_by_key(|proto_enum| proto_enum.name.name(self));
+        enums
+    }
+
+    pub fn register_syntax(&mut self, file: usize, syntax: ProtoSyntax, declared: bool) {
+        if declared && self.syntax.is_none() {
+            self.syntax = Some(syntax.clone());
+        }
+        self.file_syntax.insert(file, syntax);
+    }
+
+    pub fn register_package(&mut self, file: usize, package: String) {
+        if self.package.is_none() {
+            self.package = Some(package.clone());
+        }
+        self.file_packages.insert(file, package);
+    }
+
+    pub fn package_of(&self, file: usize) -> Option<&str> {
+        self.file_packages.get(&file).map(String::as_str)
+    }
+
+    pub fn syntax_of(&self, file: usize) -> ProtoSyntax {
+        self.file_syntax.get(&file).cloned().unwrap_or_default()
+    }
+
+    /// Singular message fields, these always track presence
+    pub fn is_message_field(&self, field: &ProtoField) -> bool {
+        match field.field_type {
+            ProtoFieldKind::Scalar(ProtoType::Type(name)) => !self.enum_db.contains_left(&name),
+            _ => false,
+        }
+    }
+
+    /// Presence of a singular field under the rules of the file it is defined in, `None` for repeated and map fields
+    pub fn field_presence(&self, message: ProtoName, field: &ProtoField) -> Option<FieldPresence> {
+        if !matches!(field.field_type, ProtoFieldKind::Scalar(_)) {
+            return None;
+        }
+
+        if field.oneof.is_some() || self.is_message_field(field) {
+            return Some(FieldPresence::Explicit);
+        }
+
+        Some(match self.syntax_of(field.span.file) {
+            ProtoSyntax::Proto2 => match field.label {
+                ProtoLabel::Required => FieldPresence::LegacyRequired,
+                _ => FieldPresence::Explicit,
+            },
+            ProtoSyntax::Proto3 => match field.label {
+                ProtoLabel::Optional => FieldPresence::Explicit,
+                _ => FieldPresence::Implicit,
+            },
+            ProtoSyntax::Edition(_) => {
+                // Features are inherited from the file through the enclosing messages, the innermost setting wins
+                let mut scopes = vec![DefinitionRef::Field(message, field.field_number), DefinitionRef::Definition(message)];
+                let mut parent = self.parent_of(&message);
+                while let Some(name) = parent {
+                    scopes.push(DefinitionRef::Definition(name));
+                    parent = self.parent_of(&name);
+                }
+                scopes.push(DefinitionRef::File(field.span.file));
+
+                scopes.into_iter()
+                    .find_map(|scope| self.options_of(scope).iter().find(|option| option.name == FIELD_PRESENCE_FEATURE))
+                    .map_or(FieldPresence::Explicit, |option| FieldPresence::from_feature(&option.value))
+            }
+        })
+    }
+
+    /// `extend` blocks nested directly in `parent`, or all top-level ones for `None`, in source order
+    pub fn child_extensions(&self, parent: Option<ProtoName>) -> Vec<&ProtoExtension> {
+        self.extensions.iter()
+            .filter(|extension| extension.parent == parent)
+            .collect()
+    }
+
+    /// Value of a field's `default` option (proto2), as written in the source
+    pub fn default_value(&self, message: ProtoName, field: &ProtoField) -> Option<&str> {
+        self.options_of(DefinitionRef::Field(message, field.field_number)).iter()
+            .find(|option| option.name == "default")
+            .map(|option| option.value.as_str())
+    }
+
+    /// Top-level messages, enums and services, sorted by name
+    pub fn top_level_definitions(&self) -> Vec<ProtoName> {
+        let mut names = self.child_messages(None).into_iter().map(|message| message.name)
+            .chain(self.child_enums(None).into_iter().map(|proto_enum| proto_enum.name))
+            .chain(self.service_db.left_values().copied())
+            .collect::<Vec<_>>();
+        names.sort_by_key(|name| name.name(self));
+        names
+    }
+
+    /// Where a definition, field, enum value or method is declared, `None` for files and extension fields
+    pub fn span_of(&self, target: DefinitionRef) -> Option<Span> {
+        match target {
+            DefinitionRef::File(_) => None,
+            DefinitionRef::Definition(name) => self.message_db.get_by_left(&name).map(|message| message.span)
+                .or_else(|| self.enum_db.get_by_left(&name).map(|proto_enum| proto_enum.span))
+                .or_else(|| self.service_db.get_by_left(&name).map(|service| service.span)),
+            DefinitionRef::Field(message, number) => self.message_db.get_by_left(&message)?.fields.iter()
+                .find(|field| field.field_number == number)
+                .map(|field| field.span),
+            DefinitionRef::EnumValue(proto_enum, number) => self.enum_db.get_by_left(&proto_enum)?.values.iter()
+                .find(|value| value.number == number)
+                .map(|value| value.span),
+            DefinitionRef::Rpc(service, rpc) => self.service_db.get_by_left(&service)?.rpcs.iter()
+                .find(|other| other.name == rpc)
+                .map(|rpc| rpc.span),
+        }
+    }
+
     pub fn get_message(&self, name: &str) -> Option<ProtoMessage> {
-        self.message_db.get_by_left(&ProtoName::lookup(&self, name)).map(|m| m.clone())
+        self.message_db.get_by_left(&ProtoName::try_lookup(self, name)?).cloned()
+    }
+
+    /// Name of an identifier as it was registered, before any resolution
+    pub fn original_name(&self, proto_name: &ProtoName) -> String {
+        self.identifier_db_original.get_by_right(&proto_name.id).unwrap().clone()
+    }
+
+    /// Current (possibly resolved) name of an identifier, looked up by the text it had in the source
+    pub fn translate_name(&self, original: &str) -> Option<String> {
+        let id = self.identifier_db_original.get_by_left(original)?;
+        self.identifier_db.name(*id).cloned()
     }
 
     pub fn generate_nametranslation(&self) -> HashMap<String, String> {
-        self.identifier_db_original.iter().map(|(k, v)| (k.clone(), self.identifier_db.get_by_right(v).unwrap().clone())).collect()
+        self.identifier_db_original.iter().map(|(k, v)| (k.clone(), self.identifier_db.name(*v).unwrap().clone())).collect()
     }
 }
 
@@ -326,6 +952,7 @@ impl ProtoDatabase {
 
 mod tests {
     use super::*;
+    use crate::util::parse_test_proto;
     
     #[test]
     fn test_type_eq() {
@@ -333,4 +960,46 @@ mod tests {
         let map2 = ProtoFieldKind::Map(ProtoType::String, ProtoType::Uint32);
         assert_eq!(map1, map2);
     }
+
+    #[test]
+    fn test_field_presence() {
+        let mut db = ProtoDatabase::new();
+        let message = ProtoName { id: db.register_identifier("Message".to_string()) };
+        let name = ProtoName { id: db.register_identifier("field".to_string()) };
+
+        let field = |label, file| ProtoField {
+            name,
+            label,
+            field_type: ProtoFieldKind::Scalar(ProtoType::Uint32),
+            field_number: 1,
+            oneof: None,
+            span: Span { file, ..Span::default() },
+        };
+
+        db.register_syntax(0, ProtoSyntax::Proto3, true);
+        db.register_syntax(1, ProtoSyntax::Proto2, true);
+        db.register_syntax(2, ProtoSyntax::Edition("2023".to_string()), true);
+        db.register_options(DefinitionRef::File(2), vec![ProtoOption { name: FIELD_PRESENCE_FEATURE.to_string(), value: "IMPLICIT".to_string() }]);
+
+        assert_eq!(db.field_presence(message, &field(ProtoLabel::None, 0)), Some(FieldPresence::Implicit));
+        assert_eq!(db.field_presence(message, &field(ProtoLabel::Optional, 0)), Some(FieldPresence::Explicit));
+        assert_eq!(db.field_presence(message, &field(ProtoLabel::Required, 1)), Some(FieldPresence::LegacyRequired));
+        assert_eq!(db.field_presence(message, &field(ProtoLabel::None, 2)), Some(FieldPresence::Implicit));
+        assert_eq!(db.syntax, Some(ProtoSyntax::Proto3));
+    }
+
+    #[test]
+    fn test_resolve_name_taken() {
+        let db_a = parse_test_proto("message A { uint32 level = 1; }");
+
+        // b has `level` readable already, for another field of the same message than the obfuscated one
+        let mut db_b = parse_test_proto("message A { uint32 level = 1; uint32 ABCDEFGHIJK = 2; }");
+        let level_a = ProtoName::lookup(&db_a, "level");
+        let level_b = ProtoName::lookup(&db_b, "level");
+        let obfuscated = ProtoName::lookup(&db_b, "ABCDEFGHIJK");
+
+        assert!(matches!(resolve_name(&db_a, &level_a, &mut db_b, &obfuscated), Err(ProtoResolutionError::NameTaken(name)) if name == "level"));
+        assert_eq!((level_b.name(&db_b), obfuscated.name(&db_b)), ("level".to_string(), "ABCDEFGHIJK".to_string()));
+        assert!(!db_b.is_resolved(&obfuscated));
+    }
 }
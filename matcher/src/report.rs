@@ -0,0 +1,509 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use itertools::Itertools;
+use serde_json::{json, Value};
+
+use crate::matcher::{MatchEvent, MatchRule, NameConflict};
+use crate::prototype::{ProtoDatabase, ProtoField, ProtoMessage, WeakProtoFieldKind};
+
+/// A field as it is declared on one side
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldRef {
+    pub name: String,
+    pub number: u32,
+    pub type_name: String,
+    /// Where the field is declared
+    pub location: String,
+}
+
+impl FieldRef {
+    fn new(proto_db: &ProtoDatabase, field: &ProtoField) -> Self {
+        Self {
+            name: field.name.name(proto_db),
+            number: field.field_number,
+            type_name: field.field_type.type_name(proto_db),
+            location: field.span.location(proto_db),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({ "name": self.name, "number": self.number, "type": self.type_name, "location": self.location })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ResolvedField {
+    pub name: String,
+    /// Name in b before it was resolved
+    pub original_name: String,
+    /// `None` when a has no field of that name in this message
+    pub field_a: Option<FieldRef>,
+    pub field_b: FieldRef,
+    /// `None` when the name was known already, or resolved through another message
+    pub event: Option<MatchEvent>,
+}
+
+impl ResolvedField {
+    pub fn rule(&self) -> Option<MatchRule> {
+        self.event.as_ref().map(|event| event.rule)
+    }
+}
+
+/// Unresolved fields of b with the fields of a they could be, all of the same type
+#[derive(Debug, Clone)]
+pub struct AmbiguousGroup {
+    pub fields_b: Vec<FieldRef>,
+    pub candidates_a: Vec<FieldRef>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MessageReport {
+    pub name: String,
+    pub original_name: String,
+    pub resolved: Vec<ResolvedField>,
+    pub ambiguous: Vec<AmbiguousGroup>,
+    /// Unresolved fields of b with no field of the same type left in a
+    pub new_in_b: Vec<FieldRef>,
+    /// Unresolved fields of a with no field of the same type left in b
+    pub removed_from_a: Vec<FieldRef>,
+}
+
+impl MessageReport {
+    fn new(proto_db_a: &ProtoDatabase, message_a: &ProtoMessage, proto_db_b: &ProtoDatabase, message_b: &ProtoMessage, events: &[MatchEvent]) -> Self {
+        let name = message_b.name.name(proto_db_b);
+
+        let (resolved_b, unresolved_b): (Vec<&ProtoField>, Vec<_>) = message_b.fields.iter()
+            .sorted_by_key(|field| field.field_number)
+            .partition(|field| proto_db_b.is_resolved(&field.name));
+
+        let resolved = resolved_b.iter()
+            .map(|field_b| {
+                let field_name = field_b.name.name(proto_db_b);
+                let field_a = message_a.fields.iter().find(|field| field.name.name(proto_db_a) == field_name);
+                let event = events.iter()
+                    .find(|event| event.message.as_deref() == Some(name.as_str()) && event.name_a == field_name)
+                    .cloned();
+
+                ResolvedField {
+                    original_name: proto_db_b.original_name(&field_b.name),
+                    name: field_name,
+                    field_a: field_a.map(|field| FieldRef::new(proto_db_a, field)),
+                    field_b: FieldRef::new(proto_db_b, field_b),
+                    event,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let unresolved_a = message_a.fields.iter()
+            .sorted_by_key(|field| field.field_number)
+            .filter(|field_a| !resolved.iter().any(|field| field.name == field_a.name.name(proto_db_a)))
+            .collect::<Vec<_>>();
+
+        // Grouped the way the matcher compares fields, in the order the groups first appear in b
+        let mut groups: Vec<(WeakProtoFieldKind, Vec<&ProtoField>)> = Vec::new();
+        for field in unresolved_b {
+            let kind = WeakProtoFieldKind::from(field.field_type);
+            match groups.iter_mut().find(|(other, _)| *other == kind) {
+                Some((_, fields)) => fields.push(field),
+                None => groups.push((kind, vec![field])),
+            }
+        }
+
+        let mut ambiguous = Vec::new();
+        let mut new_in_b = Vec::new();
+        for (kind, fields) in &groups {
+            let fields_b = fields.iter().map(|field| FieldRef::new(proto_db_b, field)).collect::<Vec<_>>();
+            let candidates_a = unresolved_a.iter()
+                .filter(|field| WeakProtoFieldKind::from(field.field_type) == *kind)
+                .map(|field| FieldRef::new(proto_db_a, field))
+                .collect::<Vec<_>>();
+
+            if candidates_a.is_empty() {
+                new_in_b.extend(fields_b);
+            } else {
+                ambiguous.push(AmbiguousGroup { fields_b, candidates_a });
+            }
+        }
+
+        let removed_from_a = unresolved_a.iter()
+            .filter(|field| !groups.iter().any(|(kind, _)| *kind == WeakProtoFieldKind::from(field.field_type)))
+            .map(|field| FieldRef::new(proto_db_a, field))
+            .collect();
+
+        Self {
+            original_name: proto_db_b.original_name(&message_b.name),
+            name,
+            resolved,
+            ambiguous,
+            new_in_b,
+            removed_from_a,
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        self.ambiguous.is_empty() && self.new_in_b.is_empty() && self.removed_from_a.is_empty()
+    }
+}
+
+/// Outcome of a matcher run, for tooling (JSON) and for review (Markdown)
+#[derive(Debug, Clone)]
+pub struct MatchReport {
+    pub messages: Vec<MessageReport>,
+    /// Types, services and methods the matcher resolved
+    pub definitions: Vec<MatchEvent>,
+    pub rule_counts: BTreeMap<MatchRule, usize>,
+    /// Names that were found but taken by another identifier, left for review
+    pub conflicts: Vec<NameConflict>,
+}
+
+impl MatchReport {
+    pub fn new(proto_db_a: &ProtoDatabase, proto_db_b: &ProtoDatabase, events: &[MatchEvent], conflicts: &[NameConflict]) -> Self {
+        let messages = proto_db_a.message_db.right_values()
+            .filter_map(|message_a| {
+                let message_b = proto_db_b.get_message(&message_a.name.name(proto_db_a))?;
+                Some(MessageReport::new(proto_db_a, message_a, proto_db_b, &message_b, events))
+            })
+            .sorted_by(|a, b| a.name.cmp(&b.name))
+            .collect();
+
+        let definitions = events.iter().filter(|event| event.message.is_none()).cloned().collect();
+        let rule_counts = events.iter().map(|event| event.rule).counts().into_iter().collect();
+
+        Self { messages, definitions, rule_counts, conflicts: conflicts.to_vec() }
+    }
+
+    fn resolved_count(&self) -> usize {
+        self.messages.iter().map(|message| message.resolved.len()).sum()
+    }
+
+    fn unresolved_count(&self) -> usize {
+        self.messages.iter()
+            .map(|message| message.ambiguous.iter().map(|group| group.fields_b.len()).sum::<usize>() + message.new_in_b.len())
+            .sum()
+    }
+
+    pub fn to_json(&self) -> String {
+        let field_refs = |fields: &[FieldRef]| fields.iter().map(FieldRef::to_json).collect::<Vec<_>>();
+
+        let messages = self.messages.iter()
+            .map(|message| json!({
+                "name": message.name,
+                "original_name": message.original_name,
+                "resolved": message.resolved.iter().map(|field| json!({
+                    "name": field.name,
+                    "original_name": field.original_name,
+                    "number_a": field.field_a.as_ref().map(|field_a| field_a.number),
+                    "number_b": field.field_b.number,
+                    "rule": field.rule().map(|rule| rule.key()),
+                })).collect::<Vec<_>>(),
+                "ambiguous": message.ambiguous.iter().map(|group| json!({
+                    "fields_b": field_refs(&group.fields_b),
+                    "candidates_a": field_refs(&group.candidates_a),
+                })).collect::<Vec<_>>(),
+                "new_in_b": field_refs(&message.new_in_b),
+                "removed_from_a": field_refs(&message.removed_from_a),
+            }))
+            .collect::<Vec<_>>();
+
+        let definitions = self.definitions.iter()
+            .map(|event| json!({ "name": event.name_a, "original_name": event.name_b, "rule": event.rule.key() }))
+            .collect::<Vec<_>>();
+
+        let rules = self.rule_counts.iter()
+            .map(|(rule, count)| (rule.key().to_string(), json!(count)))
+            .collect::<serde_json::Map<_, _>>();
+
+        let conflicts = self.conflicts.iter()
+            .map(|conflict| json!({ "name": conflict.name_a, "original_name": conflict.name_b, "message": conflict.message }))
+            .collect::<Vec<_>>();
+
+        serde_json::to_string_pretty(&json!({
+            "summary": {
+                "messages": self.messages.len(),
+                "resolved_fields": self.resolved_count(),
+                "unresolved_fields": self.unresolved_count(),
+            },
+            "rules": rules,
+            "definitions": definitions,
+            "conflicts": conflicts,
+            "messages": messages,
+        })).unwrap()
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut output = String::from("# Matching report\n\n");
+        writeln!(output, "{} fields resolved, {} unresolved in {} shared messages.\n", self.resolved_count(), self.unresolved_count(), self.messages.len()).unwrap();
+
+        output.push_str("| Rule | Description | Resolved |\n|---|---|---:|\n");
+        for (rule, count) in &self.rule_counts {
+            writeln!(output, "| `{}` | {} | {} |", rule.key(), rule.description(), count).unwrap();
+        }
+
+        if !self.definitions.is_empty() {
+            output.push_str("\n## Types, services and methods\n\n| Name | Was | Rule |\n|---|---|---|\n");
+            for event in &self.definitions {
+                writeln!(output, "| {} | {} | `{}` |", event.name_a, event.name_b, event.rule.key()).unwrap();
+            }
+        }
+
+        if !self.conflicts.is_empty() {
+            output.push_str("\n## Name conflicts\n\nNames that were found but are taken by another identifier in the same scope.\n\n| Name | Was | Message |\n|---|---|---|\n");
+            for conflict in &self.conflicts {
+                writeln!(output, "| {} | {} | {} |", conflict.name_a, conflict.name_b, conflict.message.as_deref().unwrap_or_default()).unwrap();
+            }
+        }
+
+        // Messages that need attention come first
+        for message in self.messages.iter().sorted_by_key(|message| message.is_complete()) {
+            output.push('\n');
+            if message.name == message.original_name {
+                writeln!(output, "## {}\n", message.name).unwrap();
+            } else {
+                writeln!(output, "## {} (was {})\n", message.name, message.original_name).unwrap();
+            }
+
+            if !message.resolved.is_empty() {
+                output.push_str("| Field | Was | Number a | Number b | Rule |\n|---|---|---:|---:|---|\n");
+                for field in &message.resolved {
+                    let number_a = field.field_a.as_ref().map(|field_a| field_a.number.to_string()).unwrap_or_default();
+                    let rule = field.rule().map(|rule| format!("`{}`", rule.key())).unwrap_or_default();
+                    writeln!(output, "| {} | {} | {} | {} | {} |", field.name, field.original_name, number_a, field.field_b.number, rule).unwrap();
+                }
+            }
+
+            for group in &message.ambiguous {
+                writeln!(output, "\nAmbiguous `{}`:", group.fields_b[0].type_name).unwrap();
+                writeln!(output, "- b: {}", format_fields(&group.fields_b)).unwrap();
+                writeln!(output, "- candidates in a: {}", format_fields(&group.candidates_a)).unwrap();
+            }
+
+            if !message.new_in_b.is_empty() {
+                writeln!(output, "\nNew in b: {}", format_fields(&message.new_in_b)).unwrap();
+            }
+
+            if !message.removed_from_a.is_empty() {
+                writeln!(output, "\nRemoved from a: {}", format_fields(&message.removed_from_a)).unwrap();
+            }
+        }
+
+        output
+    }
+
+    /// A single page with a's and b's fields side by side per message, searchable and without outside resources
+    pub fn to_html(&self) -> String {
+        let mut index = String::new();
+        let mut sections = String::new();
+
+        for (i, message) in self.messages.iter().sorted_by_key(|message| message.is_complete()).enumerate() {
+            let status = if message.is_complete() { "complete" } else { "review" };
+            let title = if message.name == message.original_name {
+                escape_html(&message.name)
+            } else {
+                format!("{} <small>was {}</small>", escape_html(&message.name), escape_html(&message.original_name))
+            };
+            let search = escape_html(&message_search_text(message));
+
+            writeln!(index, r##"<li class="{}" data-search="{}"><a href="#m{}">{}</a></li>"##, status, search, i, escape_html(&message.name)).unwrap();
+
+            writeln!(sections, r#"<section id="m{}" class="{}" data-search="{}">"#, i, status, search).unwrap();
+            writeln!(sections, "<h2>{}</h2>", title).unwrap();
+            sections.push_str("<table>\n<tr><th colspan=\"3\">a</th><th colspan=\"3\">b</th><th>Evidence</th></tr>\n");
+
+            for field in &message.resolved {
+                let evidence = match &field.event {
+                    Some(event) => event_evidence(event),
+                    None => "Name known already or resolved in another message".to_string(),
+                };
+                let was = Some(field.original_name.as_str()).filter(|original| *original != field.name);
+
+                writeln!(sections, r#"<tr class="resolved">{}{}<td>{}</td></tr>"#, field_cells(field.field_a.as_ref(), None), field_cells(Some(&field.field_b), was), evidence).unwrap();
+            }
+
+            for group in &message.ambiguous {
+                let evidence = format!("{} candidate(s) of the same type in a, needs a data match", group.candidates_a.len());
+                for row in 0..group.fields_b.len().max(group.candidates_a.len()) {
+                    let evidence = if row == 0 { evidence.as_str() } else { "" };
+                    writeln!(sections, r#"<tr class="ambiguous">{}{}<td>{}</td></tr>"#, field_cells(group.candidates_a.get(row), None), field_cells(group.fields_b.get(row), None), evidence).unwrap();
+                }
+            }
+
+            for field in &message.new_in_b {
+                writeln!(sections, r#"<tr class="new">{}{}<td>No field of this type left in a</td></tr>"#, field_cells(None, None), field_cells(Some(field), None)).unwrap();
+            }
+
+            for field in &message.removed_from_a {
+                writeln!(sections, r#"<tr class="removed">{}{}<td>No field of this type left in b</td></tr>"#, field_cells(Some(field), None), field_cells(None, None)).unwrap();
+            }
+
+            sections.push_str("</table>\n</section>\n");
+        }
+
+        let mut rules = String::new();
+        for (rule, count) in &self.rule_counts {
+            writeln!(rules, "<tr><td><code>{}</code></td><td>{}</td><td>{}</td></tr>", rule.key(), rule.description(), count).unwrap();
+        }
+
+        let mut summary = format!("{} fields resolved, {} unresolved in {} shared messages", self.resolved_count(), self.unresolved_count(), self.messages.len());
+        if !self.conflicts.is_empty() {
+            let names = self.conflicts.iter().map(|conflict| format!("{} (was {})", conflict.name_a, conflict.name_b)).join(", ");
+            write!(summary, ", names taken: {}", escape_html(&names)).unwrap();
+        }
+
+        HTML_TEMPLATE
+            .replace("{summary}", &summary)
+            .replace("{rules}", &rules)
+            .replace("{index}", &index)
+            .replace("{sections}", &sections)
+    }
+}
+
+/// Message and field names of both sides, lowercased for the search box
+fn message_search_text(message: &MessageReport) -> String {
+    let mut names = vec![message.name.as_str(), message.original_name.as_str()];
+    names.extend(message.resolved.iter().flat_map(|field| [field.name.as_str(), field.original_name.as_str()]));
+    names.extend(message.ambiguous.iter().flat_map(|group| group.fields_b.iter().chain(&group.candidates_a)).map(|field| field.name.as_str()));
+    names.extend(message.new_in_b.iter().chain(&message.removed_from_a).map(|field| field.name.as_str()));
+
+    names.into_iter().unique().join(" ").to_lowercase()
+}
+
+/// The rule with the names it picked from and the names it needed resolved, like `explain` prints them
+fn event_evidence(event: &MatchEvent) -> String {
+    let mut evidence = format!("{} (<code>{}</code>), round {}", event.rule.description(), event.rule.key(), event.round);
+    for (label, names) in [("considered in a", &event.considered_a), ("considered in b", &event.considered_b), ("depends on", &event.depends_on)] {
+        if !names.is_empty() {
+            write!(evidence, "<br><small>{}: {}</small>", label, escape_html(&names.join(", "))).unwrap();
+        }
+    }
+    evidence
+}
+
+/// Number, type and name cells of one side of a row, empty when the field has no counterpart
+fn field_cells(field: Option<&FieldRef>, was: Option<&str>) -> String {
+    match field {
+        Some(field) => {
+            let was = was.map(|was| format!(" <small>was {}</small>", escape_html(was))).unwrap_or_default();
+            format!("<td>{}</td><td><code>{}</code></td><td>{}{}</td>", field.number, escape_html(&field.type_name), escape_html(&field.name), was)
+        }
+        None => "<td></td><td></td><td></td>".to_string(),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+const HTML_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Matching report</title>
+<style>
+body { margin: 0; display: flex; font: 14px sans-serif; }
+nav { width: 280px; height: 100vh; position: sticky; top: 0; overflow-y: auto; padding: 8px; border-right: 1px solid #ccc; box-sizing: border-box; }
+nav input { width: 100%; box-sizing: border-box; margin-bottom: 8px; }
+nav ul { list-style: none; padding: 0; margin: 0; }
+nav li.review a { font-weight: bold; }
+main { flex: 1; padding: 0 16px; }
+table { border-collapse: collapse; margin-bottom: 8px; }
+td, th { border: 1px solid #ddd; padding: 2px 6px; text-align: left; }
+small { color: #666; }
+tr.resolved { background: #e6f4e6; }
+tr.ambiguous { background: #fff4d6; }
+tr.new { background: #e0ecff; }
+tr.removed { background: #fbe2e2; }
+.hidden { display: none; }
+</style>
+</head>
+<body>
+<nav>
+<input id="search" type="search" placeholder="Search messages and fields">
+<ul>
+{index}</ul>
+</nav>
+<main>
+<h1>Matching report</h1>
+<p>{summary}</p>
+<p>Rows: <span style="background:#e6f4e6">resolved</span> <span style="background:#fff4d6">ambiguous</span> <span style="background:#e0ecff">new in b</span> <span style="background:#fbe2e2">removed from a</span></p>
+<table>
+<tr><th>Rule</th><th>Description</th><th>Resolved</th></tr>
+{rules}</table>
+{sections}</main>
+<script>
+document.getElementById("search").addEventListener("input", event => {
+    const query = event.target.value.trim().toLowerCase();
+    for (const element of document.querySelectorAll("[data-search]")) {
+        element.classList.toggle("hidden", query !== "" && !element.dataset.search.includes(query));
+    }
+});
+</script>
+</body>
+</html>
+"#;
+
+fn format_fields(fields: &[FieldRef]) -> String {
+    fields.iter().map(|field| format!("`{} {} = {}`", field.type_name, field.name, field.number)).join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::Matcher;
+    use crate::util::parse_test_proto;
+
+    #[test]
+    fn test_report() {
+        let proto_db_a = parse_test_proto("
+            message Avatar {
+                uint32 level = 1;
+                int64 exp = 2;
+                int64 rank = 3;
+                bool skin = 4;
+                int32 score = 5;
+            }
+        ");
+
+        // b renumbered every field, added a string, dropped the bool and widened the score
+        let proto_db_b = parse_test_proto("
+            message Avatar {
+                uint32 ABCDEFGHIJK = 7;
+                int64 BCDEFGHIJKL = 8;
+                int64 CDEFGHIJKLM = 9;
+                string DEFGHIJKLMN = 10;
+                int64 score = 11;
+            }
+        ");
+
+        let mut matcher = Matcher::new(proto_db_a, proto_db_b);
+        matcher.run();
+        let report = matcher.report();
+
+        assert_eq!(report.rule_counts.get(&MatchRule::UniqueWeakType), Some(&1));
+
+        let [avatar] = &report.messages[..] else {
+            panic!("expected one shared message");
+        };
+        assert_eq!(avatar.resolved.len(), 2);
+        assert_eq!((avatar.resolved[0].name.as_str(), avatar.resolved[0].original_name.as_str()), ("level", "ABCDEFGHIJK"));
+        assert_eq!((avatar.resolved[0].field_a.as_ref().map(|field| field.number), avatar.resolved[0].field_b.number), (Some(1), 7));
+
+        assert_eq!(avatar.ambiguous.len(), 1);
+        assert_eq!(avatar.ambiguous[0].fields_b.iter().map(|field| field.number).collect::<Vec<_>>(), [8, 9]);
+        assert_eq!(avatar.ambiguous[0].candidates_a.iter().map(|field| field.name.as_str()).collect::<Vec<_>>(), ["exp", "rank"]);
+
+        assert_eq!(avatar.new_in_b.iter().map(|field| field.number).collect::<Vec<_>>(), [10]);
+        assert_eq!(avatar.removed_from_a.iter().map(|field| field.name.as_str()).collect::<Vec<_>>(), ["skin"]);
+
+        let json: Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["summary"]["resolved_fields"], 2);
+        assert_eq!(json["rules"]["unique_weak_type"], 1);
+        assert!(report.to_markdown().contains("Removed from a: `bool skin = 4`"));
+
+        let html = report.to_html();
+        assert!(html.contains(r#"<tr class="resolved"><td>1</td><td><code>uint32</code></td><td>level</td><td>7</td><td><code>uint32</code></td><td>level <small>was ABCDEFGHIJK</small></td>"#));
+        assert!(html.contains("<small>considered in b: ABCDEFGHIJK</small><br><small>depends on: Avatar</small>"));
+        assert!(html.contains(r#"<tr class="resolved"><td>5</td><td><code>int32</code></td><td>score</td><td>11</td><td><code>int64</code></td><td>score</td>"#));
+        assert!(html.contains(r#"<tr class="removed"><td>4</td><td><code>bool</code></td><td>skin</td><td></td>"#));
+    }
+}
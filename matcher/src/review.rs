@@ -0,0 +1,296 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, List, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::matcher::Matcher;
+use crate::pins::{Pin, PinFile};
+use crate::report::{AmbiguousGroup, FieldRef};
+
+const HELP: &str = "↑/↓ select  ←/→ switch side  enter pin selected  a accept suggestion  n/p next/previous group  q quit";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    B,
+    A,
+}
+
+/// Steps through the ambiguous groups, every decision is pinned and the matcher rerun with it
+pub struct Review<'a> {
+    matcher: &'a mut Matcher,
+    pin_file: &'a mut PinFile,
+    /// Ambiguous groups left, with the message they are in
+    groups: Vec<(String, AmbiguousGroup)>,
+    group: usize,
+    selected_b: usize,
+    selected_a: usize,
+    focus: Side,
+    status: String,
+    /// Pins were added since the pin file was last written
+    changed: bool,
+}
+
+impl<'a> Review<'a> {
+    pub fn new(matcher: &'a mut Matcher, pin_file: &'a mut PinFile) -> Self {
+        let mut review = Self {
+            matcher,
+            pin_file,
+            groups: Vec::new(),
+            group: 0,
+            selected_b: 0,
+            selected_a: 0,
+            focus: Side::B,
+            status: String::new(),
+            changed: false,
+        };
+        review.refresh();
+        review.status = format!("{} ambiguous group(s) to review", review.groups.len());
+        review
+    }
+
+    fn refresh(&mut self) {
+        self.groups = self.matcher.report().messages.into_iter()
+            .flat_map(|message| {
+                let name = message.name;
+                message.ambiguous.into_iter().map(move |group| (name.clone(), group))
+            })
+            .collect();
+
+        self.group = self.group.min(self.groups.len().saturating_sub(1));
+        self.selected_b = 0;
+        self.selected_a = 0;
+        self.focus = Side::B;
+    }
+
+    /// Handles a key press, returns false once the user quits
+    pub fn handle_key(&mut self, code: KeyCode) -> bool {
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(false),
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(true),
+            KeyCode::Left | KeyCode::Right | KeyCode::Tab | KeyCode::Char('h') | KeyCode::Char('l') => {
+                self.focus = if self.focus == Side::B { Side::A } else { Side::B };
+            }
+            KeyCode::Char('n') | KeyCode::PageDown => self.select_group(self.group + 1),
+            KeyCode::Char('p') | KeyCode::PageUp => self.select_group(self.group.saturating_sub(1)),
+            KeyCode::Enter => {
+                if let Some((message, group)) = self.groups.get(self.group) {
+                    let pin = Pin {
+                        message: message.clone(),
+                        field_b: group.fields_b[self.selected_b].name.clone(),
+                        field_a: group.candidates_a[self.selected_a].name.clone(),
+                    };
+                    self.pin(vec![pin]);
+                }
+            }
+            KeyCode::Char('a') => match self.suggestion() {
+                Some(pins) => self.pin(pins),
+                None => self.status = "No suggestion, the group has more fields on one side".to_string(),
+            },
+            _ => {}
+        }
+        true
+    }
+
+    fn move_selection(&mut self, down: bool) {
+        let Some((_, group)) = self.groups.get(self.group) else {
+            return;
+        };
+
+        let (selected, len) = match self.focus {
+            Side::B => (&mut self.selected_b, group.fields_b.len()),
+            Side::A => (&mut self.selected_a, group.candidates_a.len()),
+        };
+        *selected = if down { (*selected + 1).min(len - 1) } else { selected.saturating_sub(1) };
+    }
+
+    fn select_group(&mut self, group: usize) {
+        if group < self.groups.len() {
+            self.group = group;
+            self.selected_b = 0;
+            self.selected_a = 0;
+        }
+    }
+
+    /// Fields paired up in field number order, when both sides have the same number of them
+    fn suggestion(&self) -> Option<Vec<Pin>> {
+        let (message, group) = self.groups.get(self.group)?;
+        if group.fields_b.len() != group.candidates_a.len() {
+            return None;
+        }
+
+        Some(group.fields_b.iter().zip(&group.candidates_a)
+            .map(|(field_b, field_a)| Pin { message: message.clone(), field_b: field_b.name.clone(), field_a: field_a.name.clone() })
+            .collect())
+    }
+
+    /// Applies the pins and reruns the matcher, only pins the matcher accepted go into the pin file
+    fn pin(&mut self, pins: Vec<Pin>) {
+        let mut pinned = Vec::new();
+        let mut errors = Vec::new();
+        for pin in pins {
+            match self.matcher.apply_pin(&pin) {
+                Ok(()) => {
+                    pinned.push(pin.to_string());
+                    self.pin_file.add(pin);
+                }
+                Err(e) => errors.push(format!("cannot pin {}: {}", pin, e)),
+            }
+        }
+
+        if !pinned.is_empty() {
+            // A pin can unlock more static matches, in this message and others
+            self.matcher.run();
+            self.changed = true;
+            self.refresh();
+        }
+
+        self.status = match (pinned.is_empty(), errors.is_empty()) {
+            (false, true) => format!("Pinned {}, {} ambiguous group(s) left", pinned.join(", "), self.groups.len()),
+            (true, _) => capitalize(&errors.join(", ")),
+            (false, false) => format!("Pinned {}, {}", pinned.join(", "), errors.join(", ")),
+        };
+    }
+
+    fn render(&self, frame: &mut Frame) {
+        let [header, body, footer] = Layout::vertical([Constraint::Length(1), Constraint::Min(3), Constraint::Length(2)]).areas(frame.area());
+        frame.render_widget(Paragraph::new(format!("{}\n{}", self.status, HELP)), footer);
+
+        let Some((message, group)) = self.groups.get(self.group) else {
+            frame.render_widget(Paragraph::new("No ambiguous groups left"), body);
+            return;
+        };
+
+        let title = format!("{} ({}/{}), {} field(s) of type {}", message, self.group + 1, self.groups.len(), group.fields_b.len(), group.fields_b[0].type_name);
+        frame.render_widget(Paragraph::new(title).style(Style::new().add_modifier(Modifier::BOLD)), header);
+
+        let [left, right] = Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(body);
+        let suggestion = self.suggestion();
+
+        let items_b = group.fields_b.iter().enumerate()
+            .map(|(i, field)| match &suggestion {
+                Some(pins) => format!("{}  -> {}?", field_line(field), pins[i].field_a),
+                None => field_line(field),
+            });
+        self.render_list(frame, left, "b", items_b, self.selected_b, Side::B);
+
+        let items_a = group.candidates_a.iter().map(field_line);
+        self.render_list(frame, right, "a candidates", items_a, self.selected_a, Side::A);
+    }
+
+    fn render_list(&self, frame: &mut Frame, area: Rect, title: &str, items: impl Iterator<Item = String>, selected: usize, side: Side) {
+        let mut block = Block::bordered().title(title.to_string());
+        if self.focus == side {
+            block = block.border_style(Style::new().add_modifier(Modifier::BOLD));
+        }
+
+        let list = List::new(items).block(block).highlight_symbol("> ").highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, area, &mut ListState::default().with_selected(Some(selected)));
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    chars.next().map(|first| first.to_uppercase().chain(chars).collect()).unwrap_or_default()
+}
+
+fn field_line(field: &FieldRef) -> String {
+    format!("{:>4}  {} {}  ({})", field.number, field.type_name, field.name, field.location)
+}
+
+/// Runs the review in the terminal, pins are written to `pin_path` after every decision
+pub fn review(matcher: &mut Matcher, pin_file: &mut PinFile, pin_path: &Path) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, &mut Review::new(matcher, pin_file), pin_path);
+    ratatui::restore();
+    result
+}
+
+fn run(terminal: &mut DefaultTerminal, review: &mut Review, pin_path: &Path) -> io::Result<()> {
+    loop {
+        terminal.draw(|frame| review.render(frame))?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        if !review.handle_key(key.code) {
+            return Ok(());
+        }
+
+        if review.changed {
+            fs::write(pin_path, review.pin_file.render())?;
+            review.changed = false;
+
+            // The matcher logs to stderr while it reruns, which ends up on the same terminal, draw everything again
+            terminal.clear()?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::parse_test_proto;
+
+    #[test]
+    fn test_review_pins() {
+        let proto_db_a = parse_test_proto("
+            message Avatar {
+                int64 exp = 1;
+                int64 rank = 2;
+            }
+        ");
+        let proto_db_b = parse_test_proto("
+            message Avatar {
+                int64 BCDEFGHIJKL = 5;
+                int64 CDEFGHIJKLM = 6;
+            }
+        ");
+
+        let mut matcher = Matcher::new(proto_db_a, proto_db_b);
+        matcher.run();
+
+        let mut pin_file = PinFile::default();
+        let mut review = Review::new(&mut matcher, &mut pin_file);
+        assert_eq!(review.groups.len(), 1);
+
+        // Second field of b is the first of a, the remaining pair is then unique
+        review.handle_key(KeyCode::Down);
+        review.handle_key(KeyCode::Right);
+        review.handle_key(KeyCode::Enter);
+        assert!(review.groups.is_empty());
+        assert!(!review.handle_key(KeyCode::Char('q')));
+
+        assert_eq!(pin_file.render().lines().nth(1), Some("Avatar.CDEFGHIJKLM -> exp"));
+        let report = matcher.report();
+        let resolved = report.messages[0].resolved.iter().map(|field| (field.original_name.as_str(), field.name.as_str(), field.rule().map(|rule| rule.key()))).collect::<Vec<_>>();
+        assert_eq!(resolved, [("BCDEFGHIJKL", "rank", Some("unique_weak_type")), ("CDEFGHIJKLM", "exp", Some("manual"))]);
+    }
+
+    #[test]
+    fn test_review_rejected_pin() {
+        let proto_db_a = parse_test_proto("message Avatar { int64 exp = 1; int64 rank = 2; }");
+        let proto_db_b = parse_test_proto("message Avatar { int64 BCDEFGHIJKL = 5; int64 CDEFGHIJKLM = 6; }");
+
+        let mut matcher = Matcher::new(proto_db_a, proto_db_b);
+        matcher.run();
+
+        let mut pin_file = PinFile::default();
+        let mut review = Review::new(&mut matcher, &mut pin_file);
+        review.pin(vec![Pin { message: "Avatar".to_string(), field_b: "DEFGHIJKLMN".to_string(), field_a: "exp".to_string() }]);
+
+        assert_eq!(review.status, "Cannot pin Avatar.DEFGHIJKLMN -> exp: no field DEFGHIJKLMN in the message");
+        assert!(!review.changed);
+        assert_eq!(review.groups.len(), 1);
+        assert!(pin_file.pins.is_empty());
+    }
+}
@@ -0,0 +1,92 @@
+use crate::prototype::ProtoDatabase;
+
+/// Replaces every identifier occurrence in one of the database's source files with its current (resolved) name,
+/// and adds the comments the matcher carried over, leaving everything else untouched
+pub fn rewrite_source(source: &str, proto_db: &ProtoDatabase, file: usize) -> String {
+    let mut replacements = proto_db.identifier_occurrences.iter()
+        .filter_map(|(id, spans)| {
+            let original = proto_db.identifier_db_original.get_by_right(id)?;
+            let current = proto_db.identifier_db.name(*id)?;
+            (original != current).then_some((spans, current))
+        })
+        .flat_map(|(spans, current)| {
+            spans.iter()
+                .filter(|span| span.file == file)
+                .map(move |span| (span.start_byte, span.end_byte, current.clone()))
+        })
+        .collect::<Vec<_>>();
+
+    // Insertions are empty replacements, leading comments go above the line at its indentation and trailing ones after the statement
+    for target in &proto_db.carried_comments {
+        let (Some(span), Some(comments)) = (proto_db.span_of(*target), proto_db.comments.get(target)) else {
+            continue;
+        };
+        if span.file != file {
+            continue;
+        }
+
+        let line_start = source[..span.start_byte].rfind('\n').map_or(0, |newline| newline + 1);
+        let indent = &source[line_start..span.start_byte];
+        let indent = if indent.trim().is_empty() { indent } else { "" };
+        let leading = comments.leading.iter().map(|comment| format!("{}// {}", indent, comment).trim_end().to_string() + "\n").collect::<String>();
+        replacements.push((line_start, line_start, leading));
+
+        if let Some(trailing) = &comments.trailing {
+            replacements.push((span.end_byte, span.end_byte, format!(" // {}", trailing)));
+        }
+    }
+
+    // An insertion comes before a replacement starting at the same byte
+    replacements.sort_by_key(|&(start, end, _)| (start, end));
+
+    let mut output = String::with_capacity(source.len());
+    let mut position = 0;
+    for (start, end, text) in replacements {
+        output.push_str(&source[position..start]);
+        output.push_str(&text);
+        position = end;
+    }
+    output.push_str(&source[position..]);
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prototype::Span;
+
+    #[test]
+    fn test_rewrite_only_touches_occurrences() {
+        let source = "message Foo {\n    JNLOABDHEIH JNLOABDHEIHX = 1;\n}\n";
+
+        let mut proto_db = ProtoDatabase::new();
+        let file = proto_db.register_source_file("test.proto");
+        let id = proto_db.register_identifier("JNLOABDHEIH".to_string());
+        let start_byte = source.find("JNLOABDHEIH").unwrap();
+        proto_db.register_occurrence(id, Span { file, start_byte, end_byte: start_byte + 11, line: 2, column: 5 });
+        proto_db.identifier_db.insert("AvatarInfo".to_string(), id);
+
+        assert_eq!(rewrite_source(source, &proto_db, file), "message Foo {\n    AvatarInfo JNLOABDHEIHX = 1;\n}\n");
+    }
+
+    #[test]
+    fn test_rewrite_carried_comments() {
+        let proto_db_a = crate::util::parse_test_proto("
+            // An avatar
+            message Avatar {
+                // Experience level
+                uint32 level = 1; // capped at 80
+            }
+        ");
+
+        let source = "message Avatar {\n    uint32 ABCDEFGHIJK = 1;\n}\n";
+        let mut proto_db_b = ProtoDatabase::new();
+        crate::parser::parse_proto_into(&mut proto_db_b, source, "b.proto").unwrap();
+
+        let mut matcher = crate::matcher::Matcher::new(proto_db_a, proto_db_b);
+        matcher.run();
+
+        assert_eq!(rewrite_source(source, matcher.db_b(), 0), "// An avatar\nmessage Avatar {\n    // Experience level\n    uint32 level = 1; // capped at 80\n}\n");
+    }
+}
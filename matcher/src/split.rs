@@ -0,0 +1,404 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::hash::Hash;
+
+use clap::ValueEnum;
+use itertools::Itertools;
+
+use crate::emit::ProtoEmitter;
+use crate::prototype::{ProtoDatabase, ProtoField, ProtoName, ProtoType};
+
+/// Type references between top-level definitions and services, nested types count as part of their outermost parent
+pub struct DependencyGraph<T = ProtoName> {
+    pub edges: BTreeMap<T, BTreeSet<T>>,
+}
+
+impl DependencyGraph {
+    pub fn new(proto_db: &ProtoDatabase) -> Self {
+        let mut edges: BTreeMap<ProtoName, BTreeSet<ProtoName>> = BTreeMap::new();
+
+        for proto_enum in proto_db.enum_db.right_values() {
+            edges.entry(proto_db.top_level_of(&proto_enum.name)).or_default();
+        }
+
+        for message in proto_db.message_db.right_values() {
+            let from = proto_db.top_level_of(&message.name);
+            let dependencies = edges.entry(from).or_default();
+            dependencies.extend(field_dependencies(proto_db, &message.fields).into_iter().filter(|&to| to != from));
+        }
+
+        for service in proto_db.service_db.right_values() {
+            let dependencies = edges.entry(service.name).or_default();
+            dependencies.extend(service.rpcs.iter()
+                .flat_map(|rpc| [rpc.request, rpc.response])
+                .filter_map(|typ| match typ {
+                    ProtoType::Type(name) if proto_db.is_defined(&name) => Some(proto_db.top_level_of(&name)),
+                    _ => None,
+                }));
+        }
+
+        // Extensions nested in a message belong to it, top-level ones are placed by `split`
+        for extension in &proto_db.extensions {
+            if let Some(parent) = extension.parent {
+                let from = proto_db.top_level_of(&parent);
+                let dependencies = edges.entry(from).or_default();
+                dependencies.extend(field_dependencies(proto_db, &extension.fields).into_iter().filter(|&to| to != from));
+            }
+        }
+
+        Self { edges }
+    }
+}
+
+/// Top-level definitions the fields refer to, types that are only referenced can't be imported from anywhere
+fn field_dependencies(proto_db: &ProtoDatabase, fields: &[ProtoField]) -> BTreeSet<ProtoName> {
+    fields.iter()
+        .flat_map(|field| field.field_type.type_refs())
+        .filter(|to| proto_db.is_defined(to))
+        .map(|to| proto_db.top_level_of(&to))
+        .collect()
+}
+
+impl<T: Copy + Ord + Hash> DependencyGraph<T> {
+    /// Tarjan's algorithm, every node ends up in exactly one component (most of them on their own)
+    pub fn strongly_connected_components(&self) -> Vec<Vec<T>> {
+        let mut tarjan = Tarjan {
+            graph: self,
+            index: 0,
+            indices: HashMap::new(),
+            lowlinks: HashMap::new(),
+            stack: Vec::new(),
+            on_stack: HashSet::new(),
+            components: Vec::new(),
+        };
+
+        for node in self.edges.keys() {
+            if !tarjan.indices.contains_key(node) {
+                tarjan.visit(*node);
+            }
+        }
+
+        tarjan.components
+    }
+}
+
+struct Tarjan<'a, T> {
+    graph: &'a DependencyGraph<T>,
+    index: usize,
+    indices: HashMap<T, usize>,
+    lowlinks: HashMap<T, usize>,
+    stack: Vec<T>,
+    on_stack: HashSet<T>,
+    components: Vec<Vec<T>>,
+}
+
+impl<T: Copy + Ord + Hash> Tarjan<'_, T> {
+    fn visit(&mut self, node: T) {
+        self.indices.insert(node, self.index);
+        self.lowlinks.insert(node, self.index);
+        self.index += 1;
+        self.stack.push(node);
+        self.on_stack.insert(node);
+
+        for &next in &self.graph.edges[&node] {
+            if !self.indices.contains_key(&next) {
+                self.visit(next);
+                let lowlink = self.lowlinks[&node].min(self.lowlinks[&next]);
+                self.lowlinks.insert(node, lowlink);
+            } else if self.on_stack.contains(&next) {
+                let lowlink = self.lowlinks[&node].min(self.indices[&next]);
+                self.lowlinks.insert(node, lowlink);
+            }
+        }
+
+        if self.lowlinks[&node] == self.indices[&node] {
+            let mut component = Vec::new();
+            loop {
+                let member = self.stack.pop().unwrap();
+                self.on_stack.remove(&member);
+                component.push(member);
+
+                if member == node {
+                    break;
+                }
+            }
+
+            component.sort();
+            self.components.push(component);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GroupStrategy {
+    /// One file per type, types in a cycle share a file named after all of them
+    Type,
+    /// One file per strongly connected component, cycles are named after their first member and a hash of the rest
+    Scc,
+    /// One file per feature area, by name prefix
+    Prefix,
+}
+
+pub struct SplitOptions {
+    pub strategy: GroupStrategy,
+    /// Feature area prefixes for `GroupStrategy::Prefix`, names without a match are grouped by their first word
+    pub prefixes: Vec<String>,
+    /// Package declared in every file, files are placed in the matching directory
+    pub package: Option<String>,
+}
+
+pub struct SplitFile {
+    /// Path relative to the output directory, without extension
+    pub name: String,
+    pub package: Option<String>,
+    pub definitions: Vec<ProtoName>,
+    /// Top-level `extend` blocks, as indices into `ProtoDatabase::extensions`
+    pub extensions: Vec<usize>,
+    pub imports: BTreeSet<String>,
+}
+
+impl SplitFile {
+    pub fn path(&self) -> String {
+        format!("{}.proto", self.name)
+    }
+
+    pub fn render(&self, proto_db: &ProtoDatabase) -> String {
+        let mut emitter = ProtoEmitter::new(proto_db);
+        emitter.emit_header();
+        if let Some(package) = &self.package {
+            emitter.emit_package(package);
+        }
+        emitter.emit_imports(self.imports.iter().map(String::as_str));
+        emitter.emit_options(&proto_db.file_options());
+        emitter.emit_definitions(&self.definitions);
+        emitter.emit_extensions(&self.extensions.iter().map(|&i| &proto_db.extensions[i]).collect::<Vec<_>>());
+        emitter.finish()
+    }
+}
+
+/// Splits a database into files according to `options.strategy`, cyclic dependencies always share a file
+pub fn split(proto_db: &ProtoDatabase, options: &SplitOptions) -> Vec<SplitFile> {
+    let graph = DependencyGraph::new(proto_db);
+
+    let mut groups = graph.strongly_connected_components().into_iter()
+        .map(|mut definitions| {
+            definitions.sort_by_key(|name| name.name(proto_db));
+            definitions
+        })
+        .collect::<Vec<_>>();
+
+    if options.strategy == GroupStrategy::Prefix {
+        groups = group_by_prefix(proto_db, &graph, groups, &options.prefixes);
+    }
+
+    let directory = options.package.as_ref()
+        .map(|package| format!("{}/", package.replace('.', "/")))
+        .unwrap_or_default();
+
+    let mut files = groups.into_iter()
+        .map(|definitions| {
+            let names = definitions.iter().map(|name| name.name(proto_db)).collect::<Vec<_>>();
+
+            let name = match options.strategy {
+                GroupStrategy::Type => names.join("_"),
+                GroupStrategy::Scc => representative_name(&names[0], &names),
+                GroupStrategy::Prefix => {
+                    let keys = names.iter().map(|name| prefix_key(name, &options.prefixes)).sorted().dedup().collect::<Vec<_>>();
+                    representative_name(&keys[0], &keys)
+                }
+            };
+
+            SplitFile {
+                name: format!("{}{}", directory, name),
+                package: options.package.clone(),
+                definitions,
+                extensions: Vec::new(),
+                imports: BTreeSet::new(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let file_of = files.iter().enumerate()
+        .flat_map(|(i, file)| file.definitions.iter().map(move |name| (*name, i)))
+        .collect::<HashMap<_, _>>();
+
+    // A top-level extension goes with its extendee, or with the first type it uses if the extendee is imported from elsewhere
+    let mut extension_dependencies: HashMap<usize, BTreeSet<ProtoName>> = HashMap::new();
+    for (index, extension) in proto_db.extensions.iter().enumerate().filter(|(_, extension)| extension.parent.is_none()) {
+        let dependencies = field_dependencies(proto_db, &extension.fields);
+        let home = Some(extension.extendee).filter(|extendee| proto_db.is_defined(extendee))
+            .map(|extendee| proto_db.top_level_of(&extendee))
+            .or_else(|| dependencies.first().copied())
+            .map_or(0, |name| file_of[&name]);
+
+        if let Some(file) = files.get_mut(home) {
+            file.extensions.push(index);
+            extension_dependencies.entry(home).or_default().extend(dependencies);
+        }
+    }
+
+    for i in 0..files.len() {
+        let imports = files[i].definitions.iter()
+            .flat_map(|name| &graph.edges[name])
+            .chain(extension_dependencies.get(&i).into_iter().flatten())
+            .map(|dependency| file_of[dependency])
+            .filter(|&file| file != i)
+            .map(|file| files[file].path())
+            .collect();
+
+        files[i].imports = imports;
+    }
+
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    files
+}
+
+/// Merges components into one group per prefix, groups that end up importing each other are merged again
+fn group_by_prefix(proto_db: &ProtoDatabase, graph: &DependencyGraph, components: Vec<Vec<ProtoName>>, prefixes: &[String]) -> Vec<Vec<ProtoName>> {
+    let mut by_prefix: BTreeMap<String, Vec<ProtoName>> = BTreeMap::new();
+    for component in components {
+        // Components are sorted, so the first member decides for the whole cycle
+        let key = prefix_key(&component[0].name(proto_db), prefixes);
+        by_prefix.entry(key).or_default().extend(component);
+    }
+
+    let groups = by_prefix.into_values().collect::<Vec<_>>();
+    let group_of = groups.iter().enumerate()
+        .flat_map(|(i, group)| group.iter().map(move |name| (*name, i)))
+        .collect::<HashMap<_, _>>();
+
+    let mut group_graph = DependencyGraph { edges: BTreeMap::new() };
+    for (i, group) in groups.iter().enumerate() {
+        let dependencies = group.iter()
+            .flat_map(|name| &graph.edges[name])
+            .map(|dependency| group_of[dependency])
+            .filter(|&group| group != i)
+            .collect();
+
+        group_graph.edges.insert(i, dependencies);
+    }
+
+    group_graph.strongly_connected_components().into_iter()
+        .map(|members| {
+            let mut definitions = members.into_iter().flat_map(|i| groups[i].clone()).collect::<Vec<_>>();
+            definitions.sort_by_key(|name| name.name(proto_db));
+            definitions
+        })
+        .collect()
+}
+
+/// Longest matching prefix, or the first CamelCase word of the name
+fn prefix_key(name: &str, prefixes: &[String]) -> String {
+    if let Some(prefix) = prefixes.iter().filter(|prefix| name.starts_with(prefix.as_str())).max_by_key(|prefix| prefix.len()) {
+        return prefix.clone();
+    }
+
+    let word_end = name.char_indices()
+        .skip(1)
+        .find(|&(i, c)| c.is_ascii_uppercase() && name[..i].ends_with(|p: char| p.is_ascii_lowercase() || p.is_ascii_digit()))
+        .map_or(name.len(), |(i, _)| i);
+
+    name[..word_end].to_string()
+}
+
+/// `first` alone for a single member, otherwise `first` and a stable hash of all members
+fn representative_name(first: &str, members: &[String]) -> String {
+    if members.len() == 1 {
+        return first.to_string();
+    }
+
+    // FNV-1a, stable across runs and toolchains unlike the std hasher
+    let mut hash: u32 = 0x811c9dc5;
+    for byte in members.join("\0").bytes() {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+
+    format!("{}_{:08x}", first, hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::parse_test_proto;
+
+    #[test]
+    fn test_split_cycles() {
+        let proto_db = parse_test_proto("
+            message Alpha {
+                Beta beta = 1;
+            }
+
+            message Beta {
+                Alpha alpha = 1;
+                Undefined undefined = 2;
+            }
+
+            message Gamma {
+                Alpha alpha = 1;
+            }
+        ");
+
+        let options = SplitOptions {
+            strategy: GroupStrategy::Type,
+            prefixes: Vec::new(),
+            package: None,
+        };
+
+        let files = split(&proto_db, &options);
+        let summary = files.iter()
+            .map(|file| (file.name.as_str(), file.imports.iter().cloned().collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(summary, vec![
+            ("Alpha_Beta", vec![]),
+            ("Gamma", vec!["Alpha_Beta.proto".to_string()]),
+        ]);
+    }
+
+    #[test]
+    fn test_split_nested_reference() {
+        let proto_db = parse_test_proto("
+            message AvatarInfo {
+                message Skill {
+                    uint32 level = 1;
+                }
+
+                repeated Skill skills = 1;
+            }
+
+            message Extra {
+                repeated AvatarInfo.Skill skills = 1;
+                map<uint32, AvatarInfo.Skill> skill_map = 2;
+            }
+        ");
+
+        let options = SplitOptions {
+            strategy: GroupStrategy::Type,
+            prefixes: Vec::new(),
+            package: None,
+        };
+
+        let files = split(&proto_db, &options);
+        let extra = files.iter().find(|file| file.name == "Extra").unwrap().render(&proto_db);
+        assert!(extra.contains("import \"AvatarInfo.proto\";"));
+        assert!(extra.contains("repeated AvatarInfo.Skill skills = 1;"));
+        assert!(extra.contains("map<uint32, AvatarInfo.Skill> skill_map = 2;"));
+
+        let avatar_info = files.iter().find(|file| file.name == "AvatarInfo").unwrap().render(&proto_db);
+        assert!(avatar_info.contains("repeated Skill skills = 1;"));
+
+        // The rendered files parse back to the same schema
+        let mut reparsed = ProtoDatabase::new();
+        for source in [&avatar_info, &extra] {
+            let parsed = crate::parser::parse_proto_into(&mut reparsed, source, "split.proto").unwrap();
+            assert!(parsed.diagnostics.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_prefix_key() {
+        assert_eq!(prefix_key("AvatarInfo", &[]), "Avatar");
+        assert_eq!(prefix_key("GetAvatarDataCsReq", &["GetAvatar".to_string(), "Get".to_string()]), "GetAvatar");
+        assert_eq!(prefix_key("JNLOABDHEIH", &[]), "JNLOABDHEIH");
+    }
+}
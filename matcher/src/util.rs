@@ -105,3 +105,14 @@ pub trait QueryExecutor<'tree> {
     where
         Self: Sized;
 }
+
+/// Parses inline proto source for tests, any diagnostic fails the test
+#[cfg(test)]
+pub fn parse_test_proto(source: &str) -> crate::prototype::ProtoDatabase {
+    use itertools::Itertools;
+
+    let mut proto_db = crate::prototype::ProtoDatabase::new();
+    let parsed = crate::parser::parse_proto_into(&mut proto_db, &source.trim_indent(), "test.proto").unwrap_or_else(|e| panic!("{}", e));
+    assert!(parsed.diagnostics.is_empty(), "{}", parsed.diagnostics.iter().join("\n"));
+    proto_db
+}
@@ -0,0 +1,277 @@
+use std::fmt;
+
+/// Protobuf wire types, groups are only skipped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireType {
+    Varint,
+    Fixed64,
+    LengthDelimited,
+    StartGroup,
+    EndGroup,
+    Fixed32,
+}
+
+impl WireType {
+    fn from_tag(tag: u64) -> Option<Self> {
+        Some(match tag & 7 {
+            0 => WireType::Varint,
+            1 => WireType::Fixed64,
+            2 => WireType::LengthDelimited,
+            3 => WireType::StartGroup,
+            4 => WireType::EndGroup,
+            5 => WireType::Fixed32,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WireError {
+    /// Offset the data ended at
+    UnexpectedEof(usize),
+    VarintTooLong(usize),
+    InvalidWireType(u8, usize),
+    InvalidUtf8(usize),
+    UnmatchedEndGroup(usize),
+}
+
+impl fmt::Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WireError::UnexpectedEof(offset) => write!(f, "unexpected end of data at byte {}", offset),
+            WireError::VarintTooLong(offset) => write!(f, "varint longer than 10 bytes at byte {}", offset),
+            WireError::InvalidWireType(wire_type, offset) => write!(f, "invalid wire type {} at byte {}", wire_type, offset),
+            WireError::InvalidUtf8(offset) => write!(f, "invalid UTF-8 in string at byte {}", offset),
+            WireError::UnmatchedEndGroup(offset) => write!(f, "end of group without a start at byte {}", offset),
+        }
+    }
+}
+
+/// A field value as it appears on the wire, length-delimited values borrow from the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireValue<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+impl<'a> WireValue<'a> {
+    /// Integers of any encoding, 0 for length-delimited values
+    pub fn as_u64(&self) -> u64 {
+        match *self {
+            WireValue::Varint(value) | WireValue::Fixed64(value) => value,
+            WireValue::Fixed32(value) => value as u64,
+            WireValue::Bytes(_) => 0,
+        }
+    }
+
+    /// Negative `int32` values are sign-extended to 64 bits on the wire
+    pub fn as_i32(&self) -> i32 {
+        self.as_u64() as i32
+    }
+
+    pub fn as_bool(&self) -> bool {
+        self.as_u64() != 0
+    }
+
+    pub fn as_bytes(&self) -> &'a [u8] {
+        match *self {
+            WireValue::Bytes(bytes) => bytes,
+            _ => &[],
+        }
+    }
+}
+
+/// Reads the fields of a single message
+pub struct WireReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> WireReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// Next field number and value, `None` at the end of the message. Groups are skipped
+    pub fn read_field(&mut self) -> Result<Option<(u32, WireValue<'a>)>, WireError> {
+        loop {
+            if self.pos >= self.data.len() {
+                return Ok(None);
+            }
+
+            let start = self.pos;
+            let tag = self.read_varint()?;
+            let wire_type = WireType::from_tag(tag).ok_or(WireError::InvalidWireType((tag & 7) as u8, start))?;
+            let number = (tag >> 3) as u32;
+
+            let value = match wire_type {
+                WireType::Varint => WireValue::Varint(self.read_varint()?),
+                WireType::Fixed64 => WireValue::Fixed64(u64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+                WireType::LengthDelimited => {
+                    let length = self.read_varint()? as usize;
+                    WireValue::Bytes(self.take(length)?)
+                }
+                WireType::Fixed32 => WireValue::Fixed32(u32::from_le_bytes(self.take(4)?.try_into().unwrap())),
+                WireType::StartGroup => {
+                    self.skip_group(number)?;
+                    continue;
+                }
+                WireType::EndGroup => return Err(WireError::UnmatchedEndGroup(start)),
+            };
+
+            return Ok(Some((number, value)));
+        }
+    }
+
+    fn read_varint(&mut self) -> Result<u64, WireError> {
+        let start = self.pos;
+        let mut value = 0u64;
+
+        for shift in (0..70).step_by(7) {
+            let byte = *self.data.get(self.pos).ok_or(WireError::UnexpectedEof(self.pos))?;
+            self.pos += 1;
+
+            value |= ((byte & 0x7f) as u64) << shift.min(63);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(WireError::VarintTooLong(start))
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], WireError> {
+        let end = self.pos.checked_add(length).filter(|&end| end <= self.data.len())
+            .ok_or(WireError::UnexpectedEof(self.data.len()))?;
+
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn skip_group(&mut self, number: u32) -> Result<(), WireError> {
+        loop {
+            let start = self.pos;
+            if start >= self.data.len() {
+                return Err(WireError::UnexpectedEof(start));
+            }
+
+            let tag = self.read_varint()?;
+            match WireType::from_tag(tag) {
+                Some(WireType::EndGroup) if (tag >> 3) as u32 == number => return Ok(()),
+                Some(WireType::EndGroup) => return Err(WireError::UnmatchedEndGroup(start)),
+                Some(WireType::StartGroup) => self.skip_group((tag >> 3) as u32)?,
+                Some(WireType::Varint) => {
+                    self.read_varint()?;
+                }
+                Some(WireType::Fixed64) => {
+                    self.take(8)?;
+                }
+                Some(WireType::Fixed32) => {
+                    self.take(4)?;
+                }
+                Some(WireType::LengthDelimited) => {
+                    let length = self.read_varint()? as usize;
+                    self.take(length)?;
+                }
+                None => return Err(WireError::InvalidWireType((tag & 7) as u8, start)),
+            }
+        }
+    }
+}
+
+/// Writes the fields of a single message
+#[derive(Default)]
+pub struct WireWriter {
+    buffer: Vec<u8>,
+}
+
+impl WireWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buffer
+    }
+
+    pub fn varint(&mut self, number: u32, value: u64) {
+        self.tag(number, 0);
+        self.raw_varint(value);
+    }
+
+    /// Negative values are sign-extended to 64 bits, like protoc does
+    pub fn int32(&mut self, number: u32, value: i32) {
+        self.varint(number, value as i64 as u64);
+    }
+
+    pub fn bool(&mut self, number: u32, value: bool) {
+        self.varint(number, value as u64);
+    }
+
+    pub fn bytes(&mut self, number: u32, value: &[u8]) {
+        self.tag(number, 2);
+        self.raw_varint(value.len() as u64);
+        self.buffer.extend_from_slice(value);
+    }
+
+    pub fn string(&mut self, number: u32, value: &str) {
+        self.bytes(number, value.as_bytes());
+    }
+
+    pub fn message(&mut self, number: u32, message: WireWriter) {
+        self.bytes(number, &message.buffer);
+    }
+
+    fn tag(&mut self, number: u32, wire_type: u64) {
+        self.raw_varint((number as u64) << 3 | wire_type);
+    }
+
+    fn raw_varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buffer.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.buffer.push(value as u8);
+    }
+}
+
+/// Strings are length-delimited values that have to be UTF-8
+pub fn decode_string(value: &WireValue) -> Result<String, WireError> {
+    String::from_utf8(value.as_bytes().to_vec()).map_err(|e| WireError::InvalidUtf8(e.utf8_error().valid_up_to()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_fields() {
+        // 1: 150, 2: "hi", 3: group { 1: 5 }, 4: fixed32 7
+        let data = [0x08, 0x96, 0x01, 0x12, 0x02, b'h', b'i', 0x1b, 0x08, 0x05, 0x1c, 0x25, 0x07, 0x00, 0x00, 0x00];
+        let mut reader = WireReader::new(&data);
+
+        assert_eq!(reader.read_field(), Ok(Some((1, WireValue::Varint(150)))));
+        assert_eq!(reader.read_field(), Ok(Some((2, WireValue::Bytes(b"hi")))));
+        assert_eq!(reader.read_field(), Ok(Some((4, WireValue::Fixed32(7)))));
+        assert_eq!(reader.read_field(), Ok(None));
+
+        assert_eq!(WireReader::new(&[0x12, 0x05, b'a']).read_field(), Err(WireError::UnexpectedEof(3)));
+    }
+
+    #[test]
+    fn test_write_fields() {
+        let mut writer = WireWriter::new();
+        writer.varint(1, 150);
+        writer.int32(2, -1);
+        writer.string(3, "hi");
+        let data = writer.finish();
+
+        let mut reader = WireReader::new(&data);
+        assert_eq!(reader.read_field(), Ok(Some((1, WireValue::Varint(150)))));
+        assert_eq!(reader.read_field().map(|field| field.map(|(number, value)| (number, value.as_i32()))), Ok(Some((2, -1))));
+        assert_eq!(reader.read_field(), Ok(Some((3, WireValue::Bytes(b"hi")))));
+    }
+}